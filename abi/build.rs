@@ -5,11 +5,19 @@ trait BuilderExt {
 
 fn main() {
     println!("cargo:rerun-if-changed=protos/reservation.proto");
+    println!("cargo:rerun-if-changed=protos/google/rpc");
 
     tonic_build::configure()
         .add_builder_for_reservation_query()
         .add_builder_for_reservation_filter()
-        .compile(&["protos/reservation.proto"], &["protos"])
+        .compile(
+            &[
+                "protos/reservation.proto",
+                "protos/google/rpc/status.proto",
+                "protos/google/rpc/error_details.proto",
+            ],
+            &["protos"],
+        )
         .unwrap();
 }
 
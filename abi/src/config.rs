@@ -1,4 +1,7 @@
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -9,6 +12,16 @@ use crate::Error;
 pub struct Config {
     pub db: DbConfig,
     pub server: ServerConfig,
+    #[serde(default)]
+    pub hold: HoldConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub idempotency: IdempotencyConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -26,13 +39,146 @@ pub struct ServerConfig {
     pub port: u16,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HoldConfig {
+    /// hold ttl in seconds for a pending reservation, if the request doesn't set one
+    pub default_ttl: Option<u64>,
+    /// how often expired holds are released, in seconds
+    pub sweep_interval: u64,
+}
+
+impl Default for HoldConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: None,
+            sweep_interval: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdempotencyConfig {
+    /// how long the result of a request with an idempotency key is kept, in seconds
+    pub ttl: u64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self { ttl: 24 * 3600 }
+    }
+}
+
+/// Requests must carry a bearer JWT signed with one of the keys,
+/// every request is accepted if there is no key
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// the `iss` the tokens must have, if set
+    pub issuer: Option<String>,
+    /// the `aud` the tokens must have, if set
+    pub audience: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<AuthKey>,
+}
+
+/// A key tokens are validated with
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuthKey {
+    /// secret of HS256, HS384 or HS512 tokens
+    Hmac { secret: String },
+    /// PEM file of an RSA, EC or Ed25519 public key
+    Pem { path: PathBuf },
+    /// JWKS file, a token is validated with the key matching its `kid`
+    Jwks { path: PathBuf },
+}
+
+/// Booking rules, a rule of a resource overrides the one of its class,
+/// which overrides the default one
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PolicyConfig {
+    #[serde(default)]
+    pub default: BookingPolicy,
+    /// rules of a class of resources, by class name
+    #[serde(default)]
+    pub classes: BTreeMap<String, ClassPolicy>,
+    /// rules of a single resource, by resource id
+    #[serde(default)]
+    pub resources: BTreeMap<String, BookingPolicy>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClassPolicy {
+    /// ids of the resources in the class
+    pub resources: Vec<String>,
+    #[serde(flatten)]
+    pub policy: BookingPolicy,
+}
+
+/// Durations are in seconds, a rule which isn't set doesn't apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BookingPolicy {
+    pub min_duration: Option<u64>,
+    pub max_duration: Option<u64>,
+    /// how long before its start a reservation must be made
+    pub min_lead_time: Option<u64>,
+    /// how far ahead a reservation can start
+    pub max_horizon: Option<u64>,
+    /// reject reservations which start in the past
+    pub reject_past: Option<bool>,
+}
+
+/// Limits of the reservations of a user, the default quota counts all of them,
+/// the quota of a class only counts the ones of its resources
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuotaConfig {
+    #[serde(default)]
+    pub default: Quota,
+    /// quotas of a class of resources, by class name
+    #[serde(default)]
+    pub classes: BTreeMap<String, ClassQuota>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClassQuota {
+    /// ids of the resources in the class
+    pub resources: Vec<String>,
+    #[serde(flatten)]
+    pub quota: Quota,
+}
+
+/// A limit which isn't set doesn't apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Quota {
+    /// max number of reservations which are neither cancelled nor over
+    pub max_active: Option<u32>,
+    /// max hours of the reservations starting in the same week, weeks start on monday (UTC)
+    pub max_weekly_hours: Option<u32>,
+}
+
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let f = fs::read_to_string(path)?;
-        let config = toml::from_str(&f).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        let config: Self = toml::from_str(&f).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Check the values a valid toml file can still get wrong
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.hold.sweep_interval == 0 {
+            return Err(Error::InvalidConfig(
+                "hold.sweep_interval must be at least 1 second".to_string(),
+            ));
+        }
+        if self.hold.default_ttl == Some(0) {
+            return Err(Error::InvalidConfig(
+                "hold.default_ttl must be at least 1 second".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         let f = toml::to_string(self).map_err(|e| Error::InvalidConfig(e.to_string()))?;
         fs::write(path, f).map_err(Error::IoError)?;
@@ -77,6 +223,62 @@ mod tests {
                 host: "localhost".to_string(),
                 port: 8080,
             },
+            hold: HoldConfig {
+                default_ttl: Some(900),
+                sweep_interval: 30,
+            },
+            policy: PolicyConfig {
+                default: BookingPolicy {
+                    reject_past: Some(true),
+                    ..Default::default()
+                },
+                classes: BTreeMap::from([(
+                    "rooms".to_string(),
+                    ClassPolicy {
+                        resources: vec!["room".to_string(), "room1".to_string()],
+                        policy: BookingPolicy {
+                            max_duration: Some(14400),
+                            ..Default::default()
+                        },
+                    },
+                )]),
+                resources: BTreeMap::from([(
+                    "projector".to_string(),
+                    BookingPolicy {
+                        min_lead_time: Some(3600),
+                        ..Default::default()
+                    },
+                )]),
+            },
+            quota: QuotaConfig {
+                default: Quota {
+                    max_active: Some(3),
+                    ..Default::default()
+                },
+                classes: BTreeMap::from([(
+                    "rooms".to_string(),
+                    ClassQuota {
+                        resources: vec!["room".to_string(), "room1".to_string()],
+                        quota: Quota {
+                            max_weekly_hours: Some(10),
+                            ..Default::default()
+                        },
+                    },
+                )]),
+            },
+            idempotency: IdempotencyConfig { ttl: 3600 },
+            auth: AuthConfig {
+                issuer: Some("https://auth.example.com".to_string()),
+                audience: None,
+                keys: vec![
+                    AuthKey::Hmac {
+                        secret: "secret".to_string(),
+                    },
+                    AuthKey::Jwks {
+                        path: PathBuf::from("/etc/rereserve/jwks.json"),
+                    },
+                ],
+            },
         };
         let result = || -> Result<Config, Error> {
             config.save(&path)?;
@@ -85,4 +287,36 @@ mod tests {
         fs::remove_file(&path).unwrap();
         assert_eq!(result.unwrap(), config);
     }
+
+    #[test]
+    fn zero_hold_durations_should_be_rejected() {
+        let config: Config = toml::from_str(
+            r#"
+            [db]
+            host = "localhost"
+            port = 5432
+            user = "postgres"
+            password = ""
+            database = "reservation"
+
+            [server]
+            host = "localhost"
+            port = 8080
+
+            [hold]
+            sweep_interval = 0
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+
+        let config = Config {
+            hold: HoldConfig {
+                default_ttl: Some(0),
+                sweep_interval: 60,
+            },
+            ..config
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+    }
 }
@@ -3,32 +3,75 @@ use std::{convert::Infallible, str::FromStr, sync::OnceLock};
 use chrono::{DateTime, Utc};
 use regex::Regex;
 
+use crate::{
+    utils::{datetime_to_timestamp, timestamp_to_datetime},
+    ConflictDetail, ConflictWindow, Reservation,
+};
+
 static RESERVATION_CONFLICT_REGEX: OnceLock<Regex> = OnceLock::new();
+static REMAINING_CAPACITY_REGEX: OnceLock<Regex> = OnceLock::new();
+static EXISTING_ID_REGEX: OnceLock<Regex> = OnceLock::new();
 
 #[derive(Debug)]
 pub enum ReservationConflictInfo {
-    Parsed(ReservationConflict),
+    Parsed(Box<ReservationConflict>),
     Raw(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ReservationConflict {
     pub new: ReservationWindow,
     pub old: ReservationWindow,
+    /// seats of the resource which are still free during the new window
+    pub remaining_capacity: u32,
+    /// id of the existing reservation, not reported by older databases
+    pub existing_id: Option<i64>,
+    /// the existing reservations overlapping the new window, earliest first,
+    /// empty if the conflict was only parsed from the database error.
+    /// Decoded from a status, they only have their ids, resources, timespans, seats and buffers.
+    pub existing: Vec<Reservation>,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ReservationWindow {
     pub resource_id: String,
     pub start: DateTime<Utc>,
     pub end: DateTime<Utc>,
 }
 
+impl ReservationConflict {
+    /// The conflict of a reservation with the existing ones it overlaps, `None` if there is none
+    pub fn new(
+        rsvp: &Reservation,
+        existing: Vec<Reservation>,
+        remaining_capacity: u32,
+    ) -> Option<Self> {
+        let first = existing.first()?;
+        Some(Self {
+            new: ReservationWindow::of(rsvp)?,
+            old: ReservationWindow::of(first)?,
+            remaining_capacity,
+            existing_id: Some(first.id),
+            existing,
+        })
+    }
+}
+
+impl ReservationWindow {
+    fn of(rsvp: &Reservation) -> Option<Self> {
+        Some(Self {
+            resource_id: rsvp.resource_id.clone(),
+            start: timestamp_to_datetime(rsvp.start.as_ref()?),
+            end: timestamp_to_datetime(rsvp.end.as_ref()?),
+        })
+    }
+}
+
 impl FromStr for ReservationConflictInfo {
     type Err = Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if let Ok(parsed) = s.parse() {
-            return Ok(ReservationConflictInfo::Parsed(parsed));
+            return Ok(ReservationConflictInfo::Parsed(Box::new(parsed)));
         }
         Ok(ReservationConflictInfo::Raw(s.to_string()))
     }
@@ -64,7 +107,87 @@ impl FromStr for ReservationConflict {
                 .with_timezone(&Utc),
         };
 
-        Ok(ReservationConflict { new, old })
+        // the exclusion constraint didn't report it, the resource was fully booked
+        let remaining_capacity = REMAINING_CAPACITY_REGEX
+            .get_or_init(|| Regex::new(r"remaining capacity: (\d+)").unwrap())
+            .captures(s)
+            .and_then(|cap| cap[1].parse().ok())
+            .unwrap_or(0);
+        let existing_id = EXISTING_ID_REGEX
+            .get_or_init(|| Regex::new(r"existing id: (\d+)").unwrap())
+            .captures(s)
+            .and_then(|cap| cap[1].parse().ok());
+
+        Ok(ReservationConflict {
+            new,
+            old,
+            remaining_capacity,
+            existing_id,
+            existing: Vec::new(),
+        })
+    }
+}
+
+impl From<&ReservationConflict> for ConflictDetail {
+    fn from(conflict: &ReservationConflict) -> Self {
+        Self {
+            new: Some((&conflict.new).into()),
+            existing: Some((&conflict.old).into()),
+            existing_id: conflict.existing_id,
+            remaining_capacity: conflict.remaining_capacity,
+            existing_reservations: conflict.existing.iter().map(redacted).collect(),
+        }
+    }
+}
+
+// the existing reservations may be of other users, so only what they block is sent,
+// a caller who may read them gets the rest by their ids
+fn redacted(rsvp: &Reservation) -> Reservation {
+    Reservation {
+        id: rsvp.id,
+        resource_id: rsvp.resource_id.clone(),
+        start: rsvp.start.clone(),
+        end: rsvp.end.clone(),
+        seats: rsvp.seats,
+        buffer_before: rsvp.buffer_before.clone(),
+        buffer_after: rsvp.buffer_after.clone(),
+        ..Default::default()
+    }
+}
+
+impl From<&ReservationWindow> for ConflictWindow {
+    fn from(window: &ReservationWindow) -> Self {
+        Self {
+            resource_id: window.resource_id.clone(),
+            start: Some(datetime_to_timestamp(window.start)),
+            end: Some(datetime_to_timestamp(window.end)),
+        }
+    }
+}
+
+impl TryFrom<ConflictDetail> for ReservationConflict {
+    type Error = ();
+
+    fn try_from(detail: ConflictDetail) -> Result<Self, Self::Error> {
+        Ok(Self {
+            new: detail.new.ok_or(())?.try_into()?,
+            old: detail.existing.ok_or(())?.try_into()?,
+            remaining_capacity: detail.remaining_capacity,
+            existing_id: detail.existing_id,
+            existing: detail.existing_reservations,
+        })
+    }
+}
+
+impl TryFrom<ConflictWindow> for ReservationWindow {
+    type Error = ();
+
+    fn try_from(window: ConflictWindow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            resource_id: window.resource_id,
+            start: timestamp_to_datetime(window.start.as_ref().ok_or(())?),
+            end: timestamp_to_datetime(window.end.as_ref().ok_or(())?),
+        })
     }
 }
 
@@ -79,4 +202,51 @@ mod tests {
         dbg!(&conflict);
         assert!(matches!(conflict, ReservationConflictInfo::Parsed(_)));
     }
+
+    #[test]
+    fn test_parse_conflict_with_remaining_capacity() {
+        let conflict = "Key (resource_id, timespan)=(parking, [\"2021-01-01 12:00:00+00\",\"2021-01-02 12:00:00+00\")) conflicts with existing key (resource_id, timespan)=(parking, [\"2021-01-01 00:00:00+00\",\"2021-01-02 00:00:00+00\")), remaining capacity: 2.";
+        let conflict: ReservationConflict = conflict.parse().unwrap();
+        assert_eq!(conflict.new.resource_id, "parking");
+        assert_eq!(conflict.remaining_capacity, 2);
+        assert_eq!(conflict.existing_id, None);
+    }
+
+    #[test]
+    fn test_parse_conflict_with_existing_id() {
+        let conflict = "Key (resource_id, timespan)=(parking, [\"2021-01-01 12:00:00+00\",\"2021-01-02 12:00:00+00\")) conflicts with existing key (resource_id, timespan)=(parking, [\"2021-01-01 00:00:00+00\",\"2021-01-02 00:00:00+00\")), remaining capacity: 0, existing id: 42.";
+        let conflict: ReservationConflict = conflict.parse().unwrap();
+        assert_eq!(conflict.remaining_capacity, 0);
+        assert_eq!(conflict.existing_id, Some(42));
+
+        let detail = ConflictDetail::from(&conflict);
+        assert_eq!(ReservationConflict::try_from(detail), Ok(conflict));
+    }
+
+    #[test]
+    fn conflict_detail_should_only_carry_windows_of_existing_reservations() {
+        let existing = Reservation {
+            id: 7,
+            cancel_reason: Some("reason".to_string()),
+            ..Reservation::new_pendding(
+                "alice",
+                "room",
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+                "2021-01-02T00:00:00Z".parse().unwrap(),
+                "private note",
+            )
+        };
+        let rsvp = Reservation {
+            user_id: "bob".to_string(),
+            ..existing.clone()
+        };
+        let conflict = ReservationConflict::new(&rsvp, vec![existing.clone()], 0).unwrap();
+
+        let detail = ConflictDetail::from(&conflict);
+        let sent = &detail.existing_reservations[0];
+        assert_eq!(sent.id, 7);
+        assert_eq!((&sent.start, &sent.end), (&existing.start, &existing.end));
+        assert!(sent.user_id.is_empty() && sent.note.is_empty());
+        assert_eq!(sent.cancel_reason, None);
+    }
 }
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use prost::Message;
+use prost_types::Any;
+use tonic::{Code, Status};
+
+use super::conflict::{ReservationConflict, ReservationConflictInfo};
+use crate::{
+    rpc::{self, bad_request::FieldViolation, BadRequest, ErrorInfo},
+    ConflictDetail,
+};
+
+const DOMAIN: &str = "reservation";
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+const CONFLICT_DETAIL: &str = "reservation.ConflictDetail";
+const ERROR_INFO: &str = "google.rpc.ErrorInfo";
+const BAD_REQUEST: &str = "google.rpc.BadRequest";
+
+/// A status with its details encoded as a `google.rpc.Status`
+pub(super) fn status_with_details(code: Code, message: String, details: Vec<Any>) -> Status {
+    let status = rpc::Status {
+        code: code as i32,
+        message: message.clone(),
+        details,
+    };
+    Status::with_details(code, message, status.encode_to_vec().into())
+}
+
+/// ALREADY_EXISTS with an `ErrorInfo` and a `ConflictDetail` for every parsed conflict,
+/// the `ErrorInfo` of a single conflict has its windows in the metadata
+pub(super) fn conflict_status<'a>(
+    reason: &str,
+    message: &str,
+    infos: impl ExactSizeIterator<Item = &'a ReservationConflictInfo>,
+) -> Status {
+    let count = infos.len();
+    let mut metadata = HashMap::new();
+    let mut details = Vec::new();
+    let mut raws = Vec::new();
+    for info in infos {
+        match info {
+            ReservationConflictInfo::Parsed(conflict) => {
+                if count == 1 {
+                    metadata = conflict_metadata(conflict);
+                }
+                details.push(any(CONFLICT_DETAIL, &ConflictDetail::from(&**conflict)));
+            }
+            ReservationConflictInfo::Raw(raw) => raws.push(raw.as_str()),
+        }
+    }
+    if count > 1 {
+        metadata.insert("conflicts".to_string(), count.to_string());
+    }
+
+    let info = ErrorInfo {
+        reason: reason.to_string(),
+        domain: DOMAIN.to_string(),
+        metadata,
+    };
+    details.insert(0, any(ERROR_INFO, &info));
+
+    let message = match raws.is_empty() {
+        true => message.to_string(),
+        false => format!("{}: {}", message, raws.join(" ")),
+    };
+    status_with_details(Code::AlreadyExists, message, details)
+}
+
+/// A status with an `ErrorInfo` telling its reason
+pub(super) fn error_status(
+    code: Code,
+    reason: &str,
+    message: String,
+    metadata: &[(&str, String)],
+) -> Status {
+    status_with_details(code, message, vec![error_info(reason, metadata)])
+}
+
+/// INVALID_ARGUMENT with an `ErrorInfo` and a `BadRequest` telling which field is invalid
+pub(super) fn bad_request(reason: &str, field: &str, message: &str) -> Status {
+    let bad_request = BadRequest {
+        field_violations: vec![FieldViolation {
+            field: field.to_string(),
+            description: message.to_string(),
+        }],
+    };
+    status_with_details(
+        Code::InvalidArgument,
+        message.to_string(),
+        vec![error_info(reason, &[]), any(BAD_REQUEST, &bad_request)],
+    )
+}
+
+fn error_info(reason: &str, metadata: &[(&str, String)]) -> Any {
+    let info = ErrorInfo {
+        reason: reason.to_string(),
+        domain: DOMAIN.to_string(),
+        metadata: metadata
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect(),
+    };
+    any(ERROR_INFO, &info)
+}
+
+fn conflict_metadata(conflict: &ReservationConflict) -> HashMap<String, String> {
+    let mut metadata = HashMap::from([
+        (
+            "new_resource_id".to_string(),
+            conflict.new.resource_id.clone(),
+        ),
+        ("new_start".to_string(), conflict.new.start.to_rfc3339()),
+        ("new_end".to_string(), conflict.new.end.to_rfc3339()),
+        (
+            "existing_resource_id".to_string(),
+            conflict.old.resource_id.clone(),
+        ),
+        (
+            "existing_start".to_string(),
+            conflict.old.start.to_rfc3339(),
+        ),
+        ("existing_end".to_string(), conflict.old.end.to_rfc3339()),
+        (
+            "remaining_capacity".to_string(),
+            conflict.remaining_capacity.to_string(),
+        ),
+    ]);
+    if let Some(id) = conflict.existing_id {
+        metadata.insert("existing_id".to_string(), id.to_string());
+    }
+    metadata
+}
+
+fn any(type_name: &str, message: &impl Message) -> Any {
+    Any {
+        type_url: format!("{}{}", TYPE_URL_PREFIX, type_name),
+        value: message.encode_to_vec(),
+    }
+}
+
+impl ErrorInfo {
+    /// The `ErrorInfo` of the service in the details of a status, if there's one
+    pub fn from_status(status: &Status) -> Option<Self> {
+        let details = rpc::Status::decode(status.details()).ok()?;
+        details
+            .details
+            .iter()
+            .filter(|any| any.type_url.strip_prefix(TYPE_URL_PREFIX) == Some(ERROR_INFO))
+            .filter_map(|any| ErrorInfo::decode(any.value.as_slice()).ok())
+            .find(|info| info.domain == DOMAIN)
+    }
+}
+
+impl ReservationConflict {
+    /// The conflicts in the details of a status, empty if the status isn't about conflicts
+    pub fn from_status(status: &Status) -> Vec<Self> {
+        let Ok(details) = rpc::Status::decode(status.details()) else {
+            return Vec::new();
+        };
+
+        details
+            .details
+            .iter()
+            .filter(|any| any.type_url.strip_prefix(TYPE_URL_PREFIX) == Some(CONFLICT_DETAIL))
+            .filter_map(|any| ConflictDetail::decode(any.value.as_slice()).ok())
+            .filter_map(|detail| detail.try_into().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::reason, Error};
+
+    const CONFLICT: &str = "Key (resource_id, timespan)=(room, [\"2021-01-01 12:00:00+00\",\"2021-01-02 12:00:00+00\")) conflicts with existing key (resource_id, timespan)=(room, [\"2021-01-01 00:00:00+00\",\"2021-01-02 00:00:00+00\")), remaining capacity: 0, existing id: 7.";
+
+    fn details(status: &Status) -> rpc::Status {
+        rpc::Status::decode(status.details()).unwrap()
+    }
+
+    #[test]
+    fn conflict_status_should_carry_details() {
+        let error = Error::ConflictReservation(CONFLICT.parse().unwrap());
+        let status = Status::from(error);
+        assert_eq!(status.code(), Code::AlreadyExists);
+
+        let details = details(&status);
+        assert_eq!(details.details.len(), 2);
+        let info = ErrorInfo::decode(details.details[0].value.as_slice()).unwrap();
+        assert_eq!(info.reason, "RESERVATION_CONFLICT");
+        assert_eq!(info.metadata["existing_id"], "7");
+        assert_eq!(info.metadata["new_start"], "2021-01-01T12:00:00+00:00");
+
+        let conflicts = ReservationConflict::from_status(&status);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0], CONFLICT.parse().unwrap());
+    }
+
+    #[test]
+    fn batch_status_should_keep_conflict_details() {
+        let error = Error::BatchReservation {
+            index: 1,
+            source: Box::new(Error::ConflictReservation(CONFLICT.parse().unwrap())),
+        };
+        let status = Status::from(error);
+        assert_eq!(status.code(), Code::AlreadyExists);
+        assert_eq!(ReservationConflict::from_status(&status).len(), 1);
+    }
+
+    #[test]
+    fn invalid_argument_should_carry_bad_request() {
+        let status = Status::from(Error::InvalidTimespan);
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "Invalid timespan");
+
+        let info = ErrorInfo::from_status(&status).unwrap();
+        assert_eq!(info.reason, reason::INVALID_TIMESPAN);
+        let details = details(&status);
+        let bad_request = BadRequest::decode(details.details[1].value.as_slice()).unwrap();
+        assert_eq!(bad_request.field_violations[0].field, "timespan");
+        assert!(ReservationConflict::from_status(&status).is_empty());
+    }
+
+    #[test]
+    fn every_status_should_carry_a_reason() {
+        let status = Status::from(Error::VersionMismatch {
+            expected: 1,
+            actual: 2,
+        });
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        let info = ErrorInfo::from_status(&status).unwrap();
+        assert_eq!(info.reason, reason::VERSION_MISMATCH);
+        assert_eq!(info.metadata["expected"], "1");
+        assert_eq!(info.metadata["actual"], "2");
+
+        let status = Status::from(Error::NotFound);
+        let info = ErrorInfo::from_status(&status).unwrap();
+        assert_eq!(info.reason, reason::NOT_FOUND);
+
+        let status = Status::from(Error::BatchReservation {
+            index: 0,
+            source: Box::new(Error::HoldExpired),
+        });
+        let info = ErrorInfo::from_status(&status).unwrap();
+        assert_eq!(info.reason, reason::HOLD_EXPIRED);
+    }
+}
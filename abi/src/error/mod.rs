@@ -2,8 +2,11 @@ use sqlx::postgres::PgDatabaseError;
 use thiserror::Error;
 
 use self::conflict::ReservationConflictInfo;
+use crate::policy::PolicyRule;
 
 pub mod conflict;
+mod details;
+pub mod reason;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -16,6 +19,67 @@ pub enum Error {
     #[error("Conflict reservation")]
     ConflictReservation(ReservationConflictInfo),
 
+    #[error("Reservation {index} in batch failed: {source}")]
+    BatchReservation { index: usize, source: Box<Error> },
+
+    #[error("Conflict reservation series")]
+    ConflictSeries(Vec<ReservationConflictInfo>),
+
+    #[error("Invalid recurrence rule: {0}")]
+    InvalidRecurrenceRule(String),
+
+    #[error("Hold expired")]
+    HoldExpired,
+
+    #[error("Invalid Resource ID")]
+    InvalidResourceId,
+
+    #[error("Resource already exists")]
+    ResourceAlreadyExists,
+
+    #[error("Resource not available: {0}")]
+    ResourceNotAvailable(String),
+
+    #[error("Resource in use")]
+    ResourceInUse,
+
+    #[error("Invalid seats")]
+    InvalidSeats,
+
+    #[error("Invalid capacity")]
+    InvalidCapacity,
+
+    #[error("Invalid buffer")]
+    InvalidBuffer,
+
+    #[error("Policy violation ({rule}): {reason}")]
+    PolicyViolation { rule: PolicyRule, reason: String },
+
+    #[error("Quota exceeded ({scope}): {reason}")]
+    QuotaExceeded { scope: String, reason: String },
+
+    #[error("Version mismatch: expected {expected}, found {actual}")]
+    VersionMismatch { expected: i64, actual: i64 },
+
+    #[error("Invalid idempotency key")]
+    InvalidIdempotencyKey,
+
+    #[error("Idempotency key reused for another request")]
+    IdempotencyKeyReused,
+
+    #[error("Invalid tenant ID")]
+    InvalidTenantId,
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("{0}")]
+    Unauthenticated(String),
+
+    /// A message of the request is missing, the field tells which one
+    #[error("Invalid {0}")]
+    MissingField(&'static str),
+
     #[error("Unknown error")]
     Unknown,
 
@@ -39,12 +103,26 @@ impl From<sqlx::Error> for Error {
     fn from(e: sqlx::Error) -> Self {
         match e {
             sqlx::Error::Database(e) => {
-                let err: &PgDatabaseError = e.downcast_ref();
+                // errors of other databases don't carry the codes of our migrations
+                let Some(err) = e.try_downcast_ref::<PgDatabaseError>() else {
+                    return Error::DatabaseError(sqlx::Error::Database(e));
+                };
 
                 match (err.code(), err.schema(), err.table()) {
                     ("23P01", Some("rsvp"), Some("reservations")) => Error::ConflictReservation(
                         err.detail().unwrap_or_default().parse().unwrap(),
                     ),
+                    // raised by reservations_resource_trigger
+                    ("RS001", _, _) => {
+                        Error::ResourceNotAvailable(err.detail().unwrap_or_default().to_string())
+                    }
+                    ("23505", Some("rsvp"), Some("resources")) => Error::ResourceAlreadyExists,
+                    // a resource which still has reservations is deleted
+                    ("23503", Some("rsvp"), Some("reservations"))
+                        if err.constraint() == Some("reservations_resource_id_fkey") =>
+                    {
+                        Error::ResourceInUse
+                    }
                     _ => Error::DatabaseError(sqlx::Error::Database(e)),
                 }
             }
@@ -56,16 +134,164 @@ impl From<sqlx::Error> for Error {
 
 impl From<Error> for tonic::Status {
     fn from(e: Error) -> Self {
+        use tonic::Code;
         match e {
-            Error::InvalidUserId => tonic::Status::invalid_argument("Invalid User ID"),
-            Error::InvalidTimespan => tonic::Status::invalid_argument("Invalid timespan"),
-            Error::ConflictReservation(e) => tonic::Status::already_exists(format!("{:?}", e)),
-            Error::Unknown => tonic::Status::unknown("Unknown error"),
-            Error::InvalidId => tonic::Status::invalid_argument("Invalid ID"),
-            Error::DatabaseError(_) => tonic::Status::internal("Database error"),
-            Error::NotFound => tonic::Status::not_found("Row not found"),
-            Error::IoError(_) => tonic::Status::internal("IO error"),
-            Error::InvalidConfig(_) => tonic::Status::invalid_argument("Invalid config"),
+            Error::InvalidUserId => {
+                details::bad_request(reason::INVALID_USER_ID, "user_id", "Invalid User ID")
+            }
+            Error::InvalidTimespan => {
+                details::bad_request(reason::INVALID_TIMESPAN, "timespan", "Invalid timespan")
+            }
+            Error::ConflictReservation(e) => details::conflict_status(
+                reason::RESERVATION_CONFLICT,
+                "Conflict reservation",
+                std::iter::once(&e),
+            ),
+            Error::BatchReservation { index, source } => {
+                let status = tonic::Status::from(*source);
+                tonic::Status::with_details(
+                    status.code(),
+                    format!(
+                        "Reservation {} in batch failed: {}",
+                        index,
+                        status.message()
+                    ),
+                    tonic::codegen::Bytes::copy_from_slice(status.details()),
+                )
+            }
+            Error::ConflictSeries(e) => details::conflict_status(
+                reason::RESERVATION_SERIES_CONFLICT,
+                "Conflict reservation series",
+                e.iter(),
+            ),
+            Error::InvalidRecurrenceRule(e) => details::bad_request(
+                reason::INVALID_RECURRENCE_RULE,
+                "rrule",
+                &format!("Invalid recurrence rule: {}", e),
+            ),
+            Error::HoldExpired => details::error_status(
+                Code::FailedPrecondition,
+                reason::HOLD_EXPIRED,
+                "Hold expired".to_string(),
+                &[],
+            ),
+            Error::InvalidResourceId => details::bad_request(
+                reason::INVALID_RESOURCE_ID,
+                "resource_id",
+                "Invalid Resource ID",
+            ),
+            Error::ResourceAlreadyExists => details::error_status(
+                Code::AlreadyExists,
+                reason::RESOURCE_ALREADY_EXISTS,
+                "Resource already exists".to_string(),
+                &[],
+            ),
+            Error::ResourceNotAvailable(id) => details::error_status(
+                Code::FailedPrecondition,
+                reason::RESOURCE_NOT_AVAILABLE,
+                format!("Resource not available: {}", id),
+                &[("resource_id", id)],
+            ),
+            Error::ResourceInUse => details::error_status(
+                Code::FailedPrecondition,
+                reason::RESOURCE_IN_USE,
+                "Resource in use".to_string(),
+                &[],
+            ),
+            Error::InvalidSeats => {
+                details::bad_request(reason::INVALID_SEATS, "seats", "Invalid seats")
+            }
+            Error::InvalidCapacity => {
+                details::bad_request(reason::INVALID_CAPACITY, "capacity", "Invalid capacity")
+            }
+            Error::InvalidBuffer => {
+                details::bad_request(reason::INVALID_BUFFER, "buffer", "Invalid buffer")
+            }
+            Error::PolicyViolation { rule, reason: why } => details::error_status(
+                Code::FailedPrecondition,
+                reason::POLICY_VIOLATION,
+                format!("Policy violation ({}): {}", rule, why),
+                &[("rule", rule.to_string())],
+            ),
+            Error::QuotaExceeded { scope, reason: why } => details::error_status(
+                Code::ResourceExhausted,
+                reason::QUOTA_EXCEEDED,
+                format!("Quota exceeded ({}): {}", scope, why),
+                &[("scope", scope)],
+            ),
+            Error::VersionMismatch { expected, actual } => details::error_status(
+                Code::FailedPrecondition,
+                reason::VERSION_MISMATCH,
+                format!("Version mismatch: expected {}, found {}", expected, actual),
+                &[
+                    ("expected", expected.to_string()),
+                    ("actual", actual.to_string()),
+                ],
+            ),
+            Error::InvalidIdempotencyKey => details::bad_request(
+                reason::INVALID_IDEMPOTENCY_KEY,
+                "idempotency_key",
+                "Invalid idempotency key",
+            ),
+            Error::IdempotencyKeyReused => details::bad_request(
+                reason::IDEMPOTENCY_KEY_REUSED,
+                "idempotency_key",
+                "Idempotency key reused for another request",
+            ),
+            Error::InvalidTenantId => details::error_status(
+                Code::InvalidArgument,
+                reason::INVALID_TENANT_ID,
+                "Invalid tenant ID".to_string(),
+                &[],
+            ),
+            Error::PermissionDenied(why) => details::error_status(
+                Code::PermissionDenied,
+                reason::PERMISSION_DENIED,
+                format!("Permission denied: {}", why),
+                &[],
+            ),
+            Error::Unauthenticated(message) => {
+                details::error_status(Code::Unauthenticated, reason::UNAUTHENTICATED, message, &[])
+            }
+            Error::MissingField(field) => {
+                details::bad_request(reason::MISSING_FIELD, field, &format!("Invalid {}", field))
+            }
+            Error::Unknown => details::error_status(
+                Code::Unknown,
+                reason::UNKNOWN,
+                "Unknown error".to_string(),
+                &[],
+            ),
+            Error::InvalidId => details::error_status(
+                Code::InvalidArgument,
+                reason::INVALID_ID,
+                "Invalid ID".to_string(),
+                &[],
+            ),
+            Error::DatabaseError(_) => details::error_status(
+                Code::Internal,
+                reason::DATABASE_ERROR,
+                "Database error".to_string(),
+                &[],
+            ),
+            Error::NotFound => details::error_status(
+                Code::NotFound,
+                reason::NOT_FOUND,
+                "Row not found".to_string(),
+                &[],
+            ),
+            Error::IoError(_) => details::error_status(
+                Code::Internal,
+                reason::IO_ERROR,
+                "IO error".to_string(),
+                &[],
+            ),
+            Error::InvalidConfig(_) => details::error_status(
+                Code::InvalidArgument,
+                reason::INVALID_CONFIG,
+                "Invalid config".to_string(),
+                &[],
+            ),
         }
     }
 }
@@ -0,0 +1,35 @@
+//! The reasons of the `google.rpc.ErrorInfo` in the details of every status of the service,
+//! a client should tell the errors apart by them rather than by the messages
+
+pub const INVALID_USER_ID: &str = "INVALID_USER_ID";
+pub const INVALID_TIMESPAN: &str = "INVALID_TIMESPAN";
+pub const RESERVATION_CONFLICT: &str = "RESERVATION_CONFLICT";
+pub const RESERVATION_SERIES_CONFLICT: &str = "RESERVATION_SERIES_CONFLICT";
+pub const INVALID_RECURRENCE_RULE: &str = "INVALID_RECURRENCE_RULE";
+pub const HOLD_EXPIRED: &str = "HOLD_EXPIRED";
+pub const INVALID_RESOURCE_ID: &str = "INVALID_RESOURCE_ID";
+pub const RESOURCE_ALREADY_EXISTS: &str = "RESOURCE_ALREADY_EXISTS";
+/// The metadata has the `resource_id`
+pub const RESOURCE_NOT_AVAILABLE: &str = "RESOURCE_NOT_AVAILABLE";
+pub const RESOURCE_IN_USE: &str = "RESOURCE_IN_USE";
+pub const INVALID_SEATS: &str = "INVALID_SEATS";
+pub const INVALID_CAPACITY: &str = "INVALID_CAPACITY";
+pub const INVALID_BUFFER: &str = "INVALID_BUFFER";
+/// The metadata has the `rule`
+pub const POLICY_VIOLATION: &str = "POLICY_VIOLATION";
+/// The metadata has the `scope`
+pub const QUOTA_EXCEEDED: &str = "QUOTA_EXCEEDED";
+/// The metadata has the `expected` and the `actual` version
+pub const VERSION_MISMATCH: &str = "VERSION_MISMATCH";
+pub const INVALID_IDEMPOTENCY_KEY: &str = "INVALID_IDEMPOTENCY_KEY";
+pub const IDEMPOTENCY_KEY_REUSED: &str = "IDEMPOTENCY_KEY_REUSED";
+pub const INVALID_TENANT_ID: &str = "INVALID_TENANT_ID";
+pub const PERMISSION_DENIED: &str = "PERMISSION_DENIED";
+pub const UNAUTHENTICATED: &str = "UNAUTHENTICATED";
+pub const MISSING_FIELD: &str = "MISSING_FIELD";
+pub const UNKNOWN: &str = "UNKNOWN";
+pub const INVALID_ID: &str = "INVALID_ID";
+pub const DATABASE_ERROR: &str = "DATABASE_ERROR";
+pub const NOT_FOUND: &str = "NOT_FOUND";
+pub const IO_ERROR: &str = "IO_ERROR";
+pub const INVALID_CONFIG: &str = "INVALID_CONFIG";
@@ -5,8 +5,17 @@ mod pb {
 }
 pub use pb::*;
 
+/// The standard error model of gRPC, used for the details of a status
+#[allow(clippy::all)]
+pub mod rpc {
+    tonic::include_proto!("google.rpc");
+}
+
 pub mod error;
 pub use error::Error;
 pub mod config;
+pub mod policy;
+pub mod quota;
+pub mod recurrence;
 mod types;
 pub mod utils;
@@ -0,0 +1,199 @@
+//! Booking rules of the resources, see [`PolicyConfig`] for how they are configured.
+
+use std::fmt;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    config::{BookingPolicy, PolicyConfig},
+    Error,
+};
+
+/// The rule a reservation violated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyRule {
+    MinDuration,
+    MaxDuration,
+    MinLeadTime,
+    MaxHorizon,
+    RejectPast,
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyRule::MinDuration => write!(f, "min_duration"),
+            PolicyRule::MaxDuration => write!(f, "max_duration"),
+            PolicyRule::MinLeadTime => write!(f, "min_lead_time"),
+            PolicyRule::MaxHorizon => write!(f, "max_horizon"),
+            PolicyRule::RejectPast => write!(f, "reject_past"),
+        }
+    }
+}
+
+impl PolicyConfig {
+    /// The rules which apply to a resource, merged from the default, its classes and its own
+    pub fn policy_for(&self, resource_id: &str) -> BookingPolicy {
+        let classes = self
+            .classes
+            .values()
+            .filter(|class| class.resources.iter().any(|id| id == resource_id))
+            .map(|class| &class.policy);
+
+        classes
+            .chain(self.resources.get(resource_id))
+            .fold(self.default.clone(), |policy, other| policy.merge(other))
+    }
+}
+
+impl BookingPolicy {
+    /// The rules of other override the ones of self
+    pub fn merge(self, other: &BookingPolicy) -> BookingPolicy {
+        BookingPolicy {
+            min_duration: other.min_duration.or(self.min_duration),
+            max_duration: other.max_duration.or(self.max_duration),
+            min_lead_time: other.min_lead_time.or(self.min_lead_time),
+            max_horizon: other.max_horizon.or(self.max_horizon),
+            reject_past: other.reject_past.or(self.reject_past),
+        }
+    }
+
+    /// Check a reservation from start to end, made at now
+    pub fn check(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let violation = |rule, reason: String| Err(Error::PolicyViolation { rule, reason });
+        let seconds = |s: u64| TimeDelta::try_seconds(s as i64).unwrap_or(TimeDelta::MAX);
+
+        let duration = end - start;
+        if let Some(min) = self.min_duration.map(seconds) {
+            if duration < min {
+                return violation(
+                    PolicyRule::MinDuration,
+                    format!("shorter than {} seconds", min.num_seconds()),
+                );
+            }
+        }
+        if let Some(max) = self.max_duration.map(seconds) {
+            if duration > max {
+                return violation(
+                    PolicyRule::MaxDuration,
+                    format!("longer than {} seconds", max.num_seconds()),
+                );
+            }
+        }
+        if self.reject_past == Some(true) && start < now {
+            return violation(PolicyRule::RejectPast, "starts in the past".to_string());
+        }
+        let lead_time = start - now;
+        if let Some(min) = self.min_lead_time.map(seconds) {
+            if lead_time < min {
+                return violation(
+                    PolicyRule::MinLeadTime,
+                    format!("starts in less than {} seconds", min.num_seconds()),
+                );
+            }
+        }
+        if let Some(max) = self.max_horizon.map(seconds) {
+            if lead_time > max {
+                return violation(
+                    PolicyRule::MaxHorizon,
+                    format!("starts in more than {} seconds", max.num_seconds()),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::ClassPolicy;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn policy_for_should_merge_rules() {
+        let config = PolicyConfig {
+            default: BookingPolicy {
+                reject_past: Some(true),
+                max_duration: Some(3600),
+                ..Default::default()
+            },
+            classes: BTreeMap::from([(
+                "rooms".to_string(),
+                ClassPolicy {
+                    resources: vec!["room".to_string()],
+                    policy: BookingPolicy {
+                        max_duration: Some(7200),
+                        min_duration: Some(600),
+                        ..Default::default()
+                    },
+                },
+            )]),
+            resources: BTreeMap::from([(
+                "room".to_string(),
+                BookingPolicy {
+                    min_duration: Some(900),
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        let policy = config.policy_for("room");
+        assert_eq!(policy.reject_past, Some(true));
+        assert_eq!(policy.max_duration, Some(7200));
+        assert_eq!(policy.min_duration, Some(900));
+
+        assert_eq!(config.policy_for("desk"), config.default);
+    }
+
+    #[test]
+    fn check_should_name_violated_rule() {
+        let policy = BookingPolicy {
+            min_duration: Some(900),
+            max_duration: Some(7200),
+            min_lead_time: Some(3600),
+            max_horizon: Some(90 * 24 * 3600),
+            reject_past: Some(true),
+        };
+        let now = utc("2024-01-01T00:00:00Z");
+
+        let check = |start: &str, end: &str| match policy.check(utc(start), utc(end), now) {
+            Err(Error::PolicyViolation { rule, .. }) => Some(rule),
+            Err(e) => panic!("Unexpected error: {:?}", e),
+            Ok(()) => None,
+        };
+
+        assert_eq!(check("2024-01-02T00:00:00Z", "2024-01-02T01:00:00Z"), None);
+        assert_eq!(
+            check("2024-01-02T00:00:00Z", "2024-01-02T00:10:00Z"),
+            Some(PolicyRule::MinDuration)
+        );
+        assert_eq!(
+            check("2024-01-02T00:00:00Z", "2024-01-02T03:00:00Z"),
+            Some(PolicyRule::MaxDuration)
+        );
+        assert_eq!(
+            check("2023-12-31T00:00:00Z", "2023-12-31T01:00:00Z"),
+            Some(PolicyRule::RejectPast)
+        );
+        assert_eq!(
+            check("2024-01-01T00:30:00Z", "2024-01-01T01:30:00Z"),
+            Some(PolicyRule::MinLeadTime)
+        );
+        assert_eq!(
+            check("2024-06-01T00:00:00Z", "2024-06-01T01:00:00Z"),
+            Some(PolicyRule::MaxHorizon)
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Per user quotas, see [`QuotaConfig`] for how they are configured.
+
+use chrono::TimeDelta;
+
+use crate::{
+    config::{Quota, QuotaConfig},
+    utils::timedelta_to_duration,
+    Error, QuotaUsage,
+};
+
+/// Name of the scope of the default quota
+pub const DEFAULT_SCOPE: &str = "default";
+
+/// A quota with the reservations it counts
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaScope<'a> {
+    pub name: &'a str,
+    /// the resources whose reservations are counted, all of them if None
+    pub resources: Option<&'a [String]>,
+    pub quota: &'a Quota,
+}
+
+impl QuotaConfig {
+    /// Every quota which limits something
+    pub fn scopes(&self) -> Vec<QuotaScope<'_>> {
+        let default = QuotaScope {
+            name: DEFAULT_SCOPE,
+            resources: None,
+            quota: &self.default,
+        };
+        let classes = self.classes.iter().map(|(name, class)| QuotaScope {
+            name,
+            resources: Some(&class.resources),
+            quota: &class.quota,
+        });
+
+        std::iter::once(default)
+            .chain(classes)
+            .filter(|scope| scope.quota.is_limited())
+            .collect()
+    }
+
+    /// The quotas which count the reservations of a resource
+    pub fn scopes_for(&self, resource_id: &str) -> Vec<QuotaScope<'_>> {
+        self.scopes()
+            .into_iter()
+            .filter(|scope| match scope.resources {
+                Some(resources) => resources.iter().any(|id| id == resource_id),
+                None => true,
+            })
+            .collect()
+    }
+}
+
+impl Quota {
+    pub fn is_limited(&self) -> bool {
+        self.max_active.is_some() || self.max_weekly_hours.is_some()
+    }
+
+    fn max_weekly(&self) -> Option<TimeDelta> {
+        self.max_weekly_hours
+            .map(|hours| TimeDelta::try_hours(hours as i64).unwrap_or(TimeDelta::MAX))
+    }
+
+    /// Check if a new reservation fits into the quota, given the current usage.
+    /// An active reservation takes one of max_active, the duration counts for its week.
+    pub fn check(
+        &self,
+        scope: &str,
+        active: i64,
+        weekly: TimeDelta,
+        new_active: bool,
+        new_duration: TimeDelta,
+    ) -> Result<(), Error> {
+        let exceeded = |reason: String| {
+            Err(Error::QuotaExceeded {
+                scope: scope.to_string(),
+                reason,
+            })
+        };
+
+        if let Some(max) = self.max_active {
+            if new_active && active >= max as i64 {
+                return exceeded(format!("at most {} active reservations", max));
+            }
+        }
+        if let Some(max) = self.max_weekly() {
+            if weekly + new_duration > max {
+                return exceeded(format!("at most {} hours a week", max.num_hours()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn usage(&self, scope: &str, active: i64, weekly: TimeDelta) -> QuotaUsage {
+        let max_weekly = self.max_weekly();
+        QuotaUsage {
+            scope: scope.to_string(),
+            active: active as i32,
+            max_active: self.max_active.map(|max| max as i32),
+            remaining_active: self
+                .max_active
+                .map(|max| (max as i64 - active).max(0) as i32),
+            weekly: Some(timedelta_to_duration(weekly)),
+            max_weekly: max_weekly.map(timedelta_to_duration),
+            remaining_weekly: max_weekly
+                .map(|max| timedelta_to_duration((max - weekly).max(TimeDelta::zero()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::ClassQuota;
+
+    #[test]
+    fn scopes_for_should_only_return_limiting_quotas() {
+        let config = QuotaConfig {
+            default: Quota::default(),
+            classes: BTreeMap::from([(
+                "rooms".to_string(),
+                ClassQuota {
+                    resources: vec!["room".to_string()],
+                    quota: Quota {
+                        max_active: Some(1),
+                        ..Default::default()
+                    },
+                },
+            )]),
+        };
+
+        let scopes = config.scopes_for("room");
+        assert_eq!(scopes.len(), 1);
+        assert_eq!(scopes[0].name, "rooms");
+        assert!(config.scopes_for("desk").is_empty());
+    }
+
+    #[test]
+    fn check_should_work() {
+        let quota = Quota {
+            max_active: Some(3),
+            max_weekly_hours: Some(10),
+        };
+        let hours = |h| TimeDelta::try_hours(h).unwrap();
+
+        assert!(quota.check("default", 2, hours(8), true, hours(2)).is_ok());
+        assert!(matches!(
+            quota.check("default", 3, hours(0), true, hours(1)),
+            Err(Error::QuotaExceeded { .. })
+        ));
+        // a reservation which is over doesn't take an active one
+        assert!(quota.check("default", 3, hours(0), false, hours(1)).is_ok());
+        assert!(matches!(
+            quota.check("default", 0, hours(9), true, hours(2)),
+            Err(Error::QuotaExceeded { .. })
+        ));
+
+        let usage = quota.usage("default", 1, hours(4));
+        assert_eq!(usage.remaining_active, Some(2));
+        assert_eq!(
+            usage.remaining_weekly,
+            Some(timedelta_to_duration(hours(6)))
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! A subset of the RFC 5545 recurrence rule (RRULE), which is enough to describe
+//! the usual reservation series like "every monday and wednesday, 10 times".
+//!
+//! Supported parts are `FREQ` (`DAILY`, `WEEKLY`, `MONTHLY`, `YEARLY`), `INTERVAL`,
+//! `COUNT`, `UNTIL` and `BYDAY` (weekly only, without ordinal). Either `COUNT` or
+//! `UNTIL` is required, so a series always has an end.
+
+use std::{ops::Range, str::FromStr};
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveDateTime, TimeDelta, Utc, Weekday};
+
+use crate::Error;
+
+/// Max number of occurrences a rule can expand to
+pub const MAX_OCCURRENCES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    pub by_day: Vec<Weekday>,
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |msg: &str| Error::InvalidRecurrenceRule(msg.to_string());
+
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+
+        let mut freq = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        let mut by_day = Vec::new();
+
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| invalid(&format!("malformed part {}", part)))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid(&format!("unsupported FREQ {}", value))),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| *i > 0)
+                        .ok_or_else(|| invalid(&format!("invalid INTERVAL {}", value)))?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|c| *c > 0)
+                            .ok_or_else(|| invalid(&format!("invalid COUNT {}", value)))?,
+                    )
+                }
+                "UNTIL" => {
+                    until = Some(
+                        parse_until(value)
+                            .ok_or_else(|| invalid(&format!("invalid UNTIL {}", value)))?,
+                    )
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let day = match day.to_ascii_uppercase().as_str() {
+                            "MO" => Weekday::Mon,
+                            "TU" => Weekday::Tue,
+                            "WE" => Weekday::Wed,
+                            "TH" => Weekday::Thu,
+                            "FR" => Weekday::Fri,
+                            "SA" => Weekday::Sat,
+                            "SU" => Weekday::Sun,
+                            _ => return Err(invalid(&format!("unsupported BYDAY {}", day))),
+                        };
+                        if !by_day.contains(&day) {
+                            by_day.push(day);
+                        }
+                    }
+                }
+                _ => return Err(invalid(&format!("unsupported part {}", key))),
+            }
+        }
+
+        let freq = freq.ok_or_else(|| invalid("FREQ is required"))?;
+        if count.is_some() == until.is_some() {
+            return Err(invalid("exactly one of COUNT and UNTIL is required"));
+        }
+        if !by_day.is_empty() && freq != Frequency::Weekly {
+            return Err(invalid("BYDAY is only supported with FREQ=WEEKLY"));
+        }
+        by_day.sort_by_key(|d| d.num_days_from_monday());
+
+        Ok(Self {
+            freq,
+            interval,
+            count,
+            until,
+            by_day,
+        })
+    }
+}
+
+// UNTIL is either a UTC date-time (19970714T133000Z) or a date (19970714)
+fn parse_until(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ") {
+        return Some(dt.and_utc());
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y%m%d").ok()?;
+    Some(date.and_hms_opt(23, 59, 59)?.and_utc())
+}
+
+impl RecurrenceRule {
+    /// Expand the rule to the start time of every occurrence, the first one is `start`
+    /// if it matches the rule.
+    pub fn occurrences(&self, start: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, Error> {
+        let mut occurrences = Vec::new();
+        let time = start.time();
+        let date = start.date_naive();
+        let step_months = match self.freq {
+            Frequency::Yearly => self.interval.saturating_mul(12),
+            _ => self.interval,
+        };
+
+        // every period yields the candidates of one step of the rule, in order
+        for period in 0.. {
+            let candidates: Vec<NaiveDate> = match self.freq {
+                Frequency::Daily => date
+                    .checked_add_days(Days::new(period * self.interval as u64))
+                    .into_iter()
+                    .collect(),
+                Frequency::Weekly if self.by_day.is_empty() => date
+                    .checked_add_days(Days::new(period * self.interval as u64 * 7))
+                    .into_iter()
+                    .collect(),
+                Frequency::Weekly => date
+                    .checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64))
+                    .and_then(|monday| {
+                        monday.checked_add_days(Days::new(period * self.interval as u64 * 7))
+                    })
+                    .map(|monday| {
+                        self.by_day
+                            .iter()
+                            .map_while(|d| {
+                                monday.checked_add_days(Days::new(d.num_days_from_monday() as u64))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                Frequency::Monthly | Frequency::Yearly => (period as u32)
+                    .checked_mul(step_months)
+                    .and_then(|months| add_months(date, months))
+                    .into_iter()
+                    .collect(),
+            };
+
+            // a month without the day is skipped, but the series isn't over yet
+            let period_start = match self.freq {
+                Frequency::Monthly | Frequency::Yearly => (period as u32)
+                    .checked_mul(step_months)
+                    .and_then(|months| first_of_month(date, months)),
+                _ => candidates.first().copied(),
+            };
+            let Some(period_start) = period_start else {
+                break;
+            };
+            if let Some(until) = self.until {
+                if period_start.and_time(time).and_utc() > until {
+                    break;
+                }
+            }
+
+            for candidate in candidates {
+                let occurrence = candidate.and_time(time).and_utc();
+                if occurrence < start {
+                    continue;
+                }
+                if matches!(self.until, Some(until) if occurrence > until) {
+                    return Ok(occurrences);
+                }
+                if occurrences.len() == MAX_OCCURRENCES {
+                    return Err(Error::InvalidRecurrenceRule(format!(
+                        "more than {} occurrences",
+                        MAX_OCCURRENCES
+                    )));
+                }
+                occurrences.push(occurrence);
+                if matches!(self.count, Some(count) if occurrences.len() == count as usize) {
+                    return Ok(occurrences);
+                }
+            }
+        }
+
+        Ok(occurrences)
+    }
+
+    /// Expand the rule to the start and end of every occurrence, each one as long as
+    /// the first occurrence
+    pub fn timespans(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Range<DateTime<Utc>>>, Error> {
+        let duration: TimeDelta = end - start;
+        Ok(self
+            .occurrences(start)?
+            .into_iter()
+            .map(|start| start..start + duration)
+            .collect())
+    }
+}
+
+fn first_of_month(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let months = date.month0().checked_add(months)?;
+    let year = date.year().checked_add((months / 12) as i32)?;
+    NaiveDate::from_ymd_opt(year, months % 12 + 1, 1)
+}
+
+fn add_months(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let first = first_of_month(date, months)?;
+    NaiveDate::from_ymd_opt(first.year(), first.month(), date.day())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn parse_rule_should_work() {
+        let rule: RecurrenceRule = "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=WE,MO;COUNT=4"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.freq, Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.count, Some(4));
+        assert_eq!(rule.by_day, vec![Weekday::Mon, Weekday::Wed]);
+    }
+
+    #[test]
+    fn parse_rule_without_end_should_fail() {
+        let rule = "FREQ=DAILY".parse::<RecurrenceRule>();
+        assert!(matches!(rule, Err(Error::InvalidRecurrenceRule(_))));
+
+        let rule = "FREQ=DAILY;BYMONTH=1;COUNT=2".parse::<RecurrenceRule>();
+        assert!(matches!(rule, Err(Error::InvalidRecurrenceRule(_))));
+    }
+
+    #[test]
+    fn weekly_by_day_should_work() {
+        // 2024-01-03 is a wednesday
+        let rule: RecurrenceRule = "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4".parse().unwrap();
+        let occurrences = rule.occurrences(utc("2024-01-03T10:00:00Z")).unwrap();
+        assert_eq!(
+            occurrences,
+            vec![
+                utc("2024-01-03T10:00:00Z"),
+                utc("2024-01-08T10:00:00Z"),
+                utc("2024-01-10T10:00:00Z"),
+                utc("2024-01-15T10:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_by_day_beyond_the_calendar_should_end() {
+        let rule: RecurrenceRule = "FREQ=WEEKLY;BYDAY=MO;INTERVAL=100000000;COUNT=2"
+            .parse()
+            .unwrap();
+        let occurrences = rule.occurrences(utc("2024-01-01T10:00:00Z")).unwrap();
+        assert_eq!(occurrences, vec![utc("2024-01-01T10:00:00Z")]);
+    }
+
+    #[test]
+    fn daily_until_should_work() {
+        let rule: RecurrenceRule = "FREQ=DAILY;INTERVAL=2;UNTIL=20240107T100000Z"
+            .parse()
+            .unwrap();
+        let occurrences = rule.occurrences(utc("2024-01-01T10:00:00Z")).unwrap();
+        assert_eq!(occurrences.len(), 4);
+        assert_eq!(occurrences[3], utc("2024-01-07T10:00:00Z"));
+    }
+
+    #[test]
+    fn monthly_should_skip_missing_days() {
+        let rule: RecurrenceRule = "FREQ=MONTHLY;COUNT=3".parse().unwrap();
+        let occurrences = rule.occurrences(utc("2024-01-31T10:00:00Z")).unwrap();
+        assert_eq!(
+            occurrences,
+            vec![
+                utc("2024-01-31T10:00:00Z"),
+                utc("2024-03-31T10:00:00Z"),
+                utc("2024-05-31T10:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_occurrences_should_fail() {
+        let rule: RecurrenceRule = "FREQ=DAILY;COUNT=501".parse().unwrap();
+        let occurrences = rule.occurrences(utc("2024-01-01T10:00:00Z"));
+        assert!(matches!(occurrences, Err(Error::InvalidRecurrenceRule(_))));
+    }
+}
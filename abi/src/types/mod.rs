@@ -1,3 +1,18 @@
 mod reservation;
 mod reservation_query;
+mod reservation_series;
 mod reservation_status;
+mod reservation_update_type;
+mod resource;
+mod time_slot;
+
+use chrono::TimeDelta;
+
+use crate::utils::duration_to_timedelta;
+
+// a buffer which is set must be a duration of at least zero
+fn valid_buffer(buffer: Option<&prost_types::Duration>) -> bool {
+    buffer.is_none_or(|buffer| {
+        duration_to_timedelta(buffer).is_some_and(|buffer| buffer >= TimeDelta::zero())
+    })
+}
@@ -1,13 +1,20 @@
 use std::ops::Bound;
 
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
 use sqlx::{
-    postgres::{types::PgRange, PgRow},
+    postgres::{
+        types::{PgInterval, PgRange},
+        PgRow,
+    },
     FromRow, Row,
 };
 
+use super::valid_buffer;
 use crate::{
-    utils::{datetime_to_timestamp, timestamp_to_datetime},
+    utils::{
+        datetime_to_timestamp, duration_to_timedelta, interval_to_duration, timestamp_to_datetime,
+        try_timestamp_to_datetime,
+    },
     Error, Reservation, ReservationStatus,
 };
 
@@ -27,6 +34,14 @@ impl Reservation {
             start: Some(datetime_to_timestamp(start.with_timezone(&Utc))),
             end: Some(datetime_to_timestamp(end.with_timezone(&Utc))),
             note: note.into(),
+            cancel_reason: None,
+            series_id: None,
+            expires_at: None,
+            seats: 1,
+            buffer_before: None,
+            buffer_after: None,
+            waitlist_id: None,
+            version: 1,
         }
     }
 
@@ -34,6 +49,12 @@ impl Reservation {
         if self.user_id.is_empty() {
             return Err(Error::InvalidUserId);
         }
+        if self.seats < 0 {
+            return Err(Error::InvalidSeats);
+        }
+        if !valid_buffer(self.buffer_before.as_ref()) || !valid_buffer(self.buffer_after.as_ref()) {
+            return Err(Error::InvalidBuffer);
+        }
 
         Ok(())
     }
@@ -48,10 +69,33 @@ impl Reservation {
             return Err(Error::InvalidTimespan);
         }
 
-        let start = timestamp_to_datetime(self.start.as_ref().unwrap());
-        let end = timestamp_to_datetime(self.end.as_ref().unwrap());
+        let start = try_timestamp_to_datetime(self.start.as_ref().unwrap());
+        let end = try_timestamp_to_datetime(self.end.as_ref().unwrap());
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(Error::InvalidTimespan);
+        };
         Ok((start..end).into())
     }
+
+    /// Seats taken by the reservation, 0 is taken as 1
+    pub fn seats(&self) -> i32 {
+        self.seats.max(1)
+    }
+
+    /// The buffer before the reservation, None if it has none or it's out of range,
+    /// which `validate` rejects
+    pub fn buffer_before(&self) -> Option<TimeDelta> {
+        self.buffer_before.as_ref().and_then(duration_to_timedelta)
+    }
+
+    /// The buffer after the reservation, like `buffer_before`
+    pub fn buffer_after(&self) -> Option<TimeDelta> {
+        self.buffer_after.as_ref().and_then(duration_to_timedelta)
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.as_ref().map(timestamp_to_datetime)
+    }
 }
 
 impl FromRow<'_, PgRow> for Reservation {
@@ -80,6 +124,22 @@ impl FromRow<'_, PgRow> for Reservation {
             start,
             end,
             note: row.get("note"),
+            cancel_reason: row.get("cancel_reason"),
+            series_id: row.get("series_id"),
+            expires_at: row
+                .get::<Option<DateTime<Utc>>, _>("expires_at")
+                .map(datetime_to_timestamp),
+            // snapshots taken before seats existed don't have it
+            seats: row.get::<Option<i32>, _>("seats").unwrap_or(1),
+            buffer_before: row
+                .get::<Option<PgInterval>, _>("buffer_before")
+                .map(interval_to_duration),
+            buffer_after: row
+                .get::<Option<PgInterval>, _>("buffer_after")
+                .map(interval_to_duration),
+            waitlist_id: row.get("waitlist_id"),
+            // snapshots taken before versions existed don't have it
+            version: row.get::<Option<i64>, _>("version").unwrap_or(1),
         })
     }
 }
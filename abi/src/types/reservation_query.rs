@@ -1,20 +1,23 @@
 use chrono::{DateTime, Utc};
 use sqlx::postgres::types::PgRange;
 
-use crate::{utils::timestamp_to_datetime, Error, ReservationQuery};
+use crate::{utils::try_timestamp_to_datetime, Error, ReservationQuery};
 
 impl ReservationQuery {
     pub fn timespan(&self) -> Result<PgRange<DateTime<Utc>>, Error> {
-        let range = match (&self.start, &self.end) {
-            (Some(start), Some(end)) => {
-                if start.seconds >= end.seconds && start.nanos >= end.nanos {
-                    return Err(Error::InvalidTimespan);
-                }
-                (timestamp_to_datetime(start)..timestamp_to_datetime(end)).into()
+        if let (Some(start), Some(end)) = (&self.start, &self.end) {
+            if start.seconds >= end.seconds && start.nanos >= end.nanos {
+                return Err(Error::InvalidTimespan);
             }
+        }
+        let datetime = |ts| try_timestamp_to_datetime(ts).ok_or(Error::InvalidTimespan);
+        let start = self.start.as_ref().map(datetime).transpose()?;
+        let end = self.end.as_ref().map(datetime).transpose()?;
+        let range = match (start, end) {
+            (Some(start), Some(end)) => (start..end).into(),
             (None, None) => return Err(Error::InvalidTimespan),
-            (None, Some(end)) => (..timestamp_to_datetime(end)).into(),
-            (Some(start), None) => (timestamp_to_datetime(start)..).into(),
+            (None, Some(end)) => (..end).into(),
+            (Some(start), None) => (start..).into(),
         };
         Ok(range)
     }
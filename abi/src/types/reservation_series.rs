@@ -0,0 +1,54 @@
+use crate::{
+    recurrence::RecurrenceRule,
+    utils::{datetime_to_timestamp, try_timestamp_to_datetime},
+    Error, Reservation, ReservationSeries, ReservationStatus,
+};
+
+impl ReservationSeries {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.user_id.is_empty() {
+            return Err(Error::InvalidUserId);
+        }
+
+        Ok(())
+    }
+
+    /// Expand the series to a pending reservation for every occurrence
+    pub fn occurrences(&self) -> Result<Vec<Reservation>, Error> {
+        self.validate()?;
+
+        let start = self.start.as_ref().and_then(try_timestamp_to_datetime);
+        let end = self.end.as_ref().and_then(try_timestamp_to_datetime);
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(Error::InvalidTimespan);
+        };
+        if start >= end {
+            return Err(Error::InvalidTimespan);
+        }
+
+        let rule: RecurrenceRule = self.rrule.parse()?;
+        let occurrences = rule
+            .timespans(start, end)?
+            .into_iter()
+            .map(|timespan| Reservation {
+                id: 0,
+                user_id: self.user_id.clone(),
+                status: ReservationStatus::Pending as i32,
+                resource_id: self.resource_id.clone(),
+                start: Some(datetime_to_timestamp(timespan.start)),
+                end: Some(datetime_to_timestamp(timespan.end)),
+                note: self.note.clone(),
+                cancel_reason: None,
+                series_id: (self.id != 0).then_some(self.id),
+                expires_at: None,
+                seats: 1,
+                buffer_before: None,
+                buffer_after: None,
+                waitlist_id: None,
+                version: 1,
+            })
+            .collect();
+
+        Ok(occurrences)
+    }
+}
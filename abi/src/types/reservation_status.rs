@@ -41,6 +41,7 @@ impl sqlx::Encode<'_, sqlx::Postgres> for ReservationStatus {
             ReservationStatus::Pending => "pending",
             ReservationStatus::Confirmed => "confirmed",
             ReservationStatus::Blocked => "blocked",
+            ReservationStatus::Cancelled => "cancelled",
             ReservationStatus::Unknown => "unknown",
         };
 
@@ -59,6 +60,7 @@ impl sqlx::Decode<'_, sqlx::Postgres> for ReservationStatus {
             "pending" => ReservationStatus::Pending,
             "confirmed" => ReservationStatus::Confirmed,
             "blocked" => ReservationStatus::Blocked,
+            "cancelled" => ReservationStatus::Cancelled,
             "unknown" => ReservationStatus::Unknown,
             _ => return Err("Invalid status".into()),
         })
@@ -71,6 +73,7 @@ impl Display for ReservationStatus {
             ReservationStatus::Pending => "pending",
             ReservationStatus::Confirmed => "confirmed",
             ReservationStatus::Blocked => "blocked",
+            ReservationStatus::Cancelled => "cancelled",
             ReservationStatus::Unknown => "unknown",
         };
 
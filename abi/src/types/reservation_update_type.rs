@@ -0,0 +1,50 @@
+use std::fmt::Display;
+
+use crate::ReservationUpdateType;
+
+impl sqlx::Type<sqlx::Postgres> for ReservationUpdateType {
+    fn type_info() -> <sqlx::Postgres as sqlx::Database>::TypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("reservation_update_type")
+    }
+}
+
+impl sqlx::Encode<'_, sqlx::Postgres> for ReservationUpdateType {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <sqlx::Postgres as sqlx::database::HasArguments<'_>>::ArgumentBuffer,
+    ) -> sqlx::encode::IsNull {
+        buf.extend(self.to_string().as_bytes());
+        sqlx::encode::IsNull::No
+    }
+}
+
+impl sqlx::Decode<'_, sqlx::Postgres> for ReservationUpdateType {
+    fn decode(
+        value: <sqlx::Postgres as sqlx::database::HasValueRef<'_>>::ValueRef,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let op = value.as_str()?;
+
+        Ok(match op {
+            "create" => ReservationUpdateType::Create,
+            "update" => ReservationUpdateType::Update,
+            "delete" => ReservationUpdateType::Delete,
+            "promote" => ReservationUpdateType::Promote,
+            "unknown" => ReservationUpdateType::Unknown,
+            _ => return Err("Invalid update type".into()),
+        })
+    }
+}
+
+impl Display for ReservationUpdateType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            ReservationUpdateType::Create => "create",
+            ReservationUpdateType::Update => "update",
+            ReservationUpdateType::Delete => "delete",
+            ReservationUpdateType::Promote => "promote",
+            ReservationUpdateType::Unknown => "unknown",
+        };
+
+        write!(f, "{}", op)
+    }
+}
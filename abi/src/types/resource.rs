@@ -0,0 +1,77 @@
+use chrono::TimeDelta;
+use sqlx::{
+    postgres::{types::PgInterval, PgRow},
+    FromRow, Row,
+};
+
+use super::valid_buffer;
+use crate::{
+    utils::{duration_to_timedelta, interval_to_duration},
+    Error, Resource,
+};
+
+impl Resource {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            capacity: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Capacity of the resource, 0 is taken as 1
+    pub fn capacity(&self) -> i32 {
+        self.capacity.max(1)
+    }
+
+    /// The default buffer before its reservations, zero if it's out of range,
+    /// which `validate` rejects
+    pub fn buffer_before(&self) -> TimeDelta {
+        self.buffer_before
+            .as_ref()
+            .and_then(duration_to_timedelta)
+            .unwrap_or_default()
+    }
+
+    /// The default buffer after its reservations, like `buffer_before`
+    pub fn buffer_after(&self) -> TimeDelta {
+        self.buffer_after
+            .as_ref()
+            .and_then(duration_to_timedelta)
+            .unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.id.is_empty() || self.id.len() > 64 {
+            return Err(Error::InvalidResourceId);
+        }
+        if self.capacity < 0 {
+            return Err(Error::InvalidCapacity);
+        }
+        if !valid_buffer(self.buffer_before.as_ref()) || !valid_buffer(self.buffer_after.as_ref()) {
+            return Err(Error::InvalidBuffer);
+        }
+
+        Ok(())
+    }
+}
+
+impl FromRow<'_, PgRow> for Resource {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            location: row.get("location"),
+            capabilities: row.get("capabilities"),
+            disabled: row.get("disabled"),
+            capacity: row.get("capacity"),
+            buffer_before: Some(interval_to_duration(
+                row.get::<PgInterval, _>("buffer_before"),
+            )),
+            buffer_after: Some(interval_to_duration(
+                row.get::<PgInterval, _>("buffer_after"),
+            )),
+        })
+    }
+}
@@ -0,0 +1,27 @@
+use std::ops::Bound;
+
+use chrono::{DateTime, Utc};
+use sqlx::{
+    postgres::{types::PgRange, PgRow},
+    FromRow, Row,
+};
+
+use crate::{utils::datetime_to_timestamp, TimeSlot};
+
+impl FromRow<'_, PgRow> for TimeSlot {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        let range = row.get::<PgRange<DateTime<Utc>>, _>("timespan");
+        let start = match range.start {
+            Bound::Included(start) | Bound::Excluded(start) => Some(start),
+            Bound::Unbounded => None,
+        }
+        .map(datetime_to_timestamp);
+        let end = match range.end {
+            Bound::Included(end) | Bound::Excluded(end) => Some(end),
+            Bound::Unbounded => None,
+        }
+        .map(datetime_to_timestamp);
+
+        Ok(Self { start, end })
+    }
+}
@@ -1,6 +1,7 @@
 use std::time::SystemTime;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::postgres::types::PgInterval;
 
 pub fn timestamp_to_datetime(ts: &prost_types::Timestamp) -> DateTime<Utc> {
     DateTime::from(
@@ -10,6 +11,11 @@ pub fn timestamp_to_datetime(ts: &prost_types::Timestamp) -> DateTime<Utc> {
     )
 }
 
+/// Convert a protobuf timestamp, None if it's out of the range of `DateTime`
+pub fn try_timestamp_to_datetime(ts: &prost_types::Timestamp) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(ts.seconds, u32::try_from(ts.nanos).ok()?)
+}
+
 pub fn datetime_to_timestamp(dt: DateTime<Utc>) -> prost_types::Timestamp {
     let duration = dt - DateTime::from(SystemTime::UNIX_EPOCH);
     prost_types::Timestamp {
@@ -17,3 +23,22 @@ pub fn datetime_to_timestamp(dt: DateTime<Utc>) -> prost_types::Timestamp {
         nanos: duration.subsec_nanos(),
     }
 }
+
+/// Convert a protobuf duration, None if it's out of the range of `TimeDelta`
+pub fn duration_to_timedelta(duration: &prost_types::Duration) -> Option<TimeDelta> {
+    TimeDelta::try_seconds(duration.seconds)?
+        .checked_add(&TimeDelta::nanoseconds(duration.nanos as i64))
+}
+
+pub fn timedelta_to_duration(delta: TimeDelta) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: delta.num_seconds(),
+        nanos: delta.subsec_nanos(),
+    }
+}
+
+/// Convert a Postgres interval, a month is taken as 30 days
+pub fn interval_to_duration(interval: PgInterval) -> prost_types::Duration {
+    let days = interval.months as i64 * 30 + interval.days as i64;
+    timedelta_to_duration(TimeDelta::days(days) + TimeDelta::microseconds(interval.microseconds))
+}
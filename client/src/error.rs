@@ -0,0 +1,181 @@
+use abi::error::{conflict::ReservationConflict, reason};
+use abi::rpc::{self, BadRequest, ErrorInfo};
+use prost::Message;
+use thiserror::Error;
+use tonic::{Code, Status};
+
+const BAD_REQUEST: &str = "type.googleapis.com/google.rpc.BadRequest";
+
+/// An error of the service decoded from its status, or of the connection to it
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The reservation, or a reservation of a batch or series, conflicts with existing ones
+    /// The conflicts are empty if the service couldn't tell them.
+    #[error("{message}")]
+    Conflict {
+        message: String,
+        conflicts: Vec<ReservationConflict>,
+    },
+
+    /// An argument is invalid, `field` tells which one if the service did
+    #[error("{message}")]
+    InvalidArgument {
+        field: Option<String>,
+        message: String,
+    },
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Hold expired")]
+    HoldExpired,
+
+    #[error("Version mismatch: expected {expected}, found {actual}")]
+    VersionMismatch { expected: i64, actual: i64 },
+
+    #[error("Resource already exists")]
+    ResourceAlreadyExists,
+
+    #[error("Resource not available: {0}")]
+    ResourceNotAvailable(String),
+
+    #[error("Resource in use")]
+    ResourceInUse,
+
+    /// A precondition of the call doesn't hold, e.g. a booking rule of the resource
+    #[error("{0}")]
+    FailedPrecondition(String),
+
+    #[error("{0}")]
+    QuotaExceeded(String),
+
+    #[error("{0}")]
+    PermissionDenied(String),
+
+    #[error("{0}")]
+    Unauthenticated(String),
+
+    #[error("Invalid endpoint: {0}")]
+    InvalidEndpoint(String),
+
+    #[error("Missing {0} in the response")]
+    MissingField(&'static str),
+
+    #[error("Transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+
+    /// Any other status
+    #[error("{0}")]
+    Status(Box<Status>),
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        let message = status.message().to_string();
+        let info = ErrorInfo::from_status(&status).unwrap_or_default();
+        match (status.code(), info.reason.as_str()) {
+            (Code::AlreadyExists, reason::RESOURCE_ALREADY_EXISTS) => Error::ResourceAlreadyExists,
+            (Code::AlreadyExists, _) => Error::Conflict {
+                conflicts: ReservationConflict::from_status(&status),
+                message,
+            },
+            (Code::InvalidArgument, _) => Error::InvalidArgument {
+                field: invalid_field(&status),
+                message,
+            },
+            (Code::NotFound, _) => Error::NotFound,
+            (Code::FailedPrecondition, reason::HOLD_EXPIRED) => Error::HoldExpired,
+            (Code::FailedPrecondition, reason::RESOURCE_IN_USE) => Error::ResourceInUse,
+            (Code::FailedPrecondition, reason::RESOURCE_NOT_AVAILABLE) => {
+                match info.metadata.get("resource_id") {
+                    Some(id) => Error::ResourceNotAvailable(id.clone()),
+                    None => Error::FailedPrecondition(message),
+                }
+            }
+            (Code::FailedPrecondition, reason::VERSION_MISMATCH) => {
+                let version = |key| info.metadata.get(key).and_then(|v| v.parse().ok());
+                match (version("expected"), version("actual")) {
+                    (Some(expected), Some(actual)) => Error::VersionMismatch { expected, actual },
+                    _ => Error::FailedPrecondition(message),
+                }
+            }
+            (Code::FailedPrecondition, _) => Error::FailedPrecondition(message),
+            (Code::ResourceExhausted, _) => Error::QuotaExceeded(message),
+            (Code::PermissionDenied, _) => Error::PermissionDenied(message),
+            (Code::Unauthenticated, _) => Error::Unauthenticated(message),
+            _ => Error::Status(Box::new(status)),
+        }
+    }
+}
+
+impl Error {
+    /// Whether the call may succeed if it's made again, see `RetryPolicy`
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::Status(status) if is_transient(status))
+    }
+}
+
+// the service is unavailable while it can't be connected to, or while it's shutting down
+pub(crate) fn is_transient(status: &Status) -> bool {
+    status.code() == Code::Unavailable
+}
+
+// the field of the `BadRequest` in the details of the status
+fn invalid_field(status: &Status) -> Option<String> {
+    let details = rpc::Status::decode(status.details()).ok()?;
+    details
+        .details
+        .iter()
+        .filter(|any| any.type_url == BAD_REQUEST)
+        .filter_map(|any| BadRequest::decode(any.value.as_slice()).ok())
+        .flat_map(|bad_request| bad_request.field_violations)
+        .map(|violation| violation.field)
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_should_be_decoded() {
+        let status = Status::from(abi::Error::VersionMismatch {
+            expected: 1,
+            actual: 2,
+        });
+        assert!(matches!(
+            Error::from(status),
+            Error::VersionMismatch {
+                expected: 1,
+                actual: 2
+            }
+        ));
+
+        let status = Status::from(abi::Error::InvalidTimespan);
+        let Error::InvalidArgument { field, .. } = Error::from(status) else {
+            panic!("expected an invalid argument");
+        };
+        assert_eq!(field.as_deref(), Some("timespan"));
+
+        let status = Status::from(abi::Error::ResourceNotAvailable("room".to_string()));
+        assert!(matches!(Error::from(status), Error::ResourceNotAvailable(id) if id == "room"));
+
+        let status = Status::from(abi::Error::ResourceAlreadyExists);
+        assert!(matches!(Error::from(status), Error::ResourceAlreadyExists));
+
+        // the reason tells the errors apart, not the message
+        let status = Status::from(abi::Error::HoldExpired);
+        let status = Status::with_details(
+            status.code(),
+            "La réservation a expiré",
+            status.details().to_vec().into(),
+        );
+        assert!(matches!(Error::from(status), Error::HoldExpired));
+
+        let status = Status::failed_precondition("Hold expired");
+        assert!(matches!(Error::from(status), Error::FailedPrecondition(_)));
+
+        let status = Status::unavailable("connection refused");
+        assert!(Error::from(status).is_transient());
+    }
+}
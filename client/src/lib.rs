@@ -0,0 +1,571 @@
+//! A typed async client of the reservation service.
+//!
+//! It takes chrono types instead of protobuf timestamps, decodes the status of a failed call
+//! into an [`Error`] and retries calls which failed for a transient reason.
+
+mod error;
+
+use std::{future::Future, ops::Range, time::Duration};
+
+use abi::{
+    reservation_service_client::ReservationServiceClient,
+    utils::{datetime_to_timestamp, timedelta_to_duration, timestamp_to_datetime},
+    AvailabilityRequest, CancelRequest, ChangeResourceRequest, ConfirmRequest,
+    CreateResourceRequest, DeleteResourceRequest, FilterRequest, GetHistoryRequest, GetRequest,
+    GetResourceRequest, ListResourcesRequest, ListenRequest, QueryRequest, QuotaUsageRequest,
+    RescheduleRequest, ReserveBatchRequest, ReserveRequest, ReserveSeriesRequest, UpdateRequest,
+    UpdateResourceRequest,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{
+    metadata::AsciiMetadataValue,
+    transport::{Channel, Endpoint},
+    Request, Response, Status,
+};
+
+pub use abi::{
+    error::conflict::{ReservationConflict, ReservationWindow},
+    FilterPager, ListenResponse, QuotaUsage, Reservation, ReservationChange, ReservationFilter,
+    ReservationFilterBuilder, ReservationQuery, ReservationQueryBuilder, ReservationSeries,
+    ReservationStatus, ReservationUpdateType, Resource, SeriesConflictMode,
+};
+pub use error::Error;
+
+pub type ReservationId = i64;
+pub type WaitlistId = i64;
+
+/// How a call failing for a transient reason is retried
+/// The client waits `backoff` before the first retry and twice as long before each next one.
+/// Only reads and the changes with an idempotency key are retried, any other change could be
+/// made twice if the service got the first request but couldn't answer it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
+/// Options of a reservation, besides the reservation itself
+#[derive(Debug, Clone, Default)]
+pub struct ReserveOptions {
+    /// hold the pending reservation only for this long, unless it's confirmed
+    pub hold_ttl: Option<TimeDelta>,
+    /// put the reservation on the waitlist if its timespan is taken
+    pub waitlist: bool,
+    /// a retry with the same key gets the outcome of the first request instead of reserving again
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReserveOutcome {
+    Reserved(Box<Reservation>),
+    Waitlisted(WaitlistId),
+}
+
+#[derive(Debug, Clone)]
+pub struct ReservationClient {
+    inner: ReservationServiceClient<Channel>,
+    tenant: Option<String>,
+    token: Option<String>,
+    retry: RetryPolicy,
+    idempotency_key: Option<String>,
+}
+
+impl ReservationClient {
+    /// Connect to the service at an endpoint like `http://127.0.0.1:50051`
+    pub async fn connect(endpoint: impl Into<String>) -> Result<Self, Error> {
+        let endpoint = endpoint.into();
+        let channel = Endpoint::from_shared(endpoint.clone())
+            .map_err(|_| Error::InvalidEndpoint(endpoint))?
+            .connect()
+            .await?;
+        Ok(Self::new(channel))
+    }
+
+    /// A client on a channel configured by the caller, e.g. a lazily connected one
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: ReservationServiceClient::new(channel),
+            tenant: None,
+            token: None,
+            retry: RetryPolicy::default(),
+            idempotency_key: None,
+        }
+    }
+
+    /// Make every call on behalf of a tenant
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Authenticate every call with a bearer token
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Make every change of a reservation once for an idempotency key, so it's retried like a
+    /// read, the key of `ReserveOptions` wins. The calls changing resources don't take a key
+    /// and aren't retried. A key is for a single change, use a clone of the client with a new
+    /// key for each one.
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Reserve a resource for a user, the reservation is pending until it's confirmed
+    pub async fn reserve(
+        &self,
+        user_id: impl Into<String>,
+        resource_id: impl Into<String>,
+        timespan: Range<DateTime<Utc>>,
+    ) -> Result<Reservation, Error> {
+        let rsvp = Reservation::new_pendding(
+            user_id,
+            resource_id,
+            timespan.start.fixed_offset(),
+            timespan.end.fixed_offset(),
+            "",
+        );
+        match self.reserve_with(rsvp, ReserveOptions::default()).await? {
+            ReserveOutcome::Reserved(rsvp) => Ok(*rsvp),
+            ReserveOutcome::Waitlisted(_) => Err(Error::MissingField("reservation")),
+        }
+    }
+
+    /// Reserve a reservation built by the caller, e.g. with a note or several seats
+    pub async fn reserve_with(
+        &self,
+        rsvp: Reservation,
+        options: ReserveOptions,
+    ) -> Result<ReserveOutcome, Error> {
+        let idempotency_key = options
+            .idempotency_key
+            .or_else(|| self.idempotency_key.clone());
+        // a retry with the key can't reserve twice
+        let idempotent = idempotency_key.is_some();
+        let request = ReserveRequest {
+            reservation: Some(rsvp),
+            hold_ttl: options.hold_ttl.map(timedelta_to_duration),
+            waitlist: options.waitlist,
+            idempotency_key,
+        };
+        let response = self
+            .call(request, idempotent, |mut client, request| async move {
+                client.reserve(request).await
+            })
+            .await?;
+
+        match (response.reservation, response.waitlist_id) {
+            (Some(rsvp), _) => Ok(ReserveOutcome::Reserved(Box::new(rsvp))),
+            (None, Some(id)) => Ok(ReserveOutcome::Waitlisted(id)),
+            (None, None) => Err(Error::MissingField("reservation")),
+        }
+    }
+
+    /// Reserve all reservations or none of them
+    pub async fn reserve_batch(
+        &self,
+        reservations: Vec<Reservation>,
+    ) -> Result<Vec<Reservation>, Error> {
+        let request = ReserveBatchRequest {
+            reservations,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.reserve_batch(request).await },
+            )
+            .await?;
+        Ok(response.reservations)
+    }
+
+    /// Reserve the occurrences of a series, returns the series, the reserved occurrences and
+    /// the ones skipped for conflicts
+    pub async fn reserve_series(
+        &self,
+        series: ReservationSeries,
+        mode: SeriesConflictMode,
+    ) -> Result<(ReservationSeries, Vec<Reservation>, Vec<Reservation>), Error> {
+        let request = ReserveSeriesRequest {
+            series: Some(series),
+            mode: mode as i32,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.reserve_series(request).await },
+            )
+            .await?;
+        let series = response.series.ok_or(Error::MissingField("series"))?;
+        Ok((series, response.reservations, response.conflicts))
+    }
+
+    pub async fn confirm(
+        &self,
+        id: ReservationId,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, Error> {
+        let request = ConfirmRequest {
+            id,
+            expected_version,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.confirm(request).await },
+            )
+            .await?;
+        reservation(response.reservation)
+    }
+
+    pub async fn update_note(
+        &self,
+        id: ReservationId,
+        note: impl Into<String>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, Error> {
+        let request = UpdateRequest {
+            id,
+            note: note.into(),
+            expected_version,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.update(request).await },
+            )
+            .await?;
+        reservation(response.reservation)
+    }
+
+    /// Move a reservation to a new timespan, keeping its id
+    pub async fn reschedule(
+        &self,
+        id: ReservationId,
+        timespan: Range<DateTime<Utc>>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, Error> {
+        let request = RescheduleRequest {
+            id,
+            start: Some(datetime_to_timestamp(timespan.start)),
+            end: Some(datetime_to_timestamp(timespan.end)),
+            expected_version,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.reschedule(request).await },
+            )
+            .await?;
+        reservation(response.reservation)
+    }
+
+    /// Move a reservation to another resource, keeping its timespan
+    pub async fn change_resource(
+        &self,
+        id: ReservationId,
+        resource_id: impl Into<String>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, Error> {
+        let request = ChangeResourceRequest {
+            id,
+            resource_id: resource_id.into(),
+            expected_version,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.change_resource(request).await },
+            )
+            .await?;
+        reservation(response.reservation)
+    }
+
+    pub async fn cancel(
+        &self,
+        id: ReservationId,
+        reason: Option<String>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, Error> {
+        let request = CancelRequest {
+            id,
+            reason,
+            expected_version,
+            idempotency_key: self.idempotency_key.clone(),
+        };
+        let response = self
+            .call(
+                request,
+                self.idempotency_key.is_some(),
+                |mut client, request| async move { client.cancel(request).await },
+            )
+            .await?;
+        reservation(response.reservation)
+    }
+
+    pub async fn get(&self, id: ReservationId) -> Result<Reservation, Error> {
+        let response = self
+            .call(GetRequest { id }, true, |mut client, request| async move {
+                client.get(request).await
+            })
+            .await?;
+        reservation(response.reservation)
+    }
+
+    /// The changes of a reservation, oldest first
+    pub async fn get_history(&self, id: ReservationId) -> Result<Vec<ReservationChange>, Error> {
+        let response = self
+            .call(
+                GetHistoryRequest { id },
+                true,
+                |mut client, request| async move { client.get_history(request).await },
+            )
+            .await?;
+        Ok(response.changes)
+    }
+
+    /// Stream the reservations matching a query
+    pub async fn query(
+        &self,
+        query: ReservationQuery,
+    ) -> Result<impl Stream<Item = Result<Reservation, Error>> + Send + Unpin, Error> {
+        let request = QueryRequest { query: Some(query) };
+        let stream = self
+            .call(request, true, |mut client, request| async move {
+                client.query(request).await
+            })
+            .await?;
+        Ok(stream.map(|rsvp| rsvp.map_err(Error::from)))
+    }
+
+    pub async fn filter(
+        &self,
+        filter: ReservationFilter,
+    ) -> Result<(FilterPager, Vec<Reservation>), Error> {
+        let request = FilterRequest {
+            filter: Some(filter),
+        };
+        let response = self
+            .call(request, true, |mut client, request| async move {
+                client.filter(request).await
+            })
+            .await?;
+        let pager = response.pager.ok_or(Error::MissingField("pager"))?;
+        Ok((pager, response.reservation))
+    }
+
+    /// The free timespans of a resource within a window, which are at least as long as duration,
+    /// which must be positive
+    pub async fn find_availability(
+        &self,
+        resource_id: impl Into<String>,
+        window: Range<DateTime<Utc>>,
+        duration: TimeDelta,
+    ) -> Result<Vec<Range<DateTime<Utc>>>, Error> {
+        let request = AvailabilityRequest {
+            resource_id: resource_id.into(),
+            start: Some(datetime_to_timestamp(window.start)),
+            end: Some(datetime_to_timestamp(window.end)),
+            duration: Some(timedelta_to_duration(duration)),
+        };
+        let response = self
+            .call(request, true, |mut client, request| async move {
+                client.find_availability(request).await
+            })
+            .await?;
+
+        response
+            .slots
+            .into_iter()
+            .map(|slot| match (slot.start, slot.end) {
+                (Some(start), Some(end)) => {
+                    Ok(timestamp_to_datetime(&start)..timestamp_to_datetime(&end))
+                }
+                _ => Err(Error::MissingField("slot timespan")),
+            })
+            .collect()
+    }
+
+    /// Usage of every quota which limits a user, in the week of `week`, or this week
+    pub async fn quota_usage(
+        &self,
+        user_id: impl Into<String>,
+        week: Option<DateTime<Utc>>,
+    ) -> Result<Vec<QuotaUsage>, Error> {
+        let request = QuotaUsageRequest {
+            user_id: user_id.into(),
+            week: week.map(datetime_to_timestamp),
+        };
+        let response = self
+            .call(request, true, |mut client, request| async move {
+                client.get_quota_usage(request).await
+            })
+            .await?;
+        Ok(response.usages)
+    }
+
+    pub async fn create_resource(&self, resource: Resource) -> Result<Resource, Error> {
+        let request = CreateResourceRequest {
+            resource: Some(resource),
+        };
+        let response = self
+            .call(request, false, |mut client, request| async move {
+                client.create_resource(request).await
+            })
+            .await?;
+        resource_of(response.resource)
+    }
+
+    pub async fn get_resource(&self, id: impl Into<String>) -> Result<Resource, Error> {
+        let request = GetResourceRequest { id: id.into() };
+        let response = self
+            .call(request, true, |mut client, request| async move {
+                client.get_resource(request).await
+            })
+            .await?;
+        resource_of(response.resource)
+    }
+
+    /// Replace everything but the id of a resource
+    pub async fn update_resource(&self, resource: Resource) -> Result<Resource, Error> {
+        let request = UpdateResourceRequest {
+            resource: Some(resource),
+        };
+        let response = self
+            .call(request, false, |mut client, request| async move {
+                client.update_resource(request).await
+            })
+            .await?;
+        resource_of(response.resource)
+    }
+
+    /// Delete a resource, which must not be referenced by any reservation
+    pub async fn delete_resource(&self, id: impl Into<String>) -> Result<Resource, Error> {
+        let request = DeleteResourceRequest { id: id.into() };
+        let response = self
+            .call(request, false, |mut client, request| async move {
+                client.delete_resource(request).await
+            })
+            .await?;
+        resource_of(response.resource)
+    }
+
+    pub async fn list_resources(&self) -> Result<Vec<Resource>, Error> {
+        let response = self
+            .call(
+                ListResourcesRequest {},
+                true,
+                |mut client, request| async move { client.list_resources(request).await },
+            )
+            .await?;
+        Ok(response.resources)
+    }
+
+    /// Stream every change of a reservation from now on
+    pub async fn listen(
+        &self,
+    ) -> Result<impl Stream<Item = Result<ListenResponse, Error>> + Send + Unpin, Error> {
+        let stream = self
+            .call(ListenRequest {}, true, |mut client, request| async move {
+                client.listen(request).await
+            })
+            .await?;
+        Ok(stream.map(|change| change.map_err(Error::from)))
+    }
+
+    // make a call with the metadata of the client, retrying an idempotent one while it fails for
+    // a transient reason
+    async fn call<M, T, F, Fut>(&self, message: M, idempotent: bool, rpc: F) -> Result<T, Error>
+    where
+        M: Clone,
+        F: Fn(ReservationServiceClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let mut backoff = self.retry.backoff;
+        let mut retries = 0;
+        loop {
+            let request = self.request(message.clone())?;
+            match rpc(self.inner.clone(), request).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status)
+                    if idempotent
+                        && error::is_transient(&status)
+                        && retries < self.retry.max_retries =>
+                {
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                }
+                Err(status) => return Err(status.into()),
+            }
+        }
+    }
+
+    fn request<M>(&self, message: M) -> Result<Request<M>, Error> {
+        let mut request = Request::new(message);
+        if let Some(tenant) = &self.tenant {
+            request
+                .metadata_mut()
+                .insert("tenant-id", metadata(tenant, "tenant-id")?);
+        }
+        if let Some(token) = &self.token {
+            let value = metadata(&format!("Bearer {}", token), "authorization")?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        Ok(request)
+    }
+}
+
+// an invalid value can't be sent, e.g. one with a line break
+fn metadata(value: &str, field: &str) -> Result<AsciiMetadataValue, Error> {
+    value.parse().map_err(|_| Error::InvalidArgument {
+        field: Some(field.to_string()),
+        message: format!("Invalid {} metadata", field),
+    })
+}
+
+fn reservation(rsvp: Option<Reservation>) -> Result<Reservation, Error> {
+    rsvp.ok_or(Error::MissingField("reservation"))
+}
+
+fn resource_of(resource: Option<Resource>) -> Result<Resource, Error> {
+    resource.ok_or(Error::MissingField("resource"))
+}
@@ -0,0 +1,310 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+use reservation::InMemoryReservationManager;
+use reservation_client::{
+    Error, ReservationClient, ReservationQueryBuilder, ReservationStatus, ReservationUpdateType,
+    ReserveOptions, ReserveOutcome, Resource, RetryPolicy,
+};
+use reservation_service::RsvpService;
+use tokio::{sync::oneshot::Sender, task::JoinHandle};
+use tokio_stream::StreamExt;
+use tonic::transport::Endpoint;
+
+type Server = (Sender<()>, JoinHandle<Result<(), tonic::transport::Error>>);
+
+#[tokio::test]
+async fn reserve_and_confirm_should_work() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+
+    let rsvp = client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap();
+    assert_ne!(rsvp.id, 0);
+    assert_eq!(rsvp.status, ReservationStatus::Pending as i32);
+
+    let confirmed = client.confirm(rsvp.id, Some(rsvp.version)).await.unwrap();
+    assert_eq!(confirmed.status, ReservationStatus::Confirmed as i32);
+    assert_eq!(client.get(rsvp.id).await.unwrap(), confirmed);
+
+    let rescheduled = client
+        .reschedule(rsvp.id, hours(13, 14), None)
+        .await
+        .unwrap();
+    assert_eq!(rescheduled.id, rsvp.id);
+
+    let history = client.get_history(rsvp.id).await.unwrap();
+    assert_eq!(history.len(), 3);
+
+    drop(client);
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn failed_calls_should_be_decoded() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+
+    let rsvp = client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap();
+
+    let err = client
+        .reserve("bob", "room", hours(11, 13))
+        .await
+        .unwrap_err();
+    let Error::Conflict { conflicts, .. } = err else {
+        panic!("expected a conflict, got {:?}", err);
+    };
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].existing_id, Some(rsvp.id));
+    assert_eq!(conflicts[0].new.start, time(11));
+
+    let err = client
+        .reserve("bob", "room", hours(14, 13))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidArgument { field: Some(field), .. } if field == "timespan"),
+        "{:?}",
+        err
+    );
+
+    let err = client
+        .confirm(rsvp.id, Some(rsvp.version + 1))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, Error::VersionMismatch { expected, actual } if expected == rsvp.version + 1 && actual == rsvp.version)
+    );
+
+    assert!(matches!(
+        client.get(rsvp.id + 1).await,
+        Err(Error::NotFound)
+    ));
+
+    client
+        .create_resource(Resource::new("hall", "Hall"))
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.create_resource(Resource::new("hall", "Hall")).await,
+        Err(Error::ResourceAlreadyExists)
+    ));
+
+    drop(client);
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn reserve_with_options_should_waitlist() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+
+    client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap();
+
+    let rsvp = reservation_client::Reservation::new_pendding(
+        "bob",
+        "room",
+        time(10).fixed_offset(),
+        time(12).fixed_offset(),
+        "after alice",
+    );
+    let options = ReserveOptions {
+        waitlist: true,
+        ..Default::default()
+    };
+    let outcome = client.reserve_with(rsvp, options).await.unwrap();
+    assert!(matches!(outcome, ReserveOutcome::Waitlisted(_)));
+
+    drop(client);
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn changes_with_idempotency_key_should_be_made_once() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+    let rsvp = client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap();
+
+    let once = client.clone().with_idempotency_key("cancel");
+    let cancelled = once.cancel(rsvp.id, None, None).await.unwrap();
+    assert_eq!(once.cancel(rsvp.id, None, None).await.unwrap(), cancelled);
+    let err = client.cancel(rsvp.id, None, None).await.unwrap_err();
+    assert!(matches!(err, Error::NotFound), "{:?}", err);
+
+    drop((client, once));
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn query_should_stream_reservations() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+
+    client
+        .reserve("alice", "room", hours(10, 11))
+        .await
+        .unwrap();
+    client
+        .reserve("alice", "room", hours(11, 12))
+        .await
+        .unwrap();
+    client.reserve("bob", "room", hours(12, 13)).await.unwrap();
+
+    let query = ReservationQueryBuilder::default()
+        .user_id("alice")
+        .start(abi::utils::datetime_to_timestamp(time(0)))
+        .end(abi::utils::datetime_to_timestamp(time(23)))
+        .build()
+        .unwrap();
+    let rsvps: Vec<_> = client
+        .query(query)
+        .await
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .await
+        .unwrap();
+    assert_eq!(rsvps.len(), 2);
+    assert!(rsvps.iter().all(|rsvp| rsvp.user_id == "alice"));
+
+    let slots = client
+        .find_availability("room", hours(9, 14), TimeDelta::minutes(30))
+        .await
+        .unwrap();
+    assert_eq!(slots, vec![hours(9, 10), hours(13, 14)]);
+
+    drop(client);
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn listen_should_stream_changes() {
+    let (addr, server) = start().await;
+    let client = connect(addr).await;
+
+    let mut changes = client.listen().await.unwrap();
+    let rsvp = client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap();
+    client.cancel(rsvp.id, None, None).await.unwrap();
+
+    let change = next(&mut changes).await;
+    assert_eq!(change.op, ReservationUpdateType::Create as i32);
+    assert_eq!(change.reservation.unwrap().id, rsvp.id);
+    let change = next(&mut changes).await;
+    assert_eq!(change.op, ReservationUpdateType::Update as i32);
+
+    drop((client, changes));
+    stop(server).await;
+}
+
+#[tokio::test]
+async fn unavailable_service_should_be_retried() {
+    let addr = free_addr();
+    let channel = Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_lazy();
+
+    let client = ReservationClient::new(channel.clone()).with_retry(RetryPolicy::none());
+    let err = client.get(1).await.unwrap_err();
+    assert!(err.is_transient(), "{:?}", err);
+    drop(client);
+
+    let client = ReservationClient::new(channel).with_retry(RetryPolicy {
+        max_retries: 10,
+        backoff: Duration::from_millis(100),
+    });
+    let starting = tokio::spawn(async move { serve(addr).await });
+    let err = client.get(1).await.unwrap_err();
+    assert!(matches!(err, Error::NotFound), "{:?}", err);
+
+    drop(client);
+    stop(starting.await.unwrap()).await;
+}
+
+#[tokio::test]
+async fn changes_without_idempotency_key_should_not_be_retried() {
+    let addr = free_addr();
+    let channel = Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect_lazy();
+    let client = ReservationClient::new(channel).with_retry(RetryPolicy {
+        max_retries: 10,
+        backoff: Duration::from_secs(10),
+    });
+
+    let started = Instant::now();
+    let err = client
+        .reserve("alice", "room", hours(10, 12))
+        .await
+        .unwrap_err();
+    assert!(err.is_transient(), "{:?}", err);
+    let err = client.cancel(1, None, None).await.unwrap_err();
+    assert!(err.is_transient(), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+// a server with a `room` to reserve
+async fn start() -> (SocketAddr, Server) {
+    let addr = free_addr();
+    let server = serve(addr).await;
+    connect(addr)
+        .await
+        .create_resource(Resource::new("room", "Room"))
+        .await
+        .unwrap();
+    (addr, server)
+}
+
+async fn serve(addr: SocketAddr) -> Server {
+    let service = RsvpService::new(InMemoryReservationManager::new());
+    reservation_service::run(addr, service).await.unwrap()
+}
+
+// the server shuts down once every client connected to it is dropped
+async fn stop((stop_signal_tx, handler): Server) {
+    stop_signal_tx.send(()).unwrap();
+    handler.await.unwrap().unwrap();
+}
+
+async fn connect(addr: SocketAddr) -> ReservationClient {
+    ReservationClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+async fn next<T>(stream: &mut (impl tokio_stream::Stream<Item = Result<T, Error>> + Unpin)) -> T {
+    tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+}
+
+// a port which is free now, the test binds it shortly after
+fn free_addr() -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+fn time(hour: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2030, 1, 1, hour, 0, 0).unwrap()
+}
+
+fn hours(start: u32, end: u32) -> std::ops::Range<DateTime<Utc>> {
+    time(start)..time(end)
+}
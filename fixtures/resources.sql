@@ -0,0 +1,10 @@
+INSERT INTO rsvp.resources(id, name)
+    VALUES ('resource', 'resource'),
+('resource1', 'resource 1'),
+('room', 'room'),
+('room1', 'room 1'),
+('projector', 'projector'),
+('parking', 'parking');
+
+INSERT INTO rsvp.resources(id, name, capacity)
+    VALUES ('lot', 'parking lot', 3);
@@ -0,0 +1,31 @@
+-- keep a snapshot of the reservation in every change, so a listener can still
+-- get the last known reservation after it was deleted
+ALTER TABLE rsvp.reservation_changes
+    ADD COLUMN reservation jsonb;
+
+-- trigger for add/update/delete a reservation
+CREATE OR REPLACE FUNCTION rsvp.reservations_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- update reservation_changes
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(NEW.id, 'create', to_jsonb(NEW));
+    ELSIF TG_OP = 'UPDATE' THEN
+        -- if status changed, update reservation_changes
+        IF OLD.status <> NEW.status THEN
+            INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+                VALUES(NEW.id, 'update', to_jsonb(NEW));
+        END IF;
+    ELSIF TG_OP = 'DELETE' THEN
+        -- update reservation_changes with the deleted row as tombstone
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(OLD.id, 'delete', to_jsonb(OLD));
+    END IF;
+    -- notify a channel called reservation_update
+    NOTIFY reservation_update;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,2 @@
+-- postgres can not drop a value from an enum type,
+-- 'cancelled' goes away together with rsvp.reservation_status
@@ -0,0 +1,3 @@
+-- a new enum value can not be used in the same transaction it is added in,
+-- so it gets its own migration
+ALTER TYPE rsvp.reservation_status ADD VALUE 'cancelled';
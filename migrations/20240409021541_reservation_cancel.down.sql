@@ -0,0 +1,19 @@
+-- a cancelled reservation may overlap a reservation which holds its timespan, so it can't
+-- get another status, it's kept and the migration fails until it's dealt with
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM rsvp.reservations WHERE status = 'cancelled') THEN
+        RAISE EXCEPTION 'cancelled reservations exist, they must be deleted or archived first';
+    END IF;
+END
+$$;
+
+ALTER TABLE rsvp.reservations
+    DROP CONSTRAINT reservations_conflict;
+
+ALTER TABLE rsvp.reservations
+    ADD CONSTRAINT reservations_conflict
+    EXCLUDE USING gist(resource_id WITH =, timespan WITH &&);
+
+ALTER TABLE rsvp.reservations
+    DROP COLUMN cancel_reason;
@@ -0,0 +1,11 @@
+ALTER TABLE rsvp.reservations
+    ADD COLUMN cancel_reason text;
+
+-- a cancelled reservation is kept for history, but it no longer holds the timespan
+ALTER TABLE rsvp.reservations
+    DROP CONSTRAINT reservations_conflict;
+
+ALTER TABLE rsvp.reservations
+    ADD CONSTRAINT reservations_conflict
+    EXCLUDE USING gist(resource_id WITH =, timespan WITH &&)
+    WHERE (status <> 'cancelled');
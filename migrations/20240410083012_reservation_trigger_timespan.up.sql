@@ -0,0 +1,26 @@
+-- a rescheduled reservation is also an update for the listeners
+CREATE OR REPLACE FUNCTION rsvp.reservations_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- update reservation_changes
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(NEW.id, 'create', to_jsonb(NEW));
+    ELSIF TG_OP = 'UPDATE' THEN
+        -- if status or timespan changed, update reservation_changes
+        IF OLD.status <> NEW.status OR OLD.timespan <> NEW.timespan THEN
+            INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+                VALUES(NEW.id, 'update', to_jsonb(NEW));
+        END IF;
+    ELSIF TG_OP = 'DELETE' THEN
+        -- update reservation_changes with the deleted row as tombstone
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(OLD.id, 'delete', to_jsonb(OLD));
+    END IF;
+    -- notify a channel called reservation_update
+    NOTIFY reservation_update;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
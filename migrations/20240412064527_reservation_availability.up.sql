@@ -0,0 +1,24 @@
+-- find the free timespans of a resource within during, which are at least as long as duration
+-- cancelled reservations don't hold their timespan, so they are not taken into account
+CREATE OR REPLACE FUNCTION rsvp.availability(rid text, during tstzrange, duration interval DEFAULT '0'::interval)
+    RETURNS TABLE(
+        timespan tstzrange
+    )
+    AS $$
+    SELECT
+        free
+    FROM
+        unnest(tstzmultirange(during) - COALESCE((
+            SELECT
+                range_agg(r.timespan)
+            FROM rsvp.reservations r
+            WHERE
+                r.resource_id = rid
+                AND r.timespan && during
+                AND r.status <> 'cancelled'), '{}'::tstzmultirange)) AS free
+    WHERE
+        upper(free) - lower(free) >= duration
+    ORDER BY
+        lower(free);
+$$
+LANGUAGE sql;
@@ -0,0 +1,4 @@
+ALTER TABLE rsvp.reservations
+    DROP COLUMN series_id;
+
+DROP TABLE rsvp.reservation_series;
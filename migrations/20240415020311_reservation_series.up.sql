@@ -0,0 +1,17 @@
+-- a recurring reservation, every occurrence is stored as a reservation linked to it
+CREATE TABLE rsvp.reservation_series(
+    id bigserial NOT NULL,
+    user_id varchar(64) NOT NULL,
+    resource_id varchar(64) NOT NULL,
+    -- timespan of the first occurrence
+    timespan tstzrange NOT NULL,
+    rrule text NOT NULL,
+    note text,
+    CONSTRAINT reservation_series_pkey PRIMARY KEY (id)
+);
+
+ALTER TABLE rsvp.reservations
+    ADD COLUMN series_id bigint
+    CONSTRAINT reservations_series_id_fkey REFERENCES rsvp.reservation_series(id) ON DELETE SET NULL;
+
+CREATE INDEX reservations_series_id_idx ON rsvp.reservations(series_id);
@@ -0,0 +1,8 @@
+-- a pending reservation with expires_at is a hold,
+-- it's released if it's not confirmed before expires_at
+ALTER TABLE rsvp.reservations
+    ADD COLUMN expires_at timestamptz;
+
+CREATE INDEX reservations_expires_at_idx ON rsvp.reservations(expires_at)
+WHERE
+    status = 'pending';
@@ -0,0 +1,39 @@
+-- registry of the resources which can be reserved
+CREATE TABLE rsvp.resources(
+    id varchar(64) NOT NULL,
+    name text NOT NULL DEFAULT '',
+    location text NOT NULL DEFAULT '',
+    capabilities text[] NOT NULL DEFAULT '{}',
+    disabled boolean NOT NULL DEFAULT FALSE,
+    CONSTRAINT resources_pkey PRIMARY KEY (id)
+);
+
+-- register the resources which are already reserved
+INSERT INTO rsvp.resources(id, name)
+SELECT DISTINCT
+    resource_id,
+    resource_id
+FROM
+    rsvp.reservations;
+
+ALTER TABLE rsvp.reservations
+    ADD CONSTRAINT reservations_resource_id_fkey FOREIGN KEY (resource_id) REFERENCES rsvp.resources(id);
+
+-- only an enabled resource can be reserved
+CREATE OR REPLACE FUNCTION rsvp.reservations_resource_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM rsvp.resources WHERE id = NEW.resource_id AND NOT disabled) THEN
+        RAISE EXCEPTION 'resource % is unknown or disabled', NEW.resource_id
+            USING ERRCODE = 'RS001', DETAIL = NEW.resource_id;
+    END IF;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER reservations_resource_trigger
+    BEFORE INSERT OR UPDATE OF resource_id ON rsvp.reservations
+    FOR EACH ROW
+    EXECUTE PROCEDURE rsvp.reservations_resource_trigger();
@@ -0,0 +1,42 @@
+CREATE OR REPLACE FUNCTION rsvp.availability(rid text, during tstzrange, duration interval DEFAULT '0'::interval)
+    RETURNS TABLE(
+        timespan tstzrange
+    )
+    AS $$
+    SELECT
+        free
+    FROM
+        unnest(tstzmultirange(during) - COALESCE((
+            SELECT
+                range_agg(r.timespan)
+            FROM rsvp.reservations r
+            WHERE
+                r.resource_id = rid
+                AND r.timespan && during
+                AND r.status <> 'cancelled'), '{}'::tstzmultirange)) AS free
+    WHERE
+        upper(free) - lower(free) >= duration
+    ORDER BY
+        lower(free);
+$$
+LANGUAGE sql;
+
+DROP TRIGGER reservations_capacity_trigger ON rsvp.reservations;
+
+DROP FUNCTION rsvp.reservations_capacity_trigger;
+
+DROP FUNCTION rsvp.peak_seats;
+
+DROP INDEX rsvp.reservations_timespan_idx;
+
+-- fails if a resource is reserved by overlapping reservations
+ALTER TABLE rsvp.reservations
+    ADD CONSTRAINT reservations_conflict
+    EXCLUDE USING gist(resource_id WITH =, timespan WITH &&)
+    WHERE (status <> 'cancelled');
+
+ALTER TABLE rsvp.reservations
+    DROP COLUMN seats;
+
+ALTER TABLE rsvp.resources
+    DROP COLUMN capacity;
@@ -0,0 +1,167 @@
+-- a resource can be reserved by several reservations at the same time, as long as
+-- the seats they take don't exceed its capacity
+ALTER TABLE rsvp.resources
+    ADD COLUMN capacity integer NOT NULL DEFAULT 1 CHECK (capacity > 0);
+
+ALTER TABLE rsvp.reservations
+    ADD COLUMN seats integer NOT NULL DEFAULT 1 CHECK (seats > 0);
+
+-- the exclusion constraint only allows one reservation at a time,
+-- capacity is enforced by reservations_capacity_trigger instead
+ALTER TABLE rsvp.reservations
+    DROP CONSTRAINT reservations_conflict;
+
+CREATE INDEX reservations_timespan_idx ON rsvp.reservations USING gist(resource_id, timespan)
+WHERE (status <> 'cancelled');
+
+-- the max number of seats taken at the same time by the reservations of rid within during
+CREATE OR REPLACE FUNCTION rsvp.peak_seats(rid text, during tstzrange, exclude_id bigint DEFAULT NULL)
+    RETURNS integer
+    AS $$
+    WITH booked AS (
+        SELECT
+            r.timespan * during AS timespan,
+            r.seats
+        FROM
+            rsvp.reservations r
+        WHERE
+            r.resource_id = rid
+            AND r.timespan && during
+            AND r.status <> 'cancelled'
+            AND r.id IS DISTINCT FROM exclude_id
+)
+    -- the usage only goes up at the start of a reservation
+    SELECT
+        COALESCE(max(used), 0)::integer
+    FROM (
+        SELECT
+            sum(b.seats) AS used
+        FROM (
+            SELECT DISTINCT
+                lower(timespan) AS t
+            FROM
+                booked) p
+            JOIN booked b ON b.timespan @> p.t
+        GROUP BY
+            p.t) u;
+$$
+LANGUAGE sql;
+
+-- the seats of a reservation must fit into the remaining capacity of the resource,
+-- otherwise an exclusion_violation is raised, with the same detail as the exclusion
+-- constraint had, followed by the remaining capacity
+CREATE OR REPLACE FUNCTION rsvp.reservations_capacity_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _capacity integer;
+    _remaining integer;
+    _old rsvp.reservations;
+BEGIN
+    IF NEW.status = 'cancelled' THEN
+        RETURN NEW;
+    END IF;
+
+    -- lock the resource, so concurrent reservations of it are checked one by one
+    SELECT capacity INTO _capacity FROM rsvp.resources WHERE id = NEW.resource_id FOR UPDATE;
+    IF NOT FOUND THEN
+        -- reported by reservations_resource_trigger
+        RETURN NEW;
+    END IF;
+
+    _remaining := greatest(_capacity - rsvp.peak_seats(NEW.resource_id, NEW.timespan, NEW.id), 0);
+    IF NEW.seats > _remaining THEN
+        SELECT * INTO _old FROM rsvp.reservations r
+        WHERE r.resource_id = NEW.resource_id
+            AND r.timespan && NEW.timespan
+            AND r.status <> 'cancelled'
+            AND r.id <> NEW.id
+        ORDER BY lower(r.timespan), r.id
+        LIMIT 1;
+
+        RAISE EXCEPTION 'conflicting key value violates exclusion constraint "reservations_conflict"'
+            USING ERRCODE = 'exclusion_violation',
+                SCHEMA = 'rsvp',
+                TABLE = 'reservations',
+                CONSTRAINT = 'reservations_conflict',
+                DETAIL = format('Key (resource_id, timespan)=(%s, %s) conflicts with existing key (resource_id, timespan)=(%s, %s), remaining capacity: %s.',
+                    NEW.resource_id, NEW.timespan, _old.resource_id, _old.timespan, _remaining);
+    END IF;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER reservations_capacity_trigger
+    BEFORE INSERT OR UPDATE OF resource_id, timespan, seats ON rsvp.reservations
+    FOR EACH ROW
+    EXECUTE PROCEDURE rsvp.reservations_capacity_trigger();
+
+-- a timespan is only unavailable if the resource is fully booked
+CREATE OR REPLACE FUNCTION rsvp.availability(rid text, during tstzrange, duration interval DEFAULT '0'::interval)
+    RETURNS TABLE(
+        timespan tstzrange
+    )
+    AS $$
+    WITH booked AS (
+        SELECT
+            r.timespan * during AS timespan,
+            r.seats
+        FROM
+            rsvp.reservations r
+        WHERE
+            r.resource_id = rid
+            AND r.timespan && during
+            AND r.status <> 'cancelled'
+),
+bounds AS (
+    SELECT
+        lower(b.timespan) AS t
+    FROM
+        booked b
+    UNION
+    SELECT
+        upper(b.timespan)
+    FROM
+        booked b
+),
+pieces AS (
+    SELECT
+        tstzrange(t, next_t) AS piece
+    FROM (
+        SELECT
+            t,
+            lead(t) OVER (ORDER BY t) AS next_t
+        FROM
+            bounds) s
+    WHERE
+        next_t IS NOT NULL
+),
+busy AS (
+    SELECT
+        p.piece
+    FROM
+        pieces p
+        JOIN booked b ON b.timespan @> lower(p.piece)
+    GROUP BY
+        p.piece
+    HAVING
+        sum(b.seats) >= COALESCE((
+            SELECT
+                capacity
+            FROM rsvp.resources
+            WHERE
+                id = rid), 1))
+SELECT
+    free
+FROM
+    unnest(tstzmultirange(during) - COALESCE((
+        SELECT
+            range_agg(piece)
+        FROM busy), '{}'::tstzmultirange)) AS free
+WHERE
+    upper(free) - lower(free) >= duration
+ORDER BY
+    lower(free);
+$$
+LANGUAGE sql;
@@ -0,0 +1,224 @@
+-- buffers keep a resource blocked before and after a reservation, e.g. for cleaning,
+-- a reservation without its own buffers takes the ones of the resource
+ALTER TABLE rsvp.resources
+    ADD COLUMN buffer_before interval NOT NULL DEFAULT '0' CHECK (buffer_before >= '0'),
+    ADD COLUMN buffer_after interval NOT NULL DEFAULT '0' CHECK (buffer_after >= '0');
+
+ALTER TABLE rsvp.reservations
+    ADD COLUMN buffer_before interval CHECK (buffer_before >= '0'),
+    ADD COLUMN buffer_after interval CHECK (buffer_after >= '0'),
+    -- timespan with the buffers, used for conflict checks
+    ADD COLUMN blocked tstzrange;
+
+UPDATE
+    rsvp.reservations
+SET
+    blocked = timespan;
+
+ALTER TABLE rsvp.reservations
+    ALTER COLUMN blocked SET NOT NULL;
+
+DROP INDEX rsvp.reservations_timespan_idx;
+
+CREATE INDEX reservations_blocked_idx ON rsvp.reservations USING gist(resource_id, blocked)
+WHERE (status <> 'cancelled');
+
+-- the buffers of the resource are taken when the reservation is made,
+-- changing them later doesn't affect existing reservations
+CREATE OR REPLACE FUNCTION rsvp.reservations_buffer_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _before interval;
+    _after interval;
+BEGIN
+    SELECT buffer_before, buffer_after INTO _before, _after FROM rsvp.resources WHERE id = NEW.resource_id;
+    NEW.blocked := tstzrange(lower(NEW.timespan) - COALESCE(NEW.buffer_before, _before, '0'),
+        upper(NEW.timespan) + COALESCE(NEW.buffer_after, _after, '0'));
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- runs before reservations_capacity_trigger, which checks the blocked timespan
+CREATE TRIGGER reservations_buffer_trigger
+    BEFORE INSERT OR UPDATE OF resource_id, timespan, buffer_before, buffer_after ON rsvp.reservations
+    FOR EACH ROW
+    EXECUTE PROCEDURE rsvp.reservations_buffer_trigger();
+
+CREATE OR REPLACE FUNCTION rsvp.peak_seats(rid text, during tstzrange, exclude_id bigint DEFAULT NULL)
+    RETURNS integer
+    AS $$
+    WITH booked AS (
+        SELECT
+            r.blocked * during AS blocked,
+            r.seats
+        FROM
+            rsvp.reservations r
+        WHERE
+            r.resource_id = rid
+            AND r.blocked && during
+            AND r.status <> 'cancelled'
+            AND r.id IS DISTINCT FROM exclude_id
+)
+    -- the usage only goes up at the start of a reservation
+    SELECT
+        COALESCE(max(used), 0)::integer
+    FROM (
+        SELECT
+            sum(b.seats) AS used
+        FROM (
+            SELECT DISTINCT
+                lower(blocked) AS t
+            FROM
+                booked) p
+            JOIN booked b ON b.blocked @> p.t
+        GROUP BY
+            p.t) u;
+$$
+LANGUAGE sql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_capacity_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _capacity integer;
+    _remaining integer;
+    _old rsvp.reservations;
+BEGIN
+    IF NEW.status = 'cancelled' THEN
+        RETURN NEW;
+    END IF;
+
+    -- lock the resource, so concurrent reservations of it are checked one by one
+    SELECT capacity INTO _capacity FROM rsvp.resources WHERE id = NEW.resource_id FOR UPDATE;
+    IF NOT FOUND THEN
+        -- reported by reservations_resource_trigger
+        RETURN NEW;
+    END IF;
+
+    _remaining := greatest(_capacity - rsvp.peak_seats(NEW.resource_id, NEW.blocked, NEW.id), 0);
+    IF NEW.seats > _remaining THEN
+        SELECT * INTO _old FROM rsvp.reservations r
+        WHERE r.resource_id = NEW.resource_id
+            AND r.blocked && NEW.blocked
+            AND r.status <> 'cancelled'
+            AND r.id <> NEW.id
+        ORDER BY lower(r.blocked), r.id
+        LIMIT 1;
+
+        RAISE EXCEPTION 'conflicting key value violates exclusion constraint "reservations_conflict"'
+            USING ERRCODE = 'exclusion_violation',
+                SCHEMA = 'rsvp',
+                TABLE = 'reservations',
+                CONSTRAINT = 'reservations_conflict',
+                DETAIL = format('Key (resource_id, timespan)=(%s, %s) conflicts with existing key (resource_id, timespan)=(%s, %s), remaining capacity: %s.',
+                    NEW.resource_id, NEW.timespan, _old.resource_id, _old.timespan, _remaining);
+    END IF;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP TRIGGER reservations_capacity_trigger ON rsvp.reservations;
+
+CREATE TRIGGER reservations_capacity_trigger
+    BEFORE INSERT OR UPDATE OF resource_id, timespan, seats, buffer_before, buffer_after ON rsvp.reservations
+    FOR EACH ROW
+    EXECUTE PROCEDURE rsvp.reservations_capacity_trigger();
+
+-- free timespans are shrunk by the buffers of the resource, so a reservation
+-- of a returned timespan doesn't conflict with its buffers
+CREATE OR REPLACE FUNCTION rsvp.availability(rid text, during tstzrange, duration interval DEFAULT '0'::interval)
+    RETURNS TABLE(
+        timespan tstzrange
+    )
+    AS $$
+    WITH resource AS (
+        SELECT
+            COALESCE(max(capacity), 1) AS capacity,
+            COALESCE(max(buffer_before), '0') AS buffer_before,
+            COALESCE(max(buffer_after), '0') AS buffer_after
+        FROM
+            rsvp.resources
+        WHERE
+            id = rid
+),
+bounded AS (
+    SELECT
+        tstzrange(lower(during) - buffer_before, upper(during) + buffer_after) AS during
+    FROM
+        resource
+),
+booked AS (
+    SELECT
+        r.blocked * w.during AS blocked,
+        r.seats
+    FROM
+        rsvp.reservations r,
+        bounded w
+    WHERE
+        r.resource_id = rid
+        AND r.blocked && w.during
+        AND r.status <> 'cancelled'
+),
+bounds AS (
+    SELECT
+        lower(b.blocked) AS t
+    FROM
+        booked b
+    UNION
+    SELECT
+        upper(b.blocked)
+    FROM
+        booked b
+),
+pieces AS (
+    SELECT
+        tstzrange(t, next_t) AS piece
+    FROM (
+        SELECT
+            t,
+            lead(t) OVER (ORDER BY t) AS next_t
+        FROM
+            bounds) s
+    WHERE
+        next_t IS NOT NULL
+),
+busy AS (
+    SELECT
+        p.piece
+    FROM
+        pieces p
+        JOIN booked b ON b.blocked @> lower(p.piece)
+    GROUP BY
+        p.piece
+    HAVING
+        sum(b.seats) >= (
+            SELECT
+                capacity
+            FROM
+                resource)),
+free AS (
+    SELECT
+        lower(f) + r.buffer_before AS start,
+        upper(f) - r.buffer_after AS "end"
+    FROM
+        bounded w,
+        resource r,
+        unnest(tstzmultirange(w.during) - COALESCE((
+            SELECT
+                range_agg(piece)
+            FROM busy), '{}'::tstzmultirange)) AS f
+)
+SELECT
+    tstzrange(start, "end")
+FROM
+    free
+WHERE
+    "end" > start
+    AND "end" - start >= duration
+ORDER BY
+    start;
+$$
+LANGUAGE sql;
@@ -0,0 +1,2 @@
+-- postgres can not drop a value from an enum type,
+-- 'promote' goes away together with rsvp.reservation_update_type
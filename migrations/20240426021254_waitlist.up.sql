@@ -0,0 +1,117 @@
+-- reservation requests waiting for their timespan to become free
+CREATE TABLE rsvp.waitlist(
+    id bigserial NOT NULL,
+    user_id varchar(64) NOT NULL,
+    resource_id varchar(64) NOT NULL,
+    timespan tstzrange NOT NULL,
+    note text,
+    seats integer NOT NULL DEFAULT 1,
+    buffer_before interval,
+    buffer_after interval,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT waitlist_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX waitlist_resource_id_idx ON rsvp.waitlist(resource_id);
+
+-- the waitlist entry a reservation was promoted from
+ALTER TABLE rsvp.reservations
+    ADD COLUMN waitlist_id bigint;
+
+-- a promoted reservation is announced as 'promote' instead of 'create'
+CREATE OR REPLACE FUNCTION rsvp.reservations_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- update reservation_changes
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(NEW.id, CASE WHEN NEW.waitlist_id IS NULL THEN
+                    'create'
+                ELSE
+                    'promote'
+                END::rsvp.reservation_update_type, to_jsonb(NEW));
+    ELSIF TG_OP = 'UPDATE' THEN
+        -- if status, resource or timespan changed, update reservation_changes
+        IF OLD.status <> NEW.status OR OLD.resource_id <> NEW.resource_id
+            OR OLD.timespan <> NEW.timespan THEN
+            INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+                VALUES(NEW.id, 'update', to_jsonb(NEW));
+        END IF;
+    ELSIF TG_OP = 'DELETE' THEN
+        -- update reservation_changes with the deleted row as tombstone
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation)
+            VALUES(OLD.id, 'delete', to_jsonb(OLD));
+    END IF;
+    -- notify a channel called reservation_update
+    NOTIFY reservation_update;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- promote every waiting entry of a resource which fits now, oldest first,
+-- an entry which still conflicts keeps waiting
+CREATE OR REPLACE FUNCTION rsvp.promote_waitlist(rid text)
+    RETURNS void
+    AS $$
+DECLARE
+    _entry rsvp.waitlist;
+BEGIN
+    -- a reservation which is put on the waitlist holds this lock too,
+    -- so it's either promoted here or it's reserved right away
+    PERFORM
+        1
+    FROM
+        rsvp.resources
+    WHERE
+        id = rid
+    FOR UPDATE;
+
+    FOR _entry IN
+    SELECT
+        *
+    FROM
+        rsvp.waitlist
+    WHERE
+        resource_id = rid
+        AND upper(timespan) > now()
+    ORDER BY
+        id LOOP
+            BEGIN
+                INSERT INTO rsvp.reservations(user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, waitlist_id)
+                    VALUES (_entry.user_id, _entry.resource_id, _entry.timespan, _entry.note, _entry.seats, _entry.buffer_before, _entry.buffer_after, _entry.id);
+                DELETE FROM rsvp.waitlist
+                WHERE id = _entry.id;
+            EXCEPTION
+                WHEN exclusion_violation OR SQLSTATE 'RS001' THEN
+                    NULL;
+            END;
+        END LOOP;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_waitlist_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    PERFORM
+        rsvp.promote_waitlist(OLD.resource_id);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- a cancelled, expired or deleted reservation frees its timespan
+CREATE TRIGGER reservations_waitlist_cancel_trigger
+    AFTER UPDATE OF status ON rsvp.reservations
+    FOR EACH ROW
+    WHEN (OLD.status <> 'cancelled' AND NEW.status = 'cancelled')
+    EXECUTE PROCEDURE rsvp.reservations_waitlist_trigger();
+
+CREATE TRIGGER reservations_waitlist_delete_trigger
+    AFTER DELETE ON rsvp.reservations
+    FOR EACH ROW
+    WHEN (OLD.status <> 'cancelled')
+    EXECUTE PROCEDURE rsvp.reservations_waitlist_trigger();
@@ -0,0 +1,19 @@
+-- every update of a reservation increments its version, so a client can tell
+-- whether the reservation changed since it read it
+ALTER TABLE rsvp.reservations
+    ADD COLUMN version bigint NOT NULL DEFAULT 1;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_version_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    NEW.version := OLD.version + 1;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER reservations_version_trigger
+    BEFORE UPDATE ON rsvp.reservations
+    FOR EACH ROW
+    EXECUTE PROCEDURE rsvp.reservations_version_trigger();
@@ -0,0 +1,12 @@
+-- results of reserve requests sent with an idempotency key, a retry with the
+-- same key gets the stored result instead of reserving again
+CREATE TABLE rsvp.idempotency_keys(
+    key varchar(255) NOT NULL,
+    -- snapshot of the reservation, NULL if the request was put on the waitlist
+    reservation jsonb,
+    waitlist_id bigint,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT idempotency_keys_pkey PRIMARY KEY (key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON rsvp.idempotency_keys(created_at);
@@ -0,0 +1,405 @@
+-- every row belongs to a tenant, tenants can't see or collide with each other's data,
+-- the rows made before tenants existed belong to the default one
+ALTER TABLE rsvp.reservations
+    DROP CONSTRAINT reservations_resource_id_fkey;
+
+ALTER TABLE rsvp.resources
+    ADD COLUMN tenant_id varchar(64) NOT NULL DEFAULT 'default',
+    DROP CONSTRAINT resources_pkey,
+    ADD CONSTRAINT resources_pkey PRIMARY KEY (tenant_id, id);
+
+ALTER TABLE rsvp.reservations
+    ADD COLUMN tenant_id varchar(64) NOT NULL DEFAULT 'default',
+    ADD CONSTRAINT reservations_resource_id_fkey FOREIGN KEY (tenant_id, resource_id) REFERENCES rsvp.resources(tenant_id, id);
+
+DROP INDEX rsvp.reservations_blocked_idx;
+
+CREATE INDEX reservations_blocked_idx ON rsvp.reservations USING gist(tenant_id, resource_id, blocked)
+WHERE (status <> 'cancelled');
+
+DROP INDEX rsvp.reservations_user_id_idx;
+
+CREATE INDEX reservations_user_id_idx ON rsvp.reservations(tenant_id, user_id);
+
+ALTER TABLE rsvp.reservation_series
+    ADD COLUMN tenant_id varchar(64) NOT NULL DEFAULT 'default';
+
+ALTER TABLE rsvp.waitlist
+    ADD COLUMN tenant_id varchar(64) NOT NULL DEFAULT 'default';
+
+DROP INDEX rsvp.waitlist_resource_id_idx;
+
+CREATE INDEX waitlist_resource_id_idx ON rsvp.waitlist(tenant_id, resource_id);
+
+ALTER TABLE rsvp.idempotency_keys
+    ADD COLUMN tenant_id varchar(64) NOT NULL DEFAULT 'default',
+    DROP CONSTRAINT idempotency_keys_pkey,
+    ADD CONSTRAINT idempotency_keys_pkey PRIMARY KEY (tenant_id, key);
+
+-- the changes are told apart by the tenant of their snapshots
+UPDATE
+    rsvp.reservation_changes
+SET
+    reservation = reservation || '{"tenant_id": "default"}',
+    old_reservation = old_reservation || '{"tenant_id": "default"}';
+
+UPDATE
+    rsvp.idempotency_keys
+SET
+    reservation = reservation || '{"tenant_id": "default"}';
+
+-- only an enabled resource of the tenant can be reserved
+CREATE OR REPLACE FUNCTION rsvp.reservations_resource_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM rsvp.resources WHERE tenant_id = NEW.tenant_id AND id = NEW.resource_id AND NOT disabled) THEN
+        RAISE EXCEPTION 'resource % is unknown or disabled', NEW.resource_id
+            USING ERRCODE = 'RS001', DETAIL = NEW.resource_id;
+    END IF;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_buffer_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _before interval;
+    _after interval;
+BEGIN
+    SELECT buffer_before, buffer_after INTO _before, _after FROM rsvp.resources
+    WHERE tenant_id = NEW.tenant_id AND id = NEW.resource_id;
+    NEW.blocked := tstzrange(lower(NEW.timespan) - COALESCE(NEW.buffer_before, _before, '0'),
+        upper(NEW.timespan) + COALESCE(NEW.buffer_after, _after, '0'));
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION rsvp.peak_seats(text, tstzrange, bigint);
+
+CREATE OR REPLACE FUNCTION rsvp.peak_seats(tid text, rid text, during tstzrange, exclude_id bigint DEFAULT NULL)
+    RETURNS integer
+    AS $$
+    WITH booked AS (
+        SELECT
+            r.blocked * during AS blocked,
+            r.seats
+        FROM
+            rsvp.reservations r
+        WHERE
+            r.tenant_id = tid
+            AND r.resource_id = rid
+            AND r.blocked && during
+            AND r.status <> 'cancelled'
+            AND r.id IS DISTINCT FROM exclude_id
+)
+    -- the usage only goes up at the start of a reservation
+    SELECT
+        COALESCE(max(used), 0)::integer
+    FROM (
+        SELECT
+            sum(b.seats) AS used
+        FROM (
+            SELECT DISTINCT
+                lower(blocked) AS t
+            FROM
+                booked) p
+            JOIN booked b ON b.blocked @> p.t
+        GROUP BY
+            p.t) u;
+$$
+LANGUAGE sql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_capacity_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _capacity integer;
+    _remaining integer;
+    _old rsvp.reservations;
+BEGIN
+    IF NEW.status = 'cancelled' THEN
+        RETURN NEW;
+    END IF;
+
+    -- lock the resource, so concurrent reservations of it are checked one by one
+    SELECT capacity INTO _capacity FROM rsvp.resources
+    WHERE tenant_id = NEW.tenant_id AND id = NEW.resource_id FOR UPDATE;
+    IF NOT FOUND THEN
+        -- reported by reservations_resource_trigger
+        RETURN NEW;
+    END IF;
+
+    _remaining := greatest(_capacity - rsvp.peak_seats(NEW.tenant_id, NEW.resource_id, NEW.blocked, NEW.id), 0);
+    IF NEW.seats > _remaining THEN
+        SELECT * INTO _old FROM rsvp.reservations r
+        WHERE r.tenant_id = NEW.tenant_id
+            AND r.resource_id = NEW.resource_id
+            AND r.blocked && NEW.blocked
+            AND r.status <> 'cancelled'
+            AND r.id <> NEW.id
+        ORDER BY lower(r.blocked), r.id
+        LIMIT 1;
+
+        RAISE EXCEPTION 'conflicting key value violates exclusion constraint "reservations_conflict"'
+            USING ERRCODE = 'exclusion_violation',
+                SCHEMA = 'rsvp',
+                TABLE = 'reservations',
+                CONSTRAINT = 'reservations_conflict',
+                DETAIL = format('Key (resource_id, timespan)=(%s, %s) conflicts with existing key (resource_id, timespan)=(%s, %s), remaining capacity: %s.',
+                    NEW.resource_id, NEW.timespan, _old.resource_id, _old.timespan, _remaining);
+    END IF;
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION rsvp.availability(text, tstzrange, interval);
+
+CREATE OR REPLACE FUNCTION rsvp.availability(tid text, rid text, during tstzrange, duration interval DEFAULT '0'::interval)
+    RETURNS TABLE(
+        timespan tstzrange
+    )
+    AS $$
+    WITH resource AS (
+        SELECT
+            COALESCE(max(capacity), 1) AS capacity,
+            COALESCE(max(buffer_before), '0') AS buffer_before,
+            COALESCE(max(buffer_after), '0') AS buffer_after
+        FROM
+            rsvp.resources
+        WHERE
+            tenant_id = tid
+            AND id = rid
+),
+bounded AS (
+    SELECT
+        tstzrange(lower(during) - buffer_before, upper(during) + buffer_after) AS during
+    FROM
+        resource
+),
+booked AS (
+    SELECT
+        r.blocked * w.during AS blocked,
+        r.seats
+    FROM
+        rsvp.reservations r,
+        bounded w
+    WHERE
+        r.tenant_id = tid
+        AND r.resource_id = rid
+        AND r.blocked && w.during
+        AND r.status <> 'cancelled'
+),
+bounds AS (
+    SELECT
+        lower(b.blocked) AS t
+    FROM
+        booked b
+    UNION
+    SELECT
+        upper(b.blocked)
+    FROM
+        booked b
+),
+pieces AS (
+    SELECT
+        tstzrange(t, next_t) AS piece
+    FROM (
+        SELECT
+            t,
+            lead(t) OVER (ORDER BY t) AS next_t
+        FROM
+            bounds) s
+    WHERE
+        next_t IS NOT NULL
+),
+busy AS (
+    SELECT
+        p.piece
+    FROM
+        pieces p
+        JOIN booked b ON b.blocked @> lower(p.piece)
+    GROUP BY
+        p.piece
+    HAVING
+        sum(b.seats) >= (
+            SELECT
+                capacity
+            FROM
+                resource)),
+free AS (
+    SELECT
+        lower(f) + r.buffer_before AS start,
+        upper(f) - r.buffer_after AS "end"
+    FROM
+        bounded w,
+        resource r,
+        unnest(tstzmultirange(w.during) - COALESCE((
+            SELECT
+                range_agg(piece)
+            FROM busy), '{}'::tstzmultirange)) AS f
+)
+SELECT
+    tstzrange(start, "end")
+FROM
+    free
+WHERE
+    "end" > start
+    AND "end" - start >= duration
+ORDER BY
+    start;
+$$
+LANGUAGE sql;
+
+DROP FUNCTION rsvp.promote_waitlist(text);
+
+CREATE OR REPLACE FUNCTION rsvp.promote_waitlist(tid text, rid text)
+    RETURNS void
+    AS $$
+DECLARE
+    _entry rsvp.waitlist;
+BEGIN
+    -- a reservation which is put on the waitlist holds this lock too,
+    -- so it's either promoted here or it's reserved right away
+    PERFORM
+        1
+    FROM
+        rsvp.resources
+    WHERE
+        tenant_id = tid
+        AND id = rid
+    FOR UPDATE;
+
+    FOR _entry IN
+    SELECT
+        *
+    FROM
+        rsvp.waitlist
+    WHERE
+        tenant_id = tid
+        AND resource_id = rid
+        AND upper(timespan) > now()
+    ORDER BY
+        id LOOP
+            BEGIN
+                INSERT INTO rsvp.reservations(tenant_id, user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, waitlist_id)
+                    VALUES (_entry.tenant_id, _entry.user_id, _entry.resource_id, _entry.timespan, _entry.note, _entry.seats, _entry.buffer_before, _entry.buffer_after, _entry.id);
+                DELETE FROM rsvp.waitlist
+                WHERE id = _entry.id;
+            EXCEPTION
+                WHEN exclusion_violation OR SQLSTATE 'RS001' THEN
+                    NULL;
+            END;
+        END LOOP;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_waitlist_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    PERFORM
+        rsvp.promote_waitlist(OLD.tenant_id, OLD.resource_id);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION rsvp.query(text, text, tstzrange, rsvp.reservation_status, integer, bool, integer);
+
+CREATE OR REPLACE FUNCTION rsvp.query(tid text, uid text, rid text, during tstzrange, status rsvp.reservation_status DEFAULT 'unknown', page integer DEFAULT 1, is_desc bool DEFAULT FALSE, page_size integer DEFAULT 10)
+    RETURNS TABLE(
+        LIKE rsvp.reservations
+    )
+    AS $$
+DECLARE
+    _sql text;
+BEGIN
+    IF page < 1 THEN
+        page := 1;
+    END IF;
+
+    IF page_size < 1 OR page_size > 500 THEN
+        page_size := 10;
+    END IF;
+    -- format the query
+    _sql := format('SELECT * FROM rsvp.reservations WHERE tenant_id = %L AND %L @> timespan AND %s AND %s ORDER BY lower(timespan) %s LIMIT   %s  OFFSET  %s', tid, during, CASE WHEN uid IS NULL
+            AND rid IS NULL THEN
+            'TRUE'
+        WHEN uid IS NULL THEN
+            'resource_id = ' || quote_literal(rid)
+        WHEN rid IS NULL THEN
+            'user_id = ' || quote_literal(uid)
+        ELSE
+            'user_id = ' || quote_literal(uid) || ' AND resource_id = ' || quote_literal(rid)
+        END, CASE WHEN status = 'unknown' THEN
+            'TRUE'
+        ELSE
+            'status = ' || quote_literal(status)
+        END, CASE WHEN is_desc THEN
+            'DESC'
+        ELSE
+            'ASC'
+        END, page_size,(page - 1) * page_size);
+
+    RETURN QUERY EXECUTE _sql;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION rsvp.filter(text, text, rsvp.reservation_status, bigint, bool, integer);
+
+CREATE OR REPLACE FUNCTION rsvp.filter(tid text, uid text, rid text, status rsvp.reservation_status DEFAULT 'unknown', CURSOR bigint DEFAULT 0, is_desc bool DEFAULT FALSE, page_size integer DEFAULT 10)
+    RETURNS TABLE(
+        LIKE rsvp.reservations
+    )
+    AS $$
+DECLARE
+    _sql text;
+BEGIN
+    IF page_size < 1 OR page_size > 500 THEN
+        page_size := 10;
+    END IF;
+    IF CURSOR < 0 THEN
+        CURSOR := 0;
+    END IF;
+    IF CURSOR > 9223372036854775807 THEN
+        CURSOR := 9223372036854775807;
+    END IF;
+    IF CURSOR = 0 AND is_desc THEN
+        CURSOR := 9223372036854775807;
+    END IF;
+    -- format the query
+    _sql := format('SELECT * FROM rsvp.reservations WHERE tenant_id = %L AND %s AND %s AND %s ORDER BY lower(timespan) %s LIMIT %s ', tid, CASE WHEN is_desc THEN
+            'id < ' || quote_literal(CURSOR)
+        ELSE
+            'id > ' || quote_literal(CURSOR)
+        END, CASE WHEN uid IS NULL
+            AND rid IS NULL THEN
+            'TRUE'
+        WHEN uid IS NULL THEN
+            'resource_id = ' || quote_literal(rid)
+        WHEN rid IS NULL THEN
+            'user_id = ' || quote_literal(uid)
+        ELSE
+            'user_id = ' || quote_literal(uid) || ' AND resource_id = ' || quote_literal(rid)
+        END, CASE WHEN status = 'unknown' THEN
+            'TRUE'
+        ELSE
+            'status = ' || quote_literal(status)
+        END, CASE WHEN is_desc THEN
+            'DESC'
+        ELSE
+            'ASC'
+        END, page_size);
+
+    RETURN QUERY EXECUTE _sql;
+END;
+
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,17 @@
+CREATE OR REPLACE FUNCTION rsvp.reservations_buffer_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _before interval;
+    _after interval;
+BEGIN
+    SELECT buffer_before, buffer_after INTO _before, _after FROM rsvp.resources
+    WHERE tenant_id = NEW.tenant_id AND id = NEW.resource_id;
+    NEW.blocked := tstzrange(lower(NEW.timespan) - COALESCE(NEW.buffer_before, _before, '0'),
+        upper(NEW.timespan) + COALESCE(NEW.buffer_after, _after, '0'));
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+DROP FUNCTION rsvp.blocked_range(text, text, tstzrange, interval, interval);
@@ -0,0 +1,25 @@
+-- the timespan a reservation blocks on a resource, with its buffers or the ones of the resource,
+-- used to look up the reservations a rejected one conflicts with
+CREATE OR REPLACE FUNCTION rsvp.blocked_range(tid text, rid text, during tstzrange, buffer_before interval, buffer_after interval)
+    RETURNS tstzrange
+    AS $$
+    SELECT
+        tstzrange(lower(during) - COALESCE(blocked_range.buffer_before, max(r.buffer_before), '0'),
+            upper(during) + COALESCE(blocked_range.buffer_after, max(r.buffer_after), '0'))
+    FROM
+        rsvp.resources r
+    WHERE
+        r.tenant_id = tid
+        AND r.id = rid;
+$$
+LANGUAGE sql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_buffer_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    NEW.blocked := rsvp.blocked_range(NEW.tenant_id, NEW.resource_id, NEW.timespan, NEW.buffer_before, NEW.buffer_after);
+    RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,10 @@
+DELETE FROM rsvp.idempotency_keys;
+
+ALTER TABLE rsvp.idempotency_keys
+    DROP CONSTRAINT idempotency_keys_pkey,
+    DROP COLUMN actor,
+    DROP COLUMN request_hash,
+    DROP COLUMN response,
+    ADD COLUMN reservation jsonb,
+    ADD COLUMN waitlist_id bigint,
+    ADD CONSTRAINT idempotency_keys_pkey PRIMARY KEY (tenant_id, key);
@@ -0,0 +1,15 @@
+-- an outcome is only returned to a retry by the same actor which sends the same request,
+-- the outcomes stored before are dropped, they are only kept for a while anyway
+DELETE FROM rsvp.idempotency_keys;
+
+ALTER TABLE rsvp.idempotency_keys
+    DROP CONSTRAINT idempotency_keys_pkey,
+    DROP COLUMN reservation,
+    DROP COLUMN waitlist_id,
+    -- who sent the request, empty if it wasn't sent on behalf of anyone
+    ADD COLUMN actor text NOT NULL DEFAULT '',
+    -- sha256 of the request
+    ADD COLUMN request_hash bytea NOT NULL,
+    -- protobuf encoded response to the request
+    ADD COLUMN response bytea NOT NULL,
+    ADD CONSTRAINT idempotency_keys_pkey PRIMARY KEY (tenant_id, actor, key);
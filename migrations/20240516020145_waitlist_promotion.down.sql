@@ -0,0 +1,67 @@
+-- promote every waiting entry of a resource which fits now, oldest first,
+-- an entry which still conflicts keeps waiting
+CREATE OR REPLACE FUNCTION rsvp.promote_waitlist(tid text, rid text)
+    RETURNS void
+    AS $$
+DECLARE
+    _entry rsvp.waitlist;
+BEGIN
+    -- a reservation which is put on the waitlist holds this lock too,
+    -- so it's either promoted here or it's reserved right away
+    PERFORM
+        1
+    FROM
+        rsvp.resources
+    WHERE
+        tenant_id = tid
+        AND id = rid
+    FOR UPDATE;
+
+    FOR _entry IN
+    SELECT
+        *
+    FROM
+        rsvp.waitlist
+    WHERE
+        tenant_id = tid
+        AND resource_id = rid
+        AND upper(timespan) > now()
+    ORDER BY
+        id LOOP
+            BEGIN
+                INSERT INTO rsvp.reservations(tenant_id, user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, waitlist_id)
+                    VALUES (_entry.tenant_id, _entry.user_id, _entry.resource_id, _entry.timespan, _entry.note, _entry.seats, _entry.buffer_before, _entry.buffer_after, _entry.id);
+                DELETE FROM rsvp.waitlist
+                WHERE id = _entry.id;
+            EXCEPTION
+                WHEN exclusion_violation OR SQLSTATE 'RS001' THEN
+                    NULL;
+            END;
+        END LOOP;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_waitlist_trigger()
+    RETURNS TRIGGER
+    AS $$
+BEGIN
+    PERFORM
+        rsvp.promote_waitlist(OLD.tenant_id, OLD.resource_id);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
+
+-- a cancelled, expired or deleted reservation frees its timespan
+CREATE TRIGGER reservations_waitlist_cancel_trigger
+    AFTER UPDATE OF status ON rsvp.reservations
+    FOR EACH ROW
+    WHEN (OLD.status <> 'cancelled' AND NEW.status = 'cancelled')
+    EXECUTE PROCEDURE rsvp.reservations_waitlist_trigger();
+
+CREATE TRIGGER reservations_waitlist_delete_trigger
+    AFTER DELETE ON rsvp.reservations
+    FOR EACH ROW
+    WHEN (OLD.status <> 'cancelled')
+    EXECUTE PROCEDURE rsvp.reservations_waitlist_trigger();
@@ -0,0 +1,9 @@
+-- the waiting entries are promoted by the manager, which checks them against the booking
+-- rules and the quotas of their users like a new reservation
+DROP TRIGGER reservations_waitlist_delete_trigger ON rsvp.reservations;
+
+DROP TRIGGER reservations_waitlist_cancel_trigger ON rsvp.reservations;
+
+DROP FUNCTION rsvp.reservations_waitlist_trigger();
+
+DROP FUNCTION rsvp.promote_waitlist(text, text);
@@ -0,0 +1,33 @@
+ALTER TABLE rsvp.reservation_changes
+    ALTER COLUMN actor TYPE varchar(64)
+    USING left(actor, 64);
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _actor varchar(64) := NULLIF(current_setting('rsvp.actor', TRUE), '');
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- update reservation_changes
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, actor)
+            VALUES(NEW.id, CASE WHEN NEW.waitlist_id IS NULL THEN
+                    'create'
+                ELSE
+                    'promote'
+                END::rsvp.reservation_update_type, to_jsonb(NEW), _actor);
+    ELSIF TG_OP = 'UPDATE' THEN
+        -- every update is recorded, not only the ones of status, resource or timespan
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, old_reservation, actor)
+            VALUES(NEW.id, 'update', to_jsonb(NEW), to_jsonb(OLD), _actor);
+    ELSIF TG_OP = 'DELETE' THEN
+        -- update reservation_changes with the deleted row as tombstone
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, old_reservation, actor)
+            VALUES(OLD.id, 'delete', to_jsonb(OLD), to_jsonb(OLD), _actor);
+    END IF;
+    -- notify a channel called reservation_update
+    NOTIFY reservation_update;
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,45 @@
+-- the listeners read a change by its id
+CREATE UNIQUE INDEX reservation_changes_id_idx ON rsvp.reservation_changes(id);
+
+CREATE OR REPLACE FUNCTION rsvp.reservations_trigger()
+    RETURNS TRIGGER
+    AS $$
+DECLARE
+    _actor text := NULLIF(current_setting('rsvp.actor', TRUE), '');
+    _id integer;
+    _tenant text;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- update reservation_changes
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, actor)
+            VALUES(NEW.id, CASE WHEN NEW.waitlist_id IS NULL THEN
+                    'create'
+                ELSE
+                    'promote'
+                END::rsvp.reservation_update_type, to_jsonb(NEW), _actor)
+        RETURNING
+            id INTO _id;
+        _tenant := NEW.tenant_id;
+    ELSIF TG_OP = 'UPDATE' THEN
+        -- every update is recorded, not only the ones of status, resource or timespan
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, old_reservation, actor)
+            VALUES(NEW.id, 'update', to_jsonb(NEW), to_jsonb(OLD), _actor)
+        RETURNING
+            id INTO _id;
+        _tenant := NEW.tenant_id;
+    ELSIF TG_OP = 'DELETE' THEN
+        -- update reservation_changes with the deleted row as tombstone
+        INSERT INTO rsvp.reservation_changes(reservation_id, op, reservation, old_reservation, actor)
+            VALUES(OLD.id, 'delete', to_jsonb(OLD), to_jsonb(OLD), _actor)
+        RETURNING
+            id INTO _id;
+        _tenant := OLD.tenant_id;
+    END IF;
+    -- notify a channel called reservation_update with the change and its tenant,
+    -- the notifications are delivered once their transactions commit, in commit order
+    PERFORM
+        pg_notify('reservation_update', _id || ':' || _tenant);
+    RETURN NULL;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,11 @@
+DROP TABLE idempotency_keys;
+
+DROP TABLE waitlist;
+
+DROP TABLE reservation_changes;
+
+DROP TABLE reservations;
+
+DROP TABLE reservation_series;
+
+DROP TABLE resources;
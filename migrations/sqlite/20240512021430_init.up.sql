@@ -0,0 +1,109 @@
+-- the schema of SqliteReservationManager, the same tables as the rsvp schema of postgres
+-- sqlite has no ranges, exclusion constraints or intervals: times are microseconds since
+-- the epoch, intervals are microseconds, and conflicts are checked by the manager in the
+-- transaction which makes the change
+CREATE TABLE resources(
+    tenant_id text NOT NULL DEFAULT 'default',
+    id text NOT NULL,
+    name text NOT NULL DEFAULT '',
+    location text NOT NULL DEFAULT '',
+    -- a json array of strings
+    capabilities text NOT NULL DEFAULT '[]',
+    disabled boolean NOT NULL DEFAULT FALSE,
+    capacity integer NOT NULL DEFAULT 1 CHECK (capacity > 0),
+    buffer_before integer NOT NULL DEFAULT 0 CHECK (buffer_before >= 0),
+    buffer_after integer NOT NULL DEFAULT 0 CHECK (buffer_after >= 0),
+    PRIMARY KEY (tenant_id, id)
+);
+
+CREATE TABLE reservation_series(
+    id integer PRIMARY KEY AUTOINCREMENT,
+    tenant_id text NOT NULL DEFAULT 'default',
+    user_id text NOT NULL,
+    resource_id text NOT NULL,
+    -- timespan of the first occurrence
+    start_at integer NOT NULL,
+    end_at integer NOT NULL,
+    rrule text NOT NULL,
+    note text
+);
+
+CREATE TABLE reservations(
+    id integer PRIMARY KEY AUTOINCREMENT,
+    tenant_id text NOT NULL DEFAULT 'default',
+    user_id text NOT NULL,
+    status text NOT NULL DEFAULT 'pending',
+    resource_id text NOT NULL,
+    start_at integer NOT NULL,
+    end_at integer NOT NULL,
+    -- timespan with the buffers, used for conflict checks
+    blocked_start integer NOT NULL,
+    blocked_end integer NOT NULL,
+    note text NOT NULL DEFAULT '',
+    cancel_reason text,
+    series_id integer REFERENCES reservation_series(id) ON DELETE SET NULL,
+    expires_at integer,
+    seats integer NOT NULL DEFAULT 1 CHECK (seats > 0),
+    buffer_before integer CHECK (buffer_before >= 0),
+    buffer_after integer CHECK (buffer_after >= 0),
+    -- the waitlist entry the reservation was promoted from
+    waitlist_id integer,
+    version integer NOT NULL DEFAULT 1,
+    CHECK (start_at < end_at),
+    FOREIGN KEY (tenant_id, resource_id) REFERENCES resources(tenant_id, id)
+);
+
+CREATE INDEX reservations_blocked_idx ON reservations(tenant_id, resource_id, blocked_start, blocked_end)
+WHERE
+    status <> 'cancelled';
+
+CREATE INDEX reservations_user_id_idx ON reservations(tenant_id, user_id);
+
+CREATE INDEX reservations_expires_at_idx ON reservations(expires_at)
+WHERE
+    status = 'pending';
+
+-- every change of a reservation, with who made it and the reservation before and after,
+-- the reservations are protobuf encoded
+CREATE TABLE reservation_changes(
+    id integer PRIMARY KEY AUTOINCREMENT,
+    tenant_id text NOT NULL DEFAULT 'default',
+    reservation_id integer NOT NULL,
+    op text NOT NULL,
+    changed_at integer NOT NULL,
+    actor text,
+    old_reservation blob,
+    reservation blob
+);
+
+CREATE INDEX reservation_changes_reservation_id_idx ON reservation_changes(reservation_id);
+
+-- reservation requests waiting for their timespan to become free
+CREATE TABLE waitlist(
+    id integer PRIMARY KEY AUTOINCREMENT,
+    tenant_id text NOT NULL DEFAULT 'default',
+    user_id text NOT NULL,
+    resource_id text NOT NULL,
+    start_at integer NOT NULL,
+    end_at integer NOT NULL,
+    note text NOT NULL DEFAULT '',
+    seats integer NOT NULL DEFAULT 1,
+    buffer_before integer,
+    buffer_after integer,
+    created_at integer NOT NULL
+);
+
+CREATE INDEX waitlist_resource_id_idx ON waitlist(tenant_id, resource_id);
+
+-- results of reserve requests sent with an idempotency key
+CREATE TABLE idempotency_keys(
+    tenant_id text NOT NULL DEFAULT 'default',
+    key text NOT NULL,
+    -- protobuf encoded snapshot of the reservation, NULL if the request was put on the waitlist
+    reservation blob,
+    waitlist_id integer,
+    created_at integer NOT NULL,
+    PRIMARY KEY (tenant_id, key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys(created_at);
@@ -0,0 +1,13 @@
+DROP TABLE idempotency_keys;
+
+CREATE TABLE idempotency_keys(
+    tenant_id text NOT NULL DEFAULT 'default',
+    key text NOT NULL,
+    -- protobuf encoded snapshot of the reservation, NULL if the request was put on the waitlist
+    reservation blob,
+    waitlist_id integer,
+    created_at integer NOT NULL,
+    PRIMARY KEY (tenant_id, key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys(created_at);
@@ -0,0 +1,17 @@
+-- an outcome is only returned to a retry by the same actor which sends the same request
+DROP TABLE idempotency_keys;
+
+CREATE TABLE idempotency_keys(
+    tenant_id text NOT NULL DEFAULT 'default',
+    -- who sent the request, empty if it wasn't sent on behalf of anyone
+    actor text NOT NULL DEFAULT '',
+    key text NOT NULL,
+    -- sha256 of the request
+    request_hash blob NOT NULL,
+    -- protobuf encoded response to the request
+    response blob NOT NULL,
+    created_at integer NOT NULL,
+    PRIMARY KEY (tenant_id, actor, key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys(created_at);
@@ -0,0 +1,207 @@
+//! Conflict and paging rules shared by the backends which check them in Rust instead of
+//! in the database, so they behave like the triggers and functions of the migrations.
+
+use abi::error::conflict::{ReservationConflict, ReservationConflictInfo};
+use abi::utils::{datetime_to_timestamp, timedelta_to_duration, timestamp_to_datetime};
+use abi::{Reservation, ReservationStatus};
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
+
+// a timespan from its start, included, to its end, excluded
+pub(crate) type Span = (DateTime<Utc>, DateTime<Utc>);
+
+// the timespan of a reservation, which must have a start before its end
+pub(crate) fn span(rsvp: &Reservation) -> Result<Span, abi::Error> {
+    rsvp.timespan()?;
+    Ok(timespan(rsvp))
+}
+
+// the timespan of a stored reservation, which was checked when it was stored
+pub(crate) fn timespan(rsvp: &Reservation) -> Span {
+    let start = rsvp.start.as_ref().map(timestamp_to_datetime);
+    let end = rsvp.end.as_ref().map(timestamp_to_datetime);
+    (start.unwrap_or_default(), end.unwrap_or_default())
+}
+
+// the timespan of a reservation extended by its buffers, or the ones of its resource
+pub(crate) fn blocked_span(
+    rsvp: &Reservation,
+    resource: Option<&abi::Resource>,
+) -> Result<Span, abi::Error> {
+    let (start, end) = span(rsvp)?;
+    let before = rsvp
+        .buffer_before()
+        .or(resource.map(|r| r.buffer_before()))
+        .unwrap_or_default();
+    let after = rsvp
+        .buffer_after()
+        .or(resource.map(|r| r.buffer_after()))
+        .unwrap_or_default();
+
+    Ok((start - before, end + after))
+}
+
+pub(crate) fn is_cancelled(rsvp: &Reservation) -> bool {
+    rsvp.status == ReservationStatus::Cancelled as i32
+}
+
+pub(crate) fn overlaps(a: Span, b: Span) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+pub(crate) fn intersect(a: Span, b: Span) -> Span {
+    (a.0.max(b.0), a.1.min(b.1))
+}
+
+// the seats taken at a time by the booked timespans
+pub(crate) fn seats_at(booked: &[(Span, i32)], t: DateTime<Utc>) -> i32 {
+    booked
+        .iter()
+        .filter(|((start, end), _)| *start <= t && t < *end)
+        .map(|(_, seats)| seats)
+        .sum()
+}
+
+// the most seats taken at once by the booked timespans
+pub(crate) fn peak_seats(booked: &[(Span, i32)]) -> i32 {
+    // the usage only goes up at the start of a reservation
+    booked
+        .iter()
+        .map(|((t, _), _)| seats_at(booked, *t))
+        .max()
+        .unwrap_or(0)
+}
+
+// the conflict of a reservation which needs more seats than the remaining ones, with the
+// existing reservations it overlaps, ordered by the start of their blocked timespan
+pub(crate) fn conflict(
+    rsvp: &Reservation,
+    existing: Vec<Reservation>,
+    remaining: i32,
+) -> abi::Error {
+    let info = match ReservationConflict::new(rsvp, existing, remaining as u32) {
+        Some(conflict) => ReservationConflictInfo::Parsed(Box::new(conflict)),
+        None => ReservationConflictInfo::Raw(format!(
+            "{} seats of {} requested, remaining capacity: {}.",
+            rsvp.seats(),
+            rsvp.resource_id,
+            remaining
+        )),
+    };
+    abi::Error::ConflictReservation(info)
+}
+
+// the timespans of `window` where the booked timespans leave some of the capacity free
+pub(crate) fn free_spans(window: Span, booked: &[(Span, i32)], capacity: i32) -> Vec<Span> {
+    let mut bounds: Vec<DateTime<Utc>> = booked
+        .iter()
+        .flat_map(|((start, end), _)| [*start, *end])
+        .collect();
+    bounds.sort();
+    bounds.dedup();
+
+    let mut free = Vec::new();
+    let mut from = window.0;
+    for piece in bounds.windows(2) {
+        if seats_at(booked, piece[0]) >= capacity {
+            if piece[0] > from {
+                free.push((from, piece[0]));
+            }
+            from = from.max(piece[1]);
+        }
+    }
+    if window.1 > from {
+        free.push((from, window.1));
+    }
+
+    free
+}
+
+// the active ones of the counted reservations of a user, and the time booked by the ones
+// starting in the week of `week`
+pub(crate) fn quota_count<'a>(
+    counted: impl Iterator<Item = &'a Reservation>,
+    week: DateTime<Utc>,
+) -> (i64, TimeDelta) {
+    let now = Utc::now();
+    let mut active = 0;
+    let mut weekly = TimeDelta::zero();
+    for rsvp in counted {
+        let (start, end) = timespan(rsvp);
+        if end > now {
+            active += 1;
+        }
+        if start.iso_week() == week.iso_week() {
+            weekly += end - start;
+        }
+    }
+
+    (active, weekly)
+}
+
+// the filters of a query, unset ones and an unknown status match everything
+pub(crate) fn matches(
+    rsvp: &Reservation,
+    user_id: &Option<String>,
+    resource_id: &Option<String>,
+    status: i32,
+) -> bool {
+    let status = ReservationStatus::try_from(status).unwrap_or(ReservationStatus::Unknown);
+    user_id.as_ref().is_none_or(|id| *id == rsvp.user_id)
+        && resource_id
+            .as_ref()
+            .is_none_or(|id| *id == rsvp.resource_id)
+        && (status == ReservationStatus::Unknown || rsvp.status == status as i32)
+}
+
+pub(crate) fn page_size(page_size: i32) -> usize {
+    match page_size {
+        1..=500 => page_size as usize,
+        _ => 10,
+    }
+}
+
+// the cursors around a page of a filter, whose direction was already flipped if it's
+// the previous page
+pub(crate) fn pager(filter: &abi::ReservationFilter, rsvps: &[Reservation]) -> abi::FilterPager {
+    let full = rsvps.len() >= filter.page_size as usize;
+    let prev = if filter.cursor == 0 || (!full && filter.is_prev) {
+        None
+    } else {
+        rsvps.first().map(|r| r.id)
+    };
+    let next = if !full && !filter.is_prev {
+        None
+    } else {
+        rsvps.last().map(|r| r.id)
+    };
+
+    abi::FilterPager { prev, next }
+}
+
+// a resource as it's stored, with its capacity and buffers set
+pub(crate) fn normalize(resource: abi::Resource) -> abi::Resource {
+    abi::Resource {
+        capacity: resource.capacity(),
+        buffer_before: Some(timedelta_to_duration(resource.buffer_before())),
+        buffer_after: Some(timedelta_to_duration(resource.buffer_after())),
+        ..resource
+    }
+}
+
+// the free slots of at least `duration` left by the busy timespans of a resource, whose
+// buffers have to fit around a reservation in them
+pub(crate) fn slots(
+    free: Vec<Span>,
+    before: TimeDelta,
+    after: TimeDelta,
+    duration: TimeDelta,
+) -> Vec<abi::TimeSlot> {
+    free.into_iter()
+        .map(|(start, end)| (start + before, end - after))
+        .filter(|(start, end)| end > start && *end - *start >= duration)
+        .map(|(start, end)| abi::TimeSlot {
+            start: Some(datetime_to_timestamp(start)),
+            end: Some(datetime_to_timestamp(end)),
+        })
+        .collect()
+}
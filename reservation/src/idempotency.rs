@@ -0,0 +1,242 @@
+//! Outcomes of requests sent with an idempotency key, shared by the backends.
+//! An outcome is stored with the fingerprint of its request and returned to a retry only if
+//! the retry sends the same request, it's encoded like the response of the request.
+
+use abi::utils::datetime_to_timestamp;
+use abi::{
+    CancelRequest, ChangeResourceRequest, ConfirmRequest, RescheduleRequest, Reservation,
+    ReservationSeries, ReserveBatchRequest, ReserveBatchResponse, ReserveRequest, ReserveResponse,
+    ReserveSeriesRequest, ReserveSeriesResponse, SeriesConflictMode, UpdateRequest,
+};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use crate::{ReservationId, ReserveOutcome};
+
+// the idempotency key of a change and the fingerprint of its request
+pub(crate) struct Keyed {
+    pub key: String,
+    pub hash: Vec<u8>,
+}
+
+pub(crate) fn check_key(key: &str) -> Result<(), abi::Error> {
+    if key.is_empty() || key.len() > 255 {
+        return Err(abi::Error::InvalidIdempotencyKey);
+    }
+
+    Ok(())
+}
+
+// the key of a change made with an idempotency key, the fingerprint is only taken if there's one
+pub(crate) fn keyed(
+    key: Option<&String>,
+    fingerprint: impl FnOnce() -> Vec<u8>,
+) -> Result<Option<Keyed>, abi::Error> {
+    let Some(key) = key else {
+        return Ok(None);
+    };
+    check_key(key)?;
+
+    Ok(Some(Keyed {
+        key: key.clone(),
+        hash: fingerprint(),
+    }))
+}
+
+// the sha256 of the operation and its request
+pub(crate) fn fingerprint(op: &str, request: &impl Message) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(op.as_bytes());
+    hasher.update([0]);
+    hasher.update(request.encode_to_vec());
+    hasher.finalize().to_vec()
+}
+
+// the hold of a retry expires later than the one of the first request, so it's left out
+pub(crate) fn reserve_fingerprint(rsvp: &Reservation, waitlist: bool) -> Vec<u8> {
+    let request = ReserveRequest {
+        reservation: Some(Reservation {
+            expires_at: None,
+            ..rsvp.clone()
+        }),
+        hold_ttl: None,
+        waitlist,
+        idempotency_key: None,
+    };
+    fingerprint("reserve", &request)
+}
+
+pub(crate) fn batch_fingerprint(rsvps: &[Reservation]) -> Vec<u8> {
+    let request = ReserveBatchRequest {
+        reservations: rsvps.to_vec(),
+        idempotency_key: None,
+    };
+    fingerprint("reserve_batch", &request)
+}
+
+pub(crate) fn series_fingerprint(series: &ReservationSeries, mode: SeriesConflictMode) -> Vec<u8> {
+    let request = ReserveSeriesRequest {
+        series: Some(series.clone()),
+        mode: mode as i32,
+        idempotency_key: None,
+    };
+    fingerprint("reserve_series", &request)
+}
+
+pub(crate) fn cancel_fingerprint(
+    id: ReservationId,
+    reason: Option<&String>,
+    expected_version: Option<i64>,
+) -> Vec<u8> {
+    let request = CancelRequest {
+        id,
+        reason: reason.cloned(),
+        expected_version,
+        idempotency_key: None,
+    };
+    fingerprint("cancel", &request)
+}
+
+pub(crate) fn reschedule_fingerprint(
+    id: ReservationId,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    expected_version: Option<i64>,
+) -> Vec<u8> {
+    let request = RescheduleRequest {
+        id,
+        start: Some(datetime_to_timestamp(start)),
+        end: Some(datetime_to_timestamp(end)),
+        expected_version,
+        idempotency_key: None,
+    };
+    fingerprint("reschedule", &request)
+}
+
+pub(crate) fn confirm_fingerprint(id: ReservationId, expected_version: Option<i64>) -> Vec<u8> {
+    let request = ConfirmRequest {
+        id,
+        expected_version,
+        idempotency_key: None,
+    };
+    fingerprint("confirm", &request)
+}
+
+pub(crate) fn update_fingerprint(
+    id: ReservationId,
+    note: &str,
+    expected_version: Option<i64>,
+) -> Vec<u8> {
+    let request = UpdateRequest {
+        id,
+        note: note.to_string(),
+        expected_version,
+        idempotency_key: None,
+    };
+    fingerprint("update", &request)
+}
+
+pub(crate) fn change_resource_fingerprint(
+    id: ReservationId,
+    resource_id: &str,
+    expected_version: Option<i64>,
+) -> Vec<u8> {
+    let request = ChangeResourceRequest {
+        id,
+        resource_id: resource_id.to_string(),
+        expected_version,
+        idempotency_key: None,
+    };
+    fingerprint("change_resource", &request)
+}
+
+// the stored outcome, unless it was stored for another request
+pub(crate) fn replay<T: Outcome>(
+    stored_hash: &[u8],
+    response: &[u8],
+    hash: &[u8],
+) -> Result<T, abi::Error> {
+    if stored_hash != hash {
+        return Err(abi::Error::IdempotencyKeyReused);
+    }
+
+    T::decode(response)
+}
+
+pub(crate) trait Outcome: Sized {
+    fn encode(&self) -> Vec<u8>;
+
+    fn decode(response: &[u8]) -> Result<Self, abi::Error>;
+}
+
+impl Outcome for ReserveOutcome {
+    fn encode(&self) -> Vec<u8> {
+        let response = match self {
+            ReserveOutcome::Reserved(rsvp) => ReserveResponse {
+                reservation: Some((**rsvp).clone()),
+                waitlist_id: None,
+            },
+            ReserveOutcome::Waitlisted(id) => ReserveResponse {
+                reservation: None,
+                waitlist_id: Some(*id),
+            },
+        };
+        response.encode_to_vec()
+    }
+
+    fn decode(response: &[u8]) -> Result<Self, abi::Error> {
+        let response = ReserveResponse::decode(response).map_err(invalid)?;
+        match (response.reservation, response.waitlist_id) {
+            (Some(rsvp), _) => Ok(ReserveOutcome::Reserved(Box::new(rsvp))),
+            (None, Some(id)) => Ok(ReserveOutcome::Waitlisted(id)),
+            (None, None) => Err(abi::Error::NotFound),
+        }
+    }
+}
+
+// a reservation changed by id, e.g. a cancelled or rescheduled one
+impl Outcome for Reservation {
+    fn encode(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    fn decode(response: &[u8]) -> Result<Self, abi::Error> {
+        <Reservation as Message>::decode(response).map_err(invalid)
+    }
+}
+
+impl Outcome for Vec<Reservation> {
+    fn encode(&self) -> Vec<u8> {
+        let response = ReserveBatchResponse {
+            reservations: self.clone(),
+        };
+        response.encode_to_vec()
+    }
+
+    fn decode(response: &[u8]) -> Result<Self, abi::Error> {
+        let response = ReserveBatchResponse::decode(response).map_err(invalid)?;
+        Ok(response.reservations)
+    }
+}
+
+impl Outcome for (ReservationSeries, Vec<Reservation>, Vec<Reservation>) {
+    fn encode(&self) -> Vec<u8> {
+        let response = ReserveSeriesResponse {
+            series: Some(self.0.clone()),
+            reservations: self.1.clone(),
+            conflicts: self.2.clone(),
+        };
+        response.encode_to_vec()
+    }
+
+    fn decode(response: &[u8]) -> Result<Self, abi::Error> {
+        let response = ReserveSeriesResponse::decode(response).map_err(invalid)?;
+        let series = response.series.ok_or(abi::Error::NotFound)?;
+        Ok((series, response.reservations, response.conflicts))
+    }
+}
+
+fn invalid(e: prost::DecodeError) -> abi::Error {
+    sqlx::Error::Decode(Box::new(e)).into()
+}
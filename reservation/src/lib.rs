@@ -1,11 +1,44 @@
 use tokio::sync::mpsc::Receiver;
 
-use abi::{config::DbConfig, Reservation, ReservationFilter, ReservationQuery};
+use std::sync::Arc;
+
+use abi::{
+    config::{DbConfig, IdempotencyConfig, PolicyConfig, QuotaConfig},
+    Reservation, ReservationFilter, ReservationQuery,
+};
+use chrono::{DateTime, TimeDelta, Utc};
 use sqlx::Error;
 
+mod booking;
+mod idempotency;
 mod manager;
+mod memory;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(test)]
+mod suite;
+
+pub use memory::InMemoryReservationManager;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteReservationManager;
+
 pub type ReservationId = i64;
+pub type WaitlistId = i64;
+
+/// Tenant of a manager which isn't given one, and of the data made before tenants existed
+pub const DEFAULT_TENANT: &str = "default";
 
+/// A reservation which is either reserved or waiting for its timespan
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReserveOutcome {
+    Reserved(Box<Reservation>),
+    Waitlisted(WaitlistId),
+}
+
+/// Every method only sees and changes the data of the tenant of the manager, the
+/// reservations of different tenants never conflict.
+/// Methods changing a Reservation take an optional `expected_version`, they fail with
+/// `VersionMismatch` if the reservation was changed since it was read at that version.
 pub trait Rsvp {
     // Reserve a Reservation
     fn reserve(
@@ -13,6 +46,45 @@ pub trait Rsvp {
         rsvp: abi::Reservation,
     ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
 
+    /// Reserve a Reservation, or put it on the waitlist if it conflicts
+    /// It's promoted to a pending reservation once a conflicting one is cancelled,
+    /// expires or is deleted, and the listeners get a `Promote` update.
+    fn reserve_or_wait(
+        &self,
+        rsvp: abi::Reservation,
+    ) -> impl std::future::Future<Output = Result<ReserveOutcome, abi::Error>> + Send;
+
+    /// Reserve a Reservation once for an idempotency key
+    /// A retry with the same key returns the outcome of the first request instead of
+    /// reserving again, for as long as the key is kept. A failed request isn't kept.
+    /// The keys of every actor are apart, and a retry sending another request than the
+    /// first one fails with `IdempotencyKeyReused`.
+    /// With `waitlist`, a conflicting reservation is put on the waitlist.
+    fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: abi::Reservation,
+        waitlist: bool,
+    ) -> impl std::future::Future<Output = Result<ReserveOutcome, abi::Error>> + Send;
+
+    /// Reserve all Reservations in one transaction
+    /// If any of them fails, none is reserved and the error tells which one failed.
+    fn reserve_many(
+        &self,
+        rsvps: Vec<abi::Reservation>,
+    ) -> impl std::future::Future<Output = Result<Vec<Reservation>, abi::Error>> + Send;
+
+    /// Reserve every occurrence of a recurring ReservationSeries in one transaction
+    /// Returns the series with the reserved occurrences and the occurrences skipped for
+    /// conflicts. With `SeriesConflictMode::Fail`, any conflict fails the whole series.
+    fn reserve_series(
+        &self,
+        series: abi::ReservationSeries,
+        mode: abi::SeriesConflictMode,
+    ) -> impl std::future::Future<
+        Output = Result<(abi::ReservationSeries, Vec<Reservation>, Vec<Reservation>), abi::Error>,
+    > + Send;
+
     /// delete a Reservation
     fn delete(
         &self,
@@ -21,15 +93,53 @@ pub trait Rsvp {
 
     // Change a Reservation Status
     // If the reservation is pending, it will be confirmed.
+    // An expired hold can't be confirmed anymore.
     fn change_status(
         &self,
         rsvp: ReservationId,
+        expected_version: Option<i64>,
+    ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
+
+    /// Cancel a Reservation
+    /// The reservation is kept for history, but its timespan can be reserved again.
+    fn cancel(
+        &self,
+        rsvp: ReservationId,
+        reason: Option<String>,
+        expected_version: Option<i64>,
+    ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
+
+    /// Move a Reservation to a new timespan
+    /// Fails with a conflict if the new timespan is already reserved, with a policy
+    /// violation if it breaks the booking rules of the resource, or if it exceeds a quota.
+    fn reschedule(
+        &self,
+        rsvp: ReservationId,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        expected_version: Option<i64>,
     ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
 
+    /// Move a Reservation to another resource
+    /// Fails with a conflict if the resource is already reserved in the timespan.
+    fn change_resource(
+        &self,
+        rsvp: ReservationId,
+        resource_id: String,
+        expected_version: Option<i64>,
+    ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
+
+    /// Release all pending Reservations whose hold has expired
+    /// They are cancelled, so their timespan can be reserved again.
+    fn release_expired(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<Reservation>, abi::Error>> + Send;
+
     fn update_notes(
         &self,
         rsvp: ReservationId,
         note: String,
+        expected_version: Option<i64>,
     ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
 
     fn get(
@@ -37,6 +147,13 @@ pub trait Rsvp {
         rsvp: ReservationId,
     ) -> impl std::future::Future<Output = Result<Reservation, abi::Error>> + Send;
 
+    /// The changes of a Reservation, oldest first, with who made them and the
+    /// reservation before and after each of them. Deleted reservations keep their history.
+    fn get_history(
+        &self,
+        rsvp: ReservationId,
+    ) -> impl std::future::Future<Output = Result<Vec<abi::ReservationChange>, abi::Error>> + Send;
+
     fn query(
         &self,
         query: ReservationQuery,
@@ -48,21 +165,213 @@ pub trait Rsvp {
         &self,
         filter: ReservationFilter,
     ) -> impl std::future::Future<Output = Result<(abi::FilterPager, Vec<Reservation>), abi::Error>> + Send;
+
+    /// Find the free timespans of a resource between start and end,
+    /// which are at least as long as duration, which must be positive.
+    fn find_availability(
+        &self,
+        resource_id: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        duration: TimeDelta,
+    ) -> impl std::future::Future<Output = Result<Vec<abi::TimeSlot>, abi::Error>> + Send;
+
+    /// Usage of every quota which limits a user, the weekly usage is the one of
+    /// the week containing `week`
+    fn quota_usage(
+        &self,
+        user_id: String,
+        week: DateTime<Utc>,
+    ) -> impl std::future::Future<Output = Result<Vec<abi::QuotaUsage>, abi::Error>> + Send;
+
+    /// Register a Resource, so it can be reserved
+    fn create_resource(
+        &self,
+        resource: abi::Resource,
+    ) -> impl std::future::Future<Output = Result<abi::Resource, abi::Error>> + Send;
+
+    fn get_resource(
+        &self,
+        id: String,
+    ) -> impl std::future::Future<Output = Result<abi::Resource, abi::Error>> + Send;
+
+    /// Update everything but the id of a Resource
+    fn update_resource(
+        &self,
+        resource: abi::Resource,
+    ) -> impl std::future::Future<Output = Result<abi::Resource, abi::Error>> + Send;
+
+    /// Delete a Resource, which must not be referenced by any reservation
+    fn delete_resource(
+        &self,
+        id: String,
+    ) -> impl std::future::Future<Output = Result<abi::Resource, abi::Error>> + Send;
+
+    fn list_resources(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<abi::Resource>, abi::Error>> + Send;
+
+    /// Listen for reservation changes made after the call.
+    /// A deleted reservation is sent with its last known state.
+    fn listen(
+        &self,
+    ) -> impl std::future::Future<
+        Output = Result<Receiver<Result<abi::ListenResponse, abi::Error>>, abi::Error>,
+    > + Send;
 }
 
-#[derive(Debug)]
+/// A `Rsvp` which serves several tenants and actors, like the service does: it's cloned
+/// for every request and scoped to the tenant and the actor of the request.
+pub trait Scoped: Rsvp + Clone + Sized {
+    /// Only see and change the data of the tenant
+    fn with_tenant(self, tenant: impl Into<String>) -> Self;
+
+    /// Record the changes made with this manager as made by the actor
+    fn with_actor(self, actor: impl Into<String>) -> Self;
+
+    /// Make the changes with this manager once for an idempotency key, like
+    /// `Rsvp::reserve_idempotent` reserves once: a retry of `reserve_many`, `reserve_series`,
+    /// `change_status`, `update_notes`, `reschedule`, `change_resource` or `cancel` with the
+    /// same key returns the outcome of the first request instead of making the change again.
+    fn with_idempotency_key(self, key: impl Into<String>) -> Self;
+
+    /// Release the expired holds of every tenant, unlike `Rsvp::release_expired`
+    /// which only releases the ones of the tenant of the manager
+    fn release_all_expired(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<Reservation>, abi::Error>> + Send;
+}
+
+#[derive(Debug, Clone)]
 pub struct ReservationManager {
     pool: sqlx::PgPool,
+    policy: Arc<PolicyConfig>,
+    quota: Arc<QuotaConfig>,
+    // how long the outcome of a request with an idempotency key is kept
+    idempotency_ttl: TimeDelta,
+    // who the changes are made by, recorded in the history of the reservations
+    actor: Option<String>,
+    // the tenant whose data the manager sees and changes
+    tenant: String,
+    // the key of the request the changes are made once for
+    idempotency_key: Option<String>,
 }
 
 impl ReservationManager {
     pub async fn from_config(confg: &DbConfig) -> Result<Self, Error> {
         let db_url = confg.db_url();
         let pool = sqlx::PgPool::connect(&db_url).await?;
-        Ok(Self { pool })
+        Ok(Self::new(pool))
     }
 
     pub fn new(pool: sqlx::PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            policy: Default::default(),
+            quota: Default::default(),
+            idempotency_ttl: TimeDelta::try_seconds(IdempotencyConfig::default().ttl as i64)
+                .unwrap(),
+            actor: None,
+            tenant: DEFAULT_TENANT.to_string(),
+            idempotency_key: None,
+        }
+    }
+
+    /// Enforce the booking rules when reserving or rescheduling
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Enforce the quotas of the users when reserving or rescheduling
+    pub fn with_quota(mut self, quota: QuotaConfig) -> Self {
+        self.quota = Arc::new(quota);
+        self
+    }
+
+    /// Only see and change the data of the tenant
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    /// Release the expired holds of every tenant, unlike `Rsvp::release_expired`
+    /// which only releases the ones of the tenant of the manager
+    pub async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        self.release_expired_of(None).await
+    }
+
+    /// Record the changes made with this manager as made by the actor
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Make the changes with this manager once for an idempotency key
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Keep the outcome of a request with an idempotency key for the configured time
+    pub fn with_idempotency(mut self, idempotency: IdempotencyConfig) -> Self {
+        self.idempotency_ttl =
+            TimeDelta::try_seconds(idempotency.ttl as i64).unwrap_or(TimeDelta::MAX);
+        self
+    }
+}
+
+impl Scoped for ReservationManager {
+    fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        ReservationManager::with_tenant(self, tenant)
+    }
+
+    fn with_actor(self, actor: impl Into<String>) -> Self {
+        ReservationManager::with_actor(self, actor)
+    }
+
+    fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        ReservationManager::with_idempotency_key(self, key)
+    }
+
+    async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        ReservationManager::release_all_expired(self).await
+    }
+}
+
+impl Scoped for InMemoryReservationManager {
+    fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        InMemoryReservationManager::with_tenant(self, tenant)
+    }
+
+    fn with_actor(self, actor: impl Into<String>) -> Self {
+        InMemoryReservationManager::with_actor(self, actor)
+    }
+
+    fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        InMemoryReservationManager::with_idempotency_key(self, key)
+    }
+
+    async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        InMemoryReservationManager::release_all_expired(self).await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Scoped for SqliteReservationManager {
+    fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        SqliteReservationManager::with_tenant(self, tenant)
+    }
+
+    fn with_actor(self, actor: impl Into<String>) -> Self {
+        SqliteReservationManager::with_actor(self, actor)
+    }
+
+    fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        SqliteReservationManager::with_idempotency_key(self, key)
+    }
+
+    async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        SqliteReservationManager::release_all_expired(self).await
     }
 }
@@ -1,52 +1,651 @@
+use crate::idempotency::{
+    batch_fingerprint, cancel_fingerprint, change_resource_fingerprint, confirm_fingerprint, keyed,
+    replay, reschedule_fingerprint, reserve_fingerprint, series_fingerprint, update_fingerprint,
+    Keyed, Outcome,
+};
 use crate::ReservationManager;
+use crate::ReserveOutcome;
 use crate::Rsvp;
+use crate::WaitlistId;
+use abi::error::conflict::ReservationConflict;
+use abi::error::conflict::ReservationConflictInfo;
+use abi::utils::datetime_to_timestamp;
+use abi::utils::timestamp_to_datetime;
 use abi::Reservation;
 use abi::ReservationStatus;
+use abi::ReservationUpdateType;
+use abi::SeriesConflictMode;
 use chrono::DateTime;
+use chrono::TimeDelta;
 use chrono::Utc;
 use sqlx::postgres::types::PgRange;
+use sqlx::postgres::PgListener;
+use sqlx::Acquire;
+use sqlx::FromRow;
+use sqlx::PgConnection;
+use sqlx::PgExecutor;
+use sqlx::Postgres;
 use sqlx::Row;
+use sqlx::Transaction;
 use tokio::sync::mpsc;
 use tokio_stream::StreamExt as _;
 
-impl Rsvp for ReservationManager {
-    async fn reserve(&self, rsvp: abi::Reservation) -> Result<abi::Reservation, abi::Error> {
+impl ReservationManager {
+    // begin a transaction, the changes made in it are recorded with the actor
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, abi::Error> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(actor) = &self.actor {
+            sqlx::query("SELECT set_config('rsvp.actor', $1, TRUE)")
+                .bind(actor)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        Ok(tx)
+    }
+
+    // insert a reservation with the given connection, which must be inside a transaction
+    // for the quotas to be checked atomically, as an occurrence of the series if there is one
+    async fn insert(
+        &self,
+        conn: &mut PgConnection,
+        rsvp: abi::Reservation,
+        series_id: Option<i64>,
+    ) -> Result<abi::Reservation, abi::Error> {
         rsvp.validate()?;
 
         let timespan: PgRange<DateTime<Utc>> = rsvp.timespan()?;
+        let start = timestamp_to_datetime(rsvp.start.as_ref().unwrap());
+        let end = timestamp_to_datetime(rsvp.end.as_ref().unwrap());
+        self.check_policy(&rsvp.resource_id, start, end)?;
+        self.check_quota(conn, &rsvp.user_id, &rsvp.resource_id, start, end, None)
+            .await?;
         let status = ReservationStatus::try_from(rsvp.status)
             .unwrap_or(ReservationStatus::Pending)
             .to_string();
 
-        let id:i64 = sqlx::query(
+        // in a savepoint, so the reservations it conflicts with can still be looked up
+        let mut savepoint = conn.begin().await?;
+        let result = sqlx::query(
             r#"
-            INSERT INTO rsvp.reservations (user_id, resource_id, status, timespan, note) VALUES ($1, $2, $3::rsvp.reservation_status, $4, $5)
+            INSERT INTO rsvp.reservations (user_id, resource_id, status, timespan, note, series_id, expires_at, seats, buffer_before, buffer_after, tenant_id) VALUES ($1, $2, $3::rsvp.reservation_status, $4, $5, $6, $7, $8, $9, $10, $11)
             RETURNING id"#)
         .bind(&rsvp.user_id)
         .bind(&rsvp.resource_id)
         .bind(status)
         .bind(timespan)
         .bind(&rsvp.note)
-        .fetch_one(&self.pool)
-        .await?
-        .get(0);
+        .bind(series_id)
+        .bind(rsvp.expires_at())
+        .bind(rsvp.seats())
+        .bind(rsvp.buffer_before())
+        .bind(rsvp.buffer_after())
+        .bind(&self.tenant)
+        .fetch_one(&mut *savepoint)
+        .await;
+        let id: i64 = match result {
+            Ok(row) => {
+                savepoint.commit().await?;
+                row.get(0)
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                // not an id of any reservation, the id of the request would hide one conflicting
+                let new = abi::Reservation { id: 0, ..rsvp };
+                return Err(self.conflict_error(conn, &new, e).await);
+            }
+        };
         let mut rsvp = rsvp;
 
         rsvp.id = id;
+        rsvp.seats = rsvp.seats();
+        rsvp.series_id = series_id;
+        rsvp.waitlist_id = None;
+        rsvp.version = 1;
+
+        Ok(rsvp)
+    }
+
+    // the error of a statement rejected for `rsvp`, run in a savepoint of `conn` which was
+    // rolled back, a conflict gets the reservations it conflicts with instead of the parsed error
+    async fn conflict_error(
+        &self,
+        conn: &mut PgConnection,
+        rsvp: &abi::Reservation,
+        e: sqlx::Error,
+    ) -> abi::Error {
+        match abi::Error::from(e) {
+            abi::Error::ConflictReservation(info) => match self.conflict_of(conn, rsvp).await {
+                Ok(Some(conflict)) => abi::Error::ConflictReservation(
+                    ReservationConflictInfo::Parsed(Box::new(conflict)),
+                ),
+                // the conflicting reservations were cancelled meanwhile
+                Ok(None) => abi::Error::ConflictReservation(info),
+                Err(e) => e,
+            },
+            e => e,
+        }
+    }
+
+    // the conflict of a reservation with the ones overlapping what it blocks of its resource
+    async fn conflict_of(
+        &self,
+        conn: &mut PgConnection,
+        rsvp: &abi::Reservation,
+    ) -> Result<Option<ReservationConflict>, abi::Error> {
+        let existing: Vec<Reservation> = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.reservations
+            WHERE tenant_id=$1 AND resource_id=$2 AND status<>'cancelled' AND id<>$3
+                AND blocked && rsvp.blocked_range($1, $2, $4, $5, $6)
+            ORDER BY lower(blocked), id
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(&rsvp.resource_id)
+        .bind(rsvp.id)
+        .bind(rsvp.timespan()?)
+        .bind(rsvp.buffer_before())
+        .bind(rsvp.buffer_after())
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let remaining: i32 = sqlx::query(
+            r#"
+            SELECT greatest(capacity - rsvp.peak_seats($1, $2, rsvp.blocked_range($1, $2, $4, $5, $6), $3), 0)
+            FROM rsvp.resources WHERE tenant_id=$1 AND id=$2
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(&rsvp.resource_id)
+        .bind(rsvp.id)
+        .bind(rsvp.timespan()?)
+        .bind(rsvp.buffer_before())
+        .bind(rsvp.buffer_after())
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|row| row.get(0))
+        .unwrap_or(0);
+
+        Ok(ReservationConflict::new(rsvp, existing, remaining as u32))
+    }
+
+    // release the expired holds of the tenant, or of every tenant if it's None
+    pub(crate) async fn release_expired_of(
+        &self,
+        tenant: Option<&str>,
+    ) -> Result<Vec<abi::Reservation>, abi::Error> {
+        let mut tx = self.begin().await?;
+        // the trigger sends an update for every released hold
+        let rows = sqlx::query(
+            r#"
+            UPDATE rsvp.reservations SET status='cancelled', cancel_reason='hold expired'
+            WHERE status='pending' AND expires_at <= now() AND ($1::text IS NULL OR tenant_id=$1)
+            RETURNING *
+            "#,
+        )
+        .bind(tenant)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut reservations = Vec::with_capacity(rows.len());
+        for row in rows {
+            let reservation = Reservation::from_row(&row)?;
+            let tenant: String = row.get("tenant_id");
+            self.clone()
+                .with_tenant(tenant)
+                .promote_waitlist(&mut tx, &reservation.resource_id)
+                .await?;
+            reservations.push(reservation);
+        }
+        tx.commit().await?;
+
+        Ok(reservations)
+    }
+
+    // promote the waiting entries of a resource, oldest first, which fit now, an entry keeps
+    // waiting while it conflicts, breaks a booking rule or exceeds a quota of its user
+    async fn promote_waitlist(
+        &self,
+        conn: &mut PgConnection,
+        resource_id: &str,
+    ) -> Result<(), abi::Error> {
+        // a reservation which is put on the waitlist holds this lock too,
+        // so it's either promoted here or it's reserved right away
+        sqlx::query("SELECT 1 FROM rsvp.resources WHERE tenant_id=$1 AND id=$2 FOR UPDATE")
+            .bind(&self.tenant)
+            .bind(resource_id)
+            .execute(&mut *conn)
+            .await?;
+
+        let waiting: Vec<(WaitlistId, String, DateTime<Utc>, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+            SELECT id, user_id, lower(timespan), upper(timespan) FROM rsvp.waitlist
+            WHERE tenant_id=$1 AND resource_id=$2 AND upper(timespan) > now()
+            ORDER BY id
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(resource_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        for (id, user_id, start, end) in waiting {
+            if self.check_policy(resource_id, start, end).is_err() {
+                continue;
+            }
+            match self
+                .check_quota(conn, &user_id, resource_id, start, end, None)
+                .await
+            {
+                Ok(()) => {}
+                Err(abi::Error::QuotaExceeded { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+
+            let mut savepoint = conn.begin().await?;
+            let result = sqlx::query(
+                r#"
+                INSERT INTO rsvp.reservations (tenant_id, user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, waitlist_id)
+                SELECT tenant_id, user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, id
+                FROM rsvp.waitlist WHERE id=$1
+                "#,
+            )
+            .bind(id)
+            .execute(&mut *savepoint)
+            .await;
+            match result.map_err(abi::Error::from) {
+                Ok(_) => {}
+                Err(abi::Error::ConflictReservation(_) | abi::Error::ResourceNotAvailable(_)) => {
+                    savepoint.rollback().await?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            sqlx::query("DELETE FROM rsvp.waitlist WHERE id=$1")
+                .bind(id)
+                .execute(&mut *savepoint)
+                .await?;
+            savepoint.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    fn check_policy(
+        &self,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), abi::Error> {
+        self.policy
+            .policy_for(resource_id)
+            .check(start, end, Utc::now())
+    }
+
+    // reserve a reservation with the given connection, which must be inside a transaction,
+    // or put it on the waitlist if it conflicts
+    async fn insert_or_wait(
+        &self,
+        conn: &mut PgConnection,
+        rsvp: abi::Reservation,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        // promotions take this lock too, so a timespan freed meanwhile is either
+        // reserved here or the waiting entry is promoted
+        sqlx::query("SELECT 1 FROM rsvp.resources WHERE tenant_id=$1 AND id=$2 FOR UPDATE")
+            .bind(&self.tenant)
+            .bind(&rsvp.resource_id)
+            .execute(&mut *conn)
+            .await?;
+
+        let mut savepoint = conn.begin().await?;
+        match self.insert(&mut savepoint, rsvp.clone(), None).await {
+            Ok(rsvp) => {
+                savepoint.commit().await?;
+                Ok(ReserveOutcome::Reserved(Box::new(rsvp)))
+            }
+            Err(abi::Error::ConflictReservation(_)) => {
+                savepoint.rollback().await?;
+
+                let id: WaitlistId = sqlx::query(
+                    r#"
+                    INSERT INTO rsvp.waitlist (user_id, resource_id, timespan, note, seats, buffer_before, buffer_after, tenant_id)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    RETURNING id
+                    "#,
+                )
+                .bind(&rsvp.user_id)
+                .bind(&rsvp.resource_id)
+                .bind(rsvp.timespan()?)
+                .bind(&rsvp.note)
+                .bind(rsvp.seats())
+                .bind(rsvp.buffer_before())
+                .bind(rsvp.buffer_after())
+                .bind(&self.tenant)
+                .fetch_one(&mut *conn)
+                .await?
+                .get(0);
+
+                Ok(ReserveOutcome::Waitlisted(id))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // the outcome of a change made before with its idempotency key, if it has one,
+    // a retry sent while the first request is still running waits for its outcome
+    async fn replayed<T: Outcome>(
+        &self,
+        conn: &mut PgConnection,
+        keyed: Option<&Keyed>,
+    ) -> Result<Option<T>, abi::Error> {
+        let Some(keyed) = keyed else {
+            return Ok(None);
+        };
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1), hashtext($2))")
+            .bind(&self.tenant)
+            .bind(&keyed.key)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM rsvp.idempotency_keys WHERE created_at <= now() - $1")
+            .bind(self.idempotency_ttl)
+            .execute(&mut *conn)
+            .await?;
+
+        let stored: Option<(Vec<u8>, Vec<u8>)> = sqlx::query_as(
+            "SELECT request_hash, response FROM rsvp.idempotency_keys WHERE tenant_id=$1 AND actor=$2 AND key=$3",
+        )
+        .bind(&self.tenant)
+        .bind(self.actor.as_deref().unwrap_or_default())
+        .bind(&keyed.key)
+        .fetch_optional(conn)
+        .await?;
+
+        stored
+            .map(|(stored_hash, response)| replay(&stored_hash, &response, &keyed.hash))
+            .transpose()
+    }
+
+    // keep the outcome of a change made with an idempotency key
+    async fn remember(
+        &self,
+        conn: &mut PgConnection,
+        keyed: Option<&Keyed>,
+        outcome: &impl Outcome,
+    ) -> Result<(), abi::Error> {
+        let Some(keyed) = keyed else {
+            return Ok(());
+        };
+        sqlx::query(
+            "INSERT INTO rsvp.idempotency_keys (tenant_id, actor, key, request_hash, response) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&self.tenant)
+        .bind(self.actor.as_deref().unwrap_or_default())
+        .bind(&keyed.key)
+        .bind(&keyed.hash)
+        .bind(outcome.encode())
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    // lock the reservation until the transaction ends, so it can't change between
+    // checking its version and updating it
+    async fn check_version(
+        &self,
+        conn: &mut PgConnection,
+        rsvp: crate::ReservationId,
+        expected: Option<i64>,
+    ) -> Result<(), abi::Error> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+
+        let actual: i64 = sqlx::query(
+            "SELECT version FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2 FOR UPDATE",
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(conn)
+        .await?
+        .get(0);
+        if actual != expected {
+            return Err(abi::Error::VersionMismatch { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    // check the quotas of the user for a reservation of the resource, excluding the
+    // reservation being changed
+    async fn check_quota(
+        &self,
+        conn: &mut PgConnection,
+        user_id: &str,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        exclude: Option<crate::ReservationId>,
+    ) -> Result<(), abi::Error> {
+        let scopes = self.quota.scopes_for(resource_id);
+        if scopes.is_empty() {
+            return Ok(());
+        }
+
+        // reservations of the same user are checked one by one until the transaction ends,
+        // so concurrent ones can't exceed the quotas together
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1), hashtext($2))")
+            .bind(&self.tenant)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await?;
+
+        for scope in scopes {
+            let (active, weekly) = self
+                .quota_count(&mut *conn, user_id, scope.resources, start, exclude)
+                .await?;
+            scope
+                .quota
+                .check(scope.name, active, weekly, end > Utc::now(), end - start)?;
+        }
+
+        Ok(())
+    }
+
+    // count the active reservations of the user, and the time booked by the ones starting
+    // in the week of `week`
+    async fn quota_count<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        user_id: &str,
+        resources: Option<&[String]>,
+        week: DateTime<Utc>,
+        exclude: Option<crate::ReservationId>,
+    ) -> Result<(i64, TimeDelta), abi::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                count(*) FILTER (WHERE upper(timespan) > now()),
+                COALESCE(EXTRACT(EPOCH FROM sum(upper(timespan) - lower(timespan))
+                    FILTER (WHERE date_trunc('week', lower(timespan), 'UTC') = date_trunc('week', $3::timestamptz, 'UTC'))), 0)::bigint
+            FROM rsvp.reservations
+            WHERE user_id=$1 AND status<>'cancelled'
+                AND ($2::text[] IS NULL OR resource_id=ANY($2))
+                AND id IS DISTINCT FROM $4 AND tenant_id=$5
+            "#,
+        )
+        .bind(user_id)
+        .bind(resources.map(|r| r.to_vec()))
+        .bind(week)
+        .bind(exclude)
+        .bind(&self.tenant)
+        .fetch_one(executor)
+        .await?;
+
+        Ok((row.get(0), TimeDelta::seconds(row.get(1))))
+    }
+}
+
+impl Rsvp for ReservationManager {
+    async fn reserve(&self, rsvp: abi::Reservation) -> Result<abi::Reservation, abi::Error> {
+        let mut tx = self.begin().await?;
+        let rsvp = self.insert(&mut tx, rsvp, None).await?;
+        tx.commit().await?;
 
         Ok(rsvp)
     }
 
+    async fn reserve_or_wait(&self, rsvp: abi::Reservation) -> Result<ReserveOutcome, abi::Error> {
+        let mut tx = self.begin().await?;
+        let outcome = self.insert_or_wait(&mut tx, rsvp).await?;
+        tx.commit().await?;
+
+        Ok(outcome)
+    }
+
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: abi::Reservation,
+        waitlist: bool,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        let keyed = keyed(Some(&key), || reserve_fingerprint(&rsvp, waitlist))?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+
+        let outcome = if waitlist {
+            self.insert_or_wait(&mut tx, rsvp).await?
+        } else {
+            ReserveOutcome::Reserved(Box::new(self.insert(&mut tx, rsvp, None).await?))
+        };
+        self.remember(&mut tx, keyed.as_ref(), &outcome).await?;
+        tx.commit().await?;
+
+        Ok(outcome)
+    }
+
+    async fn reserve_many(
+        &self,
+        rsvps: Vec<abi::Reservation>,
+    ) -> Result<Vec<abi::Reservation>, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || batch_fingerprint(&rsvps))?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+
+        // the transaction is rolled back on drop if any reservation fails
+        let mut reserved = Vec::with_capacity(rsvps.len());
+        for (index, rsvp) in rsvps.into_iter().enumerate() {
+            let rsvp = self.insert(&mut tx, rsvp, None).await.map_err(|e| {
+                abi::Error::BatchReservation {
+                    index,
+                    source: Box::new(e),
+                }
+            })?;
+            reserved.push(rsvp);
+        }
+
+        self.remember(&mut tx, keyed.as_ref(), &reserved).await?;
+        tx.commit().await?;
+
+        Ok(reserved)
+    }
+
+    async fn reserve_series(
+        &self,
+        series: abi::ReservationSeries,
+        mode: SeriesConflictMode,
+    ) -> Result<(abi::ReservationSeries, Vec<Reservation>, Vec<Reservation>), abi::Error> {
+        let occurrences = series.occurrences()?;
+        let Some(first) = occurrences.first() else {
+            return Err(abi::Error::InvalidRecurrenceRule(
+                "no occurrence".to_string(),
+            ));
+        };
+        let timespan: PgRange<DateTime<Utc>> = first.timespan()?;
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            series_fingerprint(&series, mode)
+        })?;
+
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+
+        let id: i64 = sqlx::query(
+            r#"
+            INSERT INTO rsvp.reservation_series (user_id, resource_id, timespan, rrule, note, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+        )
+        .bind(&series.user_id)
+        .bind(&series.resource_id)
+        .bind(timespan)
+        .bind(&series.rrule)
+        .bind(&series.note)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
+        .await?
+        .get(0);
+
+        let mut reserved = Vec::with_capacity(occurrences.len());
+        let mut conflicts = Vec::new();
+        let mut conflict_infos = Vec::new();
+        for mut rsvp in occurrences {
+            rsvp.series_id = Some(id);
+
+            // every occurrence has its own savepoint,
+            // so a conflict doesn't abort the whole transaction
+            let mut savepoint = tx.begin().await?;
+            match self.insert(&mut savepoint, rsvp.clone(), Some(id)).await {
+                Ok(rsvp) => {
+                    savepoint.commit().await?;
+                    reserved.push(rsvp);
+                }
+                Err(abi::Error::ConflictReservation(info)) => {
+                    savepoint.rollback().await?;
+                    conflict_infos.push(info);
+                    conflicts.push(rsvp);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if mode == SeriesConflictMode::Fail && !conflict_infos.is_empty() {
+            return Err(abi::Error::ConflictSeries(conflict_infos));
+        }
+
+        let outcome = (abi::ReservationSeries { id, ..series }, reserved, conflicts);
+        self.remember(&mut tx, keyed.as_ref(), &outcome).await?;
+        tx.commit().await?;
+
+        Ok(outcome)
+    }
+
     async fn delete(&self, rsvp: crate::ReservationId) -> Result<(), abi::Error> {
-        let _ = sqlx::query(
+        let mut tx = self.begin().await?;
+        let deleted: Reservation = sqlx::query_as(
             r#"
-            DELETE FROM rsvp.reservations WHERE id=$1
+            DELETE FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2
             RETURNING *
             "#,
         )
         .bind(rsvp)
-        .fetch_one(&self.pool)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
         .await?;
+        if deleted.status != ReservationStatus::Cancelled as i32 {
+            self.promote_waitlist(&mut tx, &deleted.resource_id).await?;
+        }
+        tx.commit().await?;
 
         Ok(())
     }
@@ -54,17 +653,240 @@ impl Rsvp for ReservationManager {
     async fn change_status(
         &self,
         rsvp: crate::ReservationId,
+        expected_version: Option<i64>,
     ) -> Result<abi::Reservation, abi::Error> {
-        // if a reservation is pending, it will be confirmed
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            confirm_fingerprint(rsvp, expected_version)
+        })?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        // if a reservation is pending and not expired, it will be confirmed
+        let reservation: Option<Reservation> = sqlx::query_as(
+            r#"
+            UPDATE rsvp.reservations SET status='confirmed', expires_at=NULL
+            WHERE id=$1 AND tenant_id=$2 AND status='pending' AND (expires_at IS NULL OR expires_at > now())
+            RETURNING *
+            "#,
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(reservation) = reservation {
+            self.remember(&mut tx, keyed.as_ref(), &reservation).await?;
+            tx.commit().await?;
+            return Ok(reservation);
+        }
+
+        // tell an expired hold apart from a reservation that can't be confirmed
+        let expired: bool = sqlx::query(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2 AND status='pending' AND expires_at <= now())
+            "#,
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
+        .await?
+        .get(0);
+
+        if expired {
+            Err(abi::Error::HoldExpired)
+        } else {
+            Err(abi::Error::NotFound)
+        }
+    }
+
+    async fn cancel(
+        &self,
+        rsvp: crate::ReservationId,
+        reason: Option<String>,
+        expected_version: Option<i64>,
+    ) -> Result<abi::Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            cancel_fingerprint(rsvp, reason.as_ref(), expected_version)
+        })?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        // a cancelled reservation can't be cancelled again
         let reservation: Reservation = sqlx::query_as(
             r#"
-            UPDATE rsvp.reservations SET status='confirmed' WHERE id=$1 AND status='pending'
+            UPDATE rsvp.reservations SET status='cancelled', cancel_reason=$1
+            WHERE id=$2 AND tenant_id=$3 AND status<>'cancelled'
             RETURNING *
             "#,
         )
+        .bind(reason)
         .bind(rsvp)
-        .fetch_one(&self.pool)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
         .await?;
+        self.promote_waitlist(&mut tx, &reservation.resource_id)
+            .await?;
+
+        self.remember(&mut tx, keyed.as_ref(), &reservation).await?;
+        tx.commit().await?;
+
+        Ok(reservation)
+    }
+
+    async fn reschedule(
+        &self,
+        rsvp: crate::ReservationId,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        expected_version: Option<i64>,
+    ) -> Result<abi::Reservation, abi::Error> {
+        if start >= end {
+            return Err(abi::Error::InvalidTimespan);
+        }
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            reschedule_fingerprint(rsvp, start, end, expected_version)
+        })?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        let current: Reservation = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2
+            "#,
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
+        .await?;
+        self.check_policy(&current.resource_id, start, end)?;
+        self.check_quota(
+            &mut tx,
+            &current.user_id,
+            &current.resource_id,
+            start,
+            end,
+            Some(rsvp),
+        )
+        .await?;
+        let timespan: PgRange<DateTime<Utc>> = (start..end).into();
+
+        // reservations_capacity_trigger rejects the new timespan if it conflicts,
+        // including the buffers around it
+        let mut savepoint = tx.begin().await?;
+        let result = sqlx::query_as(
+            r#"
+            UPDATE rsvp.reservations SET timespan=$1 WHERE id=$2 AND tenant_id=$3 AND status<>'cancelled'
+            RETURNING *
+            "#,
+        )
+        .bind(timespan)
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(&mut *savepoint)
+        .await;
+        let reservation: Reservation = match result {
+            Ok(reservation) => {
+                savepoint.commit().await?;
+                reservation
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                let rescheduled = abi::Reservation {
+                    start: Some(datetime_to_timestamp(start)),
+                    end: Some(datetime_to_timestamp(end)),
+                    ..current
+                };
+                return Err(self.conflict_error(&mut tx, &rescheduled, e).await);
+            }
+        };
+
+        self.remember(&mut tx, keyed.as_ref(), &reservation).await?;
+        tx.commit().await?;
+
+        Ok(reservation)
+    }
+
+    async fn change_resource(
+        &self,
+        rsvp: crate::ReservationId,
+        resource_id: String,
+        expected_version: Option<i64>,
+    ) -> Result<abi::Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            change_resource_fingerprint(rsvp, &resource_id, expected_version)
+        })?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        let current: Reservation = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2
+            "#,
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
+        .await?;
+        // the booking rules and the quotas of the new resource apply
+        let start = timestamp_to_datetime(current.start.as_ref().unwrap());
+        let end = timestamp_to_datetime(current.end.as_ref().unwrap());
+        self.check_policy(&resource_id, start, end)?;
+        self.check_quota(
+            &mut tx,
+            &current.user_id,
+            &resource_id,
+            start,
+            end,
+            Some(rsvp),
+        )
+        .await?;
+
+        // reservations_capacity_trigger rejects the new resource if it conflicts
+        let mut savepoint = tx.begin().await?;
+        let result = sqlx::query_as(
+            r#"
+            UPDATE rsvp.reservations SET resource_id=$1 WHERE id=$2 AND tenant_id=$3 AND status<>'cancelled'
+            RETURNING *
+            "#,
+        )
+        .bind(&resource_id)
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_one(&mut *savepoint)
+        .await;
+        let reservation: Reservation = match result {
+            Ok(reservation) => {
+                savepoint.commit().await?;
+                reservation
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                let moved = abi::Reservation {
+                    resource_id,
+                    ..current
+                };
+                return Err(self.conflict_error(&mut tx, &moved, e).await);
+            }
+        };
+
+        self.remember(&mut tx, keyed.as_ref(), &reservation).await?;
+        tx.commit().await?;
 
         Ok(reservation)
     }
@@ -73,34 +895,107 @@ impl Rsvp for ReservationManager {
         &self,
         rsvp: crate::ReservationId,
         note: String,
+        expected_version: Option<i64>,
     ) -> Result<abi::Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            update_fingerprint(rsvp, &note, expected_version)
+        })?;
+        let mut tx = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            tx.commit().await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
         let reservation: Reservation = sqlx::query_as(
             r#"
-            UPDATE rsvp.reservations SET note=$1 WHERE id=$2
+            UPDATE rsvp.reservations SET note=$1 WHERE id=$2 AND tenant_id=$3
             RETURNING *
             "#,
         )
         .bind(note)
         .bind(rsvp)
-        .fetch_one(&self.pool)
+        .bind(&self.tenant)
+        .fetch_one(&mut *tx)
         .await?;
 
+        self.remember(&mut tx, keyed.as_ref(), &reservation).await?;
+        tx.commit().await?;
+
         Ok(reservation)
     }
 
     async fn get(&self, rsvp: crate::ReservationId) -> Result<abi::Reservation, abi::Error> {
         let reservation: Reservation = sqlx::query_as(
             r#"
-            SELECT * FROM rsvp.reservations WHERE id=$1
+            SELECT * FROM rsvp.reservations WHERE id=$1 AND tenant_id=$2
             "#,
         )
         .bind(rsvp)
+        .bind(&self.tenant)
         .fetch_one(&self.pool)
         .await?;
 
         Ok(reservation)
     }
 
+    async fn get_history(
+        &self,
+        rsvp: crate::ReservationId,
+    ) -> Result<Vec<abi::ReservationChange>, abi::Error> {
+        // a row for the reservation before and one for after every change, restored
+        // from the snapshots taken by the trigger, a missing snapshot gives a NULL id
+        let rows = sqlx::query(
+            r#"
+            SELECT c.id AS change_id, c.op, c.changed_at, c.actor, s.after, r.*
+            FROM rsvp.reservation_changes c
+            CROSS JOIN LATERAL (VALUES
+                (FALSE, c.old_reservation),
+                (TRUE, CASE WHEN c.op = 'delete' THEN NULL ELSE c.reservation END)
+            ) s(after, snapshot),
+            jsonb_populate_record(NULL::rsvp.reservations, s.snapshot) r
+            WHERE c.reservation_id=$1 AND c.reservation->>'tenant_id'=$2 ORDER BY c.id, s.after
+            "#,
+        )
+        .bind(rsvp)
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut changes: Vec<abi::ReservationChange> = Vec::new();
+        for row in rows {
+            let id: i32 = row.get("change_id");
+            if changes.last().map(|change| change.id) != Some(id as i64) {
+                let op: ReservationUpdateType = row.get("op");
+                changes.push(abi::ReservationChange {
+                    id: id as i64,
+                    op: op as i32,
+                    changed_at: row
+                        .get::<Option<DateTime<Utc>>, _>("changed_at")
+                        .map(datetime_to_timestamp),
+                    actor: row.get("actor"),
+                    before: None,
+                    after: None,
+                });
+            }
+            if row.get::<Option<i64>, _>("id").is_none() {
+                continue;
+            }
+            let snapshot = Some(Reservation::from_row(&row)?);
+            let change = changes.last_mut().unwrap();
+            if row.get("after") {
+                change.after = snapshot;
+            } else {
+                change.before = snapshot;
+            }
+        }
+
+        if changes.is_empty() {
+            return Err(abi::Error::NotFound);
+        }
+        Ok(changes)
+    }
+
     async fn query(
         &self,
         para: abi::ReservationQuery,
@@ -110,6 +1005,7 @@ impl Rsvp for ReservationManager {
             .unwrap_or(ReservationStatus::Unknown)
             .to_string();
         let pool = self.pool.clone();
+        let tenant = self.tenant.clone();
         // if user_id is null, find all reservations within during for the resource
         // if resource_id is null, find all reservations within during for the user
         // if both are null, find all reservations within during
@@ -120,9 +1016,10 @@ impl Rsvp for ReservationManager {
         tokio::spawn(async move {
             let mut query = sqlx::query_as(
                 r#"
-                SELECT * FROM rsvp.query($1, $2, $3, $4::rsvp.reservation_status, $5, $6, $7)
+                SELECT * FROM rsvp.query($1, $2, $3, $4, $5::rsvp.reservation_status, $6, $7, $8)
                 "#,
             )
+            .bind(tenant)
             .bind(para.user_id)
             .bind(para.resource_id)
             .bind(timespan)
@@ -157,9 +1054,10 @@ impl Rsvp for ReservationManager {
         // filter by user_id, resource_id, status and order by id
         let mut query: Vec<Reservation> = sqlx::query_as(
             r#"
-            SELECT * FROM rsvp.filter($1, $2, $3::rsvp.reservation_status, $4, $5, $6)
+            SELECT * FROM rsvp.filter($1, $2, $3, $4::rsvp.reservation_status, $5, $6, $7)
             "#,
         )
+        .bind(&self.tenant)
         .bind(para.user_id)
         .bind(para.resource_id)
         .bind(status)
@@ -189,310 +1087,284 @@ impl Rsvp for ReservationManager {
 
         Ok((pager, query))
     }
-}
-
-#[cfg(test)]
-mod test {
-    use abi::error::conflict::{ReservationConflict, ReservationConflictInfo};
-    use chrono::Duration;
-    use sqlx::PgPool;
-
-    use super::*;
-
-    fn default_rsvp() -> abi::Reservation {
-        abi::Reservation::new_pendding(
-            "user",
-            "resource",
-            DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
-            DateTime::parse_from_rfc3339("2021-01-02T00:00:00Z").unwrap(),
-            "note",
-        )
-    }
-
-    #[sqlx::test(migrations = "../migrations")]
-    async fn reserve_should_work_with_valid_timespan(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
 
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        assert!(rsvp.id != 0);
+    async fn release_expired(&self) -> Result<Vec<abi::Reservation>, abi::Error> {
+        self.release_expired_of(Some(&self.tenant)).await
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn reserve_should_fail_with_invalid_timespan(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = abi::Reservation::new_pendding(
-            "user",
-            "resource",
-            DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
-            DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
-            "note",
-        );
-
-        let result = manager.reserve(rsvp).await;
-
-        assert!(matches!(result, Err(abi::Error::InvalidTimespan)));
-    }
-
-    #[sqlx::test(migrations = "../migrations")]
-    async fn reserve_should_fail_with_conflicting_timespan(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let conflict_start = DateTime::parse_from_rfc3339("2021-01-01T12:00:00Z").unwrap();
-        let conflict_end = DateTime::parse_from_rfc3339("2021-01-02T12:00:00Z").unwrap();
-
-        let rsvp = default_rsvp();
-
-        let result = manager.reserve(rsvp).await.unwrap();
-        assert!(result.id != 0);
-
-        let rsvp = abi::Reservation::new_pendding(
-            "user",
-            "resource",
-            conflict_start,
-            conflict_end,
-            "note",
-        );
-
-        let result = manager.reserve(rsvp).await;
-
-        assert!(matches!(result, Err(abi::Error::ConflictReservation(_))));
-        match result.unwrap_err() {
-            abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(
-                ReservationConflict { new, old },
-            )) => {
-                assert_eq!(new.resource_id, "resource");
-                assert_eq!(old.resource_id, "resource");
-                assert_eq!(new.start, conflict_start);
-                assert_eq!(new.end, conflict_end);
-            }
-
-            e => {
-                eprintln!("{:?}", e);
-                panic!("Unexpected error");
-            }
-        };
-    }
-
-    #[sqlx::test(migrations = "../migrations")]
-    async fn reservation_can_be_confirmed(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let rsvp = manager.change_status(rsvp.id).await.unwrap();
-        assert_eq!(rsvp.status, abi::ReservationStatus::Confirmed as i32);
-    }
-
-    #[sqlx::test(migrations = "../migrations")]
-    async fn change_reservation_again_should_do_nothing(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let rsvp = manager.change_status(rsvp.id).await.unwrap();
-        assert_eq!(rsvp.status, abi::ReservationStatus::Confirmed as i32);
-
-        let ret = manager.change_status(rsvp.id).await;
-        assert!(matches!(ret, Err(abi::Error::NotFound)));
-    }
-
-    #[sqlx::test(migrations = "../migrations")]
-    async fn update_notes_should_work(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
+    async fn find_availability(
+        &self,
+        resource_id: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        duration: TimeDelta,
+    ) -> Result<Vec<abi::TimeSlot>, abi::Error> {
+        if start >= end || duration <= TimeDelta::zero() {
+            return Err(abi::Error::InvalidTimespan);
+        }
+        let timespan: PgRange<DateTime<Utc>> = (start..end).into();
 
-        let rsvp = manager.reserve(rsvp).await.unwrap();
+        let slots: Vec<abi::TimeSlot> = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.availability($1, $2, $3, $4)
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(resource_id)
+        .bind(timespan)
+        .bind(duration)
+        .fetch_all(&self.pool)
+        .await?;
 
-        let rsvp = manager
-            .update_notes(rsvp.id, "new note".to_string())
-            .await
-            .unwrap();
-        assert_eq!(rsvp.note, "new note");
+        Ok(slots)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn get_should_work(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
+    async fn quota_usage(
+        &self,
+        user_id: String,
+        week: DateTime<Utc>,
+    ) -> Result<Vec<abi::QuotaUsage>, abi::Error> {
+        if user_id.is_empty() {
+            return Err(abi::Error::InvalidUserId);
+        }
 
-        let rsvp1 = manager.reserve(rsvp).await.unwrap();
+        let mut usages = Vec::new();
+        for scope in self.quota.scopes() {
+            let (active, weekly) = self
+                .quota_count(&self.pool, &user_id, scope.resources, week, None)
+                .await?;
+            usages.push(scope.quota.usage(scope.name, active, weekly));
+        }
 
-        let rsvp = manager.get(rsvp1.id).await.unwrap();
-        assert_eq!(rsvp.id, rsvp.id);
+        Ok(usages)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn delete_should_work(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
+    async fn create_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
 
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        manager.delete(rsvp.id).await.unwrap();
+        let resource: abi::Resource = sqlx::query_as(
+            r#"
+            INSERT INTO rsvp.resources (capacity, buffer_before, buffer_after, id, name, location, capabilities, disabled, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING *
+            "#,
+        )
+        .bind(resource.capacity())
+        .bind(resource.buffer_before())
+        .bind(resource.buffer_after())
+        .bind(resource.id)
+        .bind(resource.name)
+        .bind(resource.location)
+        .bind(resource.capabilities)
+        .bind(resource.disabled)
+        .bind(&self.tenant)
+        .fetch_one(&self.pool)
+        .await?;
 
-        let rsvp = manager.get(rsvp.id).await;
-        assert!(matches!(rsvp, Err(abi::Error::NotFound)));
+        Ok(resource)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn delete_null_should_fail(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
+    async fn get_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        let resource: abi::Resource = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.resources WHERE tenant_id=$1 AND id=$2
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
 
-        let result = manager.delete(0).await;
-        assert!(matches!(result, Err(abi::Error::NotFound)));
+        Ok(resource)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn query_should_work(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
+    async fn update_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
 
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let query = abi::ReservationQueryBuilder::default()
-            .end(abi::utils::datetime_to_timestamp(Utc::now()))
-            .build()
-            .unwrap();
-
-        let mut query = manager.query(query).await.unwrap();
-
-        assert_eq!(query.recv().await.unwrap().unwrap().id, rsvp.id);
-        assert_eq!(query.len(), 0);
-
-        let query = abi::ReservationQueryBuilder::default()
-            .user_id("user")
-            .resource_id("resource")
-            .end(abi::utils::datetime_to_timestamp(Utc::now()))
-            .build()
-            .unwrap();
+        let resource: abi::Resource = sqlx::query_as(
+            r#"
+            UPDATE rsvp.resources SET capacity=$1, buffer_before=$2, buffer_after=$3, name=$4, location=$5, capabilities=$6, disabled=$7
+            WHERE tenant_id=$8 AND id=$9
+            RETURNING *
+            "#,
+        )
+        .bind(resource.capacity())
+        .bind(resource.buffer_before())
+        .bind(resource.buffer_after())
+        .bind(resource.name)
+        .bind(resource.location)
+        .bind(resource.capabilities)
+        .bind(resource.disabled)
+        .bind(&self.tenant)
+        .bind(resource.id)
+        .fetch_one(&self.pool)
+        .await?;
 
-        let mut query = manager.query(query).await.unwrap();
-        assert_eq!(query.recv().await.unwrap().unwrap().id, rsvp.id);
-        assert_eq!(query.len(), 0);
+        Ok(resource)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn query_should_work_with_user_id(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let _rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let query = abi::ReservationQueryBuilder::default()
-            .user_id("user1")
-            .end(abi::utils::datetime_to_timestamp(Utc::now()))
-            .build()
-            .unwrap();
-
-        let query = manager.query(query).await.unwrap();
+    async fn delete_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        let resource: abi::Resource = sqlx::query_as(
+            r#"
+            DELETE FROM rsvp.resources WHERE tenant_id=$1 AND id=$2
+            RETURNING *
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
 
-        assert_eq!(query.len(), 0);
+        Ok(resource)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn query_should_work_with_resource_id(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let _rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let query = abi::ReservationQueryBuilder::default()
-            .resource_id("resource1")
-            .end(abi::utils::datetime_to_timestamp(Utc::now()))
-            .build()
-            .unwrap();
-
-        let query = manager.query(query).await.unwrap();
+    async fn list_resources(&self) -> Result<Vec<abi::Resource>, abi::Error> {
+        let resources: Vec<abi::Resource> = sqlx::query_as(
+            r#"
+            SELECT * FROM rsvp.resources WHERE tenant_id=$1 ORDER BY id
+            "#,
+        )
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await?;
 
-        assert_eq!(query.len(), 0);
+        Ok(resources)
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn query_should_work_with_status(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
+    async fn listen(
+        &self,
+    ) -> Result<mpsc::Receiver<Result<abi::ListenResponse, abi::Error>>, abi::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen("reservation_update").await?;
 
-        let _rsvp = manager.reserve(rsvp).await.unwrap();
+        // only changes committed after the listener is ready will be sent
+        let pool = self.pool.clone();
+        let tenant = self.tenant.clone();
 
-        let query = abi::ReservationQueryBuilder::default()
-            .status(2)
-            .end(abi::utils::datetime_to_timestamp(Utc::now()))
-            .build()
-            .unwrap();
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            loop {
+                let notification = tokio::select! {
+                    _ = tx.closed() => break,
+                    notification = listener.recv() => match notification {
+                        Ok(notification) => notification,
+                        Err(e) => {
+                            let _ = tx.send(Err(e.into())).await;
+                            break;
+                        }
+                    },
+                };
+
+                // every change is notified with its id and tenant by reservations_trigger
+                let Some((id, change_tenant)) = notification.payload().split_once(':') else {
+                    continue;
+                };
+                let Ok(id) = id.parse::<i32>() else {
+                    continue;
+                };
+                if change_tenant != tenant {
+                    continue;
+                }
 
-        let query = manager.query(query).await.unwrap();
+                // the reservation is restored from the snapshot taken by the trigger
+                let change = sqlx::query(
+                    r#"
+                    SELECT c.op, r.* FROM rsvp.reservation_changes c,
+                    jsonb_populate_record(NULL::rsvp.reservations, c.reservation) r
+                    WHERE c.id = $1
+                    "#,
+                )
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .and_then(|row| {
+                    let op: ReservationUpdateType = row.get("op");
+                    Reservation::from_row(&row).map(|rsvp| abi::ListenResponse {
+                        op: op as i32,
+                        reservation: Some(rsvp),
+                    })
+                })
+                .map_err(abi::Error::from);
+                if tx.send(change).await.is_err() {
+                    break;
+                }
+            }
+        });
 
-        assert_eq!(query.len(), 0);
+        Ok(rx)
     }
+}
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn query_should_work_with_timespan(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let _rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let query = abi::ReservationQueryBuilder::default()
-            .start(abi::utils::datetime_to_timestamp(Utc::now()))
-            .end(abi::utils::datetime_to_timestamp(
-                Utc::now() + Duration::try_days(1).unwrap(),
-            ))
-            .build()
-            .unwrap();
-
-        let query = manager.query(query).await.unwrap();
+#[cfg(test)]
+mod test {
+    use sqlx::PgPool;
 
-        assert_eq!(query.len(), 0);
+    use super::*;
+    use crate::suite::{default_rsvp, suite};
+
+    suite!(
+        #[sqlx::test(
+            migrations = "../migrations",
+            fixtures(path = "../../fixtures", scripts("resources"))
+        )]
+        (pool: PgPool) => ReservationManager::new(pool)
+    );
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn other_foreign_keys_should_not_mean_resource_in_use(pool: PgPool) {
+        // a reservation of a series which doesn't exist violates another foreign key
+        let err = sqlx::query(
+            r#"
+            INSERT INTO rsvp.reservations (user_id, resource_id, timespan, series_id)
+            VALUES ('user', 'resource', tstzrange(now(), now() + interval '1 hour'), 42)
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            abi::Error::from(err),
+            abi::Error::DatabaseError(_)
+        ));
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn filter_should_work(pool: PgPool) {
-        let manager = ReservationManager { pool: pool.clone() };
-
-        let rsvp = default_rsvp();
-
-        let rsvp = manager.reserve(rsvp).await.unwrap();
-
-        let filter = abi::ReservationFilterBuilder::default().build().unwrap();
-
-        let filter = manager.filter(filter).await.unwrap();
-        assert!(filter.0.next.is_none());
-        assert_eq!(filter.1.len(), 1);
-        assert_eq!(filter.1[0].id, rsvp.id);
-
-        let filter = abi::ReservationFilterBuilder::default()
-            .user_id("user")
-            .resource_id("resource")
-            .build()
-            .unwrap();
-
-        let filter = manager.filter(filter).await.unwrap();
-        assert_eq!(filter.1.len(), 1);
-        assert_eq!(filter.1[0].id, rsvp.id);
-
-        let filter = abi::ReservationFilterBuilder::default()
-            .user_id("user1")
-            .resource_id("resource")
-            .build()
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn listen_should_receive_changes_committed_late(pool: PgPool) {
+        let manager = ReservationManager::new(pool.clone());
+        let other = manager.clone().with_tenant("other");
+        other
+            .create_resource(abi::Resource::new("resource", "resource"))
+            .await
             .unwrap();
+        let mut changes = manager.listen().await.unwrap();
 
-        let filter = manager.filter(filter).await.unwrap();
-        assert_eq!(filter.1.len(), 0);
+        // the change of the transaction committed last has the lower id
+        let mut late = pool.begin().await.unwrap();
+        let late_id: i64 = sqlx::query(
+            r#"
+            INSERT INTO rsvp.reservations (user_id, resource_id, timespan, note)
+            VALUES ('user', 'room', tstzrange(now(), now() + interval '1 hour'), '')
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&mut *late)
+        .await
+        .unwrap()
+        .get(0);
+        other.reserve(default_rsvp()).await.unwrap();
+        let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+        late.commit().await.unwrap();
+
+        // the change of the other tenant isn't sent
+        let change = changes.recv().await.unwrap().unwrap();
+        assert_eq!(change.reservation.unwrap().id, rsvp.id);
+        let change = changes.recv().await.unwrap().unwrap();
+        assert_eq!(change.reservation.unwrap().id, late_id);
     }
 }
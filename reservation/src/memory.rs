@@ -0,0 +1,1772 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use abi::config::{IdempotencyConfig, PolicyConfig, QuotaConfig};
+use abi::utils::{datetime_to_timestamp, timestamp_to_datetime};
+use abi::{Reservation, ReservationStatus, ReservationUpdateType, SeriesConflictMode};
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::{mpsc, watch};
+
+use crate::booking::{
+    blocked_span, conflict, free_spans, intersect, is_cancelled, matches, normalize, overlaps,
+    page_size, pager, peak_seats, quota_count, slots, span, timespan, Span,
+};
+use crate::idempotency::{
+    batch_fingerprint, cancel_fingerprint, change_resource_fingerprint, confirm_fingerprint, keyed,
+    replay, reschedule_fingerprint, reserve_fingerprint, series_fingerprint, update_fingerprint,
+    Keyed, Outcome,
+};
+use crate::{ReservationId, ReserveOutcome, Rsvp, WaitlistId, DEFAULT_TENANT};
+
+/// A `Rsvp` keeping everything in memory, for tests and for running without a database.
+/// It follows the semantics of [`ReservationManager`](crate::ReservationManager): the same
+/// conflicts, status transitions, waitlist promotions, history and paging. The clones of a
+/// manager share their data, like the clones of a `ReservationManager` share their pool.
+/// It has no indexes: each `query` scans every reservation, then clones and sorts all the
+/// ones of the tenant it matches, not only those of the page.
+#[derive(Debug, Clone)]
+pub struct InMemoryReservationManager {
+    shared: Arc<Shared>,
+    policy: Arc<PolicyConfig>,
+    quota: Arc<QuotaConfig>,
+    // how long the outcome of a request with an idempotency key is kept
+    idempotency_ttl: TimeDelta,
+    // who the changes are made by, recorded in the history of the reservations
+    actor: Option<String>,
+    // the tenant whose data the manager sees and changes
+    tenant: String,
+    // the key of the request the changes are made once for
+    idempotency_key: Option<String>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    data: Mutex<Data>,
+    // the id of the last change, sent to the listeners after every committed change
+    updates: watch::Sender<i64>,
+}
+
+// everything the database keeps, the ids are shared by all tenants like its sequences
+#[derive(Debug, Default)]
+struct Data {
+    reservations: BTreeMap<ReservationId, Entry>,
+    // by tenant and id
+    resources: BTreeMap<(String, String), abi::Resource>,
+    waitlist: BTreeMap<WaitlistId, Waiting>,
+    // by tenant, actor and key
+    idempotency_keys: HashMap<(String, String, String), StoredOutcome>,
+    changes: Vec<Change>,
+    last_reservation_id: ReservationId,
+    last_waitlist_id: WaitlistId,
+    last_series_id: i64,
+    last_change_id: i64,
+    // how to undo the changes of the running write
+    undo: Vec<Undo>,
+}
+
+// the previous value of a row changed by the running write
+#[derive(Debug)]
+enum Undo {
+    Reservation(ReservationId, Option<Entry>),
+    Resource((String, String), Option<abi::Resource>),
+    Waiting(WaitlistId, Option<Waiting>),
+    Outcome((String, String, String), Option<StoredOutcome>),
+}
+
+// what the running write can only append to, restored by truncating
+#[derive(Debug, Clone, Copy)]
+struct Checkpoint {
+    changes: usize,
+    last_reservation_id: ReservationId,
+    last_waitlist_id: WaitlistId,
+    last_series_id: i64,
+    last_change_id: i64,
+}
+
+// a write, which is undone if it's dropped before it's committed, e.g. when it fails or panics
+struct Tx<'a> {
+    data: &'a mut Data,
+    checkpoint: Checkpoint,
+    committed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    tenant: String,
+    rsvp: Reservation,
+    // the timespan extended by the buffers, which no other reservation may take
+    blocked: Span,
+}
+
+#[derive(Debug, Clone)]
+struct Waiting {
+    tenant: String,
+    rsvp: Reservation,
+}
+
+#[derive(Debug, Clone)]
+struct StoredOutcome {
+    created_at: DateTime<Utc>,
+    request_hash: Vec<u8>,
+    response: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct Change {
+    id: i64,
+    tenant: String,
+    reservation_id: ReservationId,
+    op: ReservationUpdateType,
+    changed_at: DateTime<Utc>,
+    actor: Option<String>,
+    before: Option<Reservation>,
+    after: Option<Reservation>,
+}
+
+impl Default for InMemoryReservationManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryReservationManager {
+    pub fn new() -> Self {
+        let (updates, _) = watch::channel(0);
+        Self {
+            shared: Arc::new(Shared {
+                data: Mutex::new(Data::default()),
+                updates,
+            }),
+            policy: Default::default(),
+            quota: Default::default(),
+            idempotency_ttl: TimeDelta::try_seconds(IdempotencyConfig::default().ttl as i64)
+                .unwrap(),
+            actor: None,
+            tenant: DEFAULT_TENANT.to_string(),
+            idempotency_key: None,
+        }
+    }
+
+    /// Enforce the booking rules when reserving or rescheduling
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Enforce the quotas of the users when reserving or rescheduling
+    pub fn with_quota(mut self, quota: QuotaConfig) -> Self {
+        self.quota = Arc::new(quota);
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Keep the outcome of a request with an idempotency key for the configured time
+    pub fn with_idempotency(mut self, idempotency: IdempotencyConfig) -> Self {
+        self.idempotency_ttl =
+            TimeDelta::try_seconds(idempotency.ttl as i64).unwrap_or(TimeDelta::MAX);
+        self
+    }
+
+    pub async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        self.write(|data| self.release_expired_of(data, None))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Data> {
+        // a change is undone if it panics, so the data is consistent even if a panic
+        // poisoned the lock
+        self.shared
+            .data
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Data) -> T) -> T {
+        f(&self.lock())
+    }
+
+    // make a change like a transaction: it's undone unless it succeeds, then the listeners
+    // are told about it
+    fn write<T>(
+        &self,
+        f: impl FnOnce(&mut Data) -> Result<T, abi::Error>,
+    ) -> Result<T, abi::Error> {
+        let mut data = self.lock();
+        let last_change_id = data.last_change_id;
+        let tx = Tx::begin(&mut data);
+        let result = f(tx.data)?;
+        tx.commit();
+
+        if data.last_change_id != last_change_id {
+            self.shared.updates.send_replace(data.last_change_id);
+        }
+        Ok(result)
+    }
+
+    // the outcome of a change made before with its idempotency key, if it has one,
+    // after dropping the expired outcomes
+    fn replayed<T: Outcome>(
+        &self,
+        data: &mut Data,
+        keyed: Option<&Keyed>,
+    ) -> Result<Option<T>, abi::Error> {
+        let Some(keyed) = keyed else {
+            return Ok(None);
+        };
+        let expired = Utc::now().checked_sub_signed(self.idempotency_ttl);
+        data.idempotency_keys
+            .retain(|_, stored| expired.is_none_or(|expired| stored.created_at > expired));
+
+        data.idempotency_keys
+            .get(&self.outcome_key(&keyed.key))
+            .map(|stored| replay(&stored.request_hash, &stored.response, &keyed.hash))
+            .transpose()
+    }
+
+    // keep the outcome of a change made with an idempotency key
+    fn remember(&self, data: &mut Data, keyed: Option<Keyed>, outcome: &impl Outcome) {
+        if let Some(keyed) = keyed {
+            let stored = StoredOutcome {
+                created_at: Utc::now(),
+                request_hash: keyed.hash,
+                response: outcome.encode(),
+            };
+            data.put_outcome(self.outcome_key(&keyed.key), stored);
+        }
+    }
+
+    // the outcomes of every tenant and actor are apart
+    fn outcome_key(&self, key: &str) -> (String, String, String) {
+        let actor = self.actor.clone().unwrap_or_default();
+        (self.tenant.clone(), actor, key.to_string())
+    }
+
+    // insert a reservation, as an occurrence of the series if there is one,
+    // the data is left untouched if it fails
+    fn insert(
+        &self,
+        data: &mut Data,
+        rsvp: Reservation,
+        series_id: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        rsvp.validate()?;
+
+        let (start, end) = span(&rsvp)?;
+        self.check_policy(&rsvp.resource_id, start, end)?;
+        self.check_quota(data, &rsvp.user_id, &rsvp.resource_id, start, end, None)?;
+        let status = ReservationStatus::try_from(rsvp.status).unwrap_or(ReservationStatus::Pending);
+
+        let rsvp = Reservation {
+            // not an id of any reservation, until it's inserted
+            id: 0,
+            status: status as i32,
+            seats: rsvp.seats(),
+            series_id,
+            waitlist_id: None,
+            version: 1,
+            ..rsvp
+        };
+        let blocked = self.block(data, &self.tenant, &rsvp)?;
+        check_resource(data, &self.tenant, &rsvp.resource_id)?;
+
+        Ok(self.insert_row(data, &self.tenant, rsvp, blocked))
+    }
+
+    // store a new reservation which was checked, recording its creation
+    fn insert_row(
+        &self,
+        data: &mut Data,
+        tenant: &str,
+        mut rsvp: Reservation,
+        blocked: Span,
+    ) -> Reservation {
+        data.last_reservation_id += 1;
+        rsvp.id = data.last_reservation_id;
+        let op = match rsvp.waitlist_id {
+            Some(_) => ReservationUpdateType::Promote,
+            None => ReservationUpdateType::Create,
+        };
+
+        self.record(data, tenant, rsvp.id, op, None, Some(rsvp.clone()));
+        data.put_reservation(
+            rsvp.id,
+            Entry {
+                tenant: tenant.to_string(),
+                rsvp: rsvp.clone(),
+                blocked,
+            },
+        );
+        rsvp
+    }
+
+    // change a reservation of the tenant which passes `filter`, NotFound if there is none
+    // with `reblock`, it's checked again for conflicts like a new reservation
+    fn update(
+        &self,
+        data: &mut Data,
+        tenant: &str,
+        id: ReservationId,
+        filter: impl FnOnce(&Reservation) -> bool,
+        reblock: bool,
+        change: impl FnOnce(&mut Reservation),
+    ) -> Result<Reservation, abi::Error> {
+        let entry = data
+            .get(tenant, id)
+            .ok()
+            .filter(|entry| filter(&entry.rsvp))
+            .ok_or(abi::Error::NotFound)?;
+        let before = entry.rsvp.clone();
+        let mut blocked = entry.blocked;
+
+        let mut after = before.clone();
+        change(&mut after);
+        if reblock {
+            blocked = self.block(data, tenant, &after)?;
+            if after.resource_id != before.resource_id {
+                check_resource(data, tenant, &after.resource_id)?;
+            }
+        }
+        after.version += 1;
+
+        self.record(
+            data,
+            tenant,
+            id,
+            ReservationUpdateType::Update,
+            Some(before.clone()),
+            Some(after.clone()),
+        );
+        data.put_reservation(
+            id,
+            Entry {
+                tenant: tenant.to_string(),
+                rsvp: after.clone(),
+                blocked,
+            },
+        );
+
+        if is_cancelled(&after) && !is_cancelled(&before) {
+            self.promote_waitlist(data, tenant, &before.resource_id);
+        }
+        Ok(after)
+    }
+
+    // the timespan a reservation blocks of its resource, which must have enough seats left
+    // during it, like the triggers of the database
+    fn block(&self, data: &Data, tenant: &str, rsvp: &Reservation) -> Result<Span, abi::Error> {
+        let blocked = data.blocked_range(tenant, rsvp)?;
+        if is_cancelled(rsvp) {
+            return Ok(blocked);
+        }
+        let Some(resource) = data.resource(tenant, &rsvp.resource_id) else {
+            // reported by check_resource
+            return Ok(blocked);
+        };
+
+        let peak = data.peak_seats(tenant, &rsvp.resource_id, blocked, Some(rsvp.id));
+        let remaining = (resource.capacity - peak).max(0);
+        if rsvp.seats() <= remaining {
+            return Ok(blocked);
+        }
+
+        let mut existing: Vec<&Entry> = data
+            .booked(tenant, &rsvp.resource_id, blocked, Some(rsvp.id))
+            .collect();
+        existing.sort_by_key(|entry| (entry.blocked.0, entry.rsvp.id));
+        let existing = existing
+            .into_iter()
+            .map(|entry| entry.rsvp.clone())
+            .collect();
+        Err(conflict(rsvp, existing, remaining))
+    }
+
+    // reserve a reservation, or put it on the waitlist if it conflicts
+    fn insert_or_wait(
+        &self,
+        data: &mut Data,
+        rsvp: Reservation,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        match self.insert(data, rsvp.clone(), None) {
+            Ok(rsvp) => Ok(ReserveOutcome::Reserved(Box::new(rsvp))),
+            Err(abi::Error::ConflictReservation(_)) => {
+                let (start, end) = span(&rsvp)?;
+                data.last_waitlist_id += 1;
+                let id = data.last_waitlist_id;
+                let rsvp = Reservation {
+                    user_id: rsvp.user_id,
+                    resource_id: rsvp.resource_id,
+                    start: Some(datetime_to_timestamp(start)),
+                    end: Some(datetime_to_timestamp(end)),
+                    note: rsvp.note,
+                    seats: rsvp.seats.max(1),
+                    buffer_before: rsvp.buffer_before,
+                    buffer_after: rsvp.buffer_after,
+                    ..Default::default()
+                };
+                data.put_waiting(
+                    id,
+                    Waiting {
+                        tenant: self.tenant.clone(),
+                        rsvp,
+                    },
+                );
+
+                Ok(ReserveOutcome::Waitlisted(id))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // promote the waiting entries of a resource, oldest first, whose timespan is free now,
+    // an entry keeps waiting while it breaks a booking rule or exceeds a quota of its user
+    fn promote_waitlist(&self, data: &mut Data, tenant: &str, resource_id: &str) {
+        // the quotas of the tenant of the entries, which the holds released may belong to
+        let scoped = self.clone().with_tenant(tenant);
+        let now = Utc::now();
+        let waiting: Vec<WaitlistId> = data
+            .waitlist
+            .iter()
+            .filter(|(_, waiting)| {
+                waiting.tenant == tenant
+                    && waiting.rsvp.resource_id == resource_id
+                    && span(&waiting.rsvp).is_ok_and(|(_, end)| end > now)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in waiting {
+            let rsvp = Reservation {
+                status: ReservationStatus::Pending as i32,
+                waitlist_id: Some(id),
+                version: 1,
+                ..data.waitlist[&id].rsvp.clone()
+            };
+            let Ok((start, end)) = span(&rsvp) else {
+                continue;
+            };
+            if self.check_policy(resource_id, start, end).is_err()
+                || scoped
+                    .check_quota(data, &rsvp.user_id, resource_id, start, end, None)
+                    .is_err()
+            {
+                continue;
+            }
+            let Ok(blocked) = self.block(data, tenant, &rsvp) else {
+                continue;
+            };
+            if check_resource(data, tenant, resource_id).is_err() {
+                continue;
+            }
+            self.insert_row(data, tenant, rsvp, blocked);
+            data.remove_waiting(id);
+        }
+    }
+
+    // release the expired holds of the tenant, or of every tenant if it's None
+    fn release_expired_of(
+        &self,
+        data: &mut Data,
+        tenant: Option<&str>,
+    ) -> Result<Vec<Reservation>, abi::Error> {
+        let now = Utc::now();
+        let expired: Vec<(ReservationId, String)> = data
+            .reservations
+            .values()
+            .filter(|entry| {
+                tenant.is_none_or(|tenant| entry.tenant == tenant)
+                    && entry.rsvp.status == ReservationStatus::Pending as i32
+                    && entry
+                        .rsvp
+                        .expires_at()
+                        .is_some_and(|expires_at| expires_at <= now)
+            })
+            .map(|entry| (entry.rsvp.id, entry.tenant.clone()))
+            .collect();
+
+        let mut released = Vec::with_capacity(expired.len());
+        for (id, tenant) in expired {
+            released.push(self.update(
+                data,
+                &tenant,
+                id,
+                |_| true,
+                false,
+                |rsvp| {
+                    rsvp.status = ReservationStatus::Cancelled as i32;
+                    rsvp.cancel_reason = Some("hold expired".to_string());
+                },
+            )?);
+        }
+
+        Ok(released)
+    }
+
+    fn record(
+        &self,
+        data: &mut Data,
+        tenant: &str,
+        reservation_id: ReservationId,
+        op: ReservationUpdateType,
+        before: Option<Reservation>,
+        after: Option<Reservation>,
+    ) {
+        data.last_change_id += 1;
+        data.changes.push(Change {
+            id: data.last_change_id,
+            tenant: tenant.to_string(),
+            reservation_id,
+            op,
+            changed_at: Utc::now(),
+            actor: self.actor.clone(),
+            before,
+            after,
+        });
+    }
+
+    fn check_policy(
+        &self,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), abi::Error> {
+        self.policy
+            .policy_for(resource_id)
+            .check(start, end, Utc::now())
+    }
+
+    fn check_version(
+        &self,
+        data: &Data,
+        rsvp: ReservationId,
+        expected: Option<i64>,
+    ) -> Result<(), abi::Error> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+
+        let actual = data.get(&self.tenant, rsvp)?.rsvp.version;
+        if actual != expected {
+            return Err(abi::Error::VersionMismatch { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    // check the quotas of the user for a reservation of the resource, excluding the
+    // reservation being changed
+    fn check_quota(
+        &self,
+        data: &Data,
+        user_id: &str,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        exclude: Option<ReservationId>,
+    ) -> Result<(), abi::Error> {
+        for scope in self.quota.scopes_for(resource_id) {
+            let (active, weekly) = self.quota_count(data, user_id, scope.resources, start, exclude);
+            scope
+                .quota
+                .check(scope.name, active, weekly, end > Utc::now(), end - start)?;
+        }
+
+        Ok(())
+    }
+
+    // count the active reservations of the user, and the time booked by the ones starting
+    // in the week of `week`
+    fn quota_count(
+        &self,
+        data: &Data,
+        user_id: &str,
+        resources: Option<&[String]>,
+        week: DateTime<Utc>,
+        exclude: Option<ReservationId>,
+    ) -> (i64, TimeDelta) {
+        let counted = data.reservations.values().filter(|entry| {
+            entry.tenant == self.tenant
+                && entry.rsvp.user_id == user_id
+                && !is_cancelled(&entry.rsvp)
+                && resources.is_none_or(|ids| ids.contains(&entry.rsvp.resource_id))
+                && Some(entry.rsvp.id) != exclude
+        });
+
+        quota_count(counted.map(|entry| &entry.rsvp), week)
+    }
+}
+
+impl<'a> Tx<'a> {
+    fn begin(data: &'a mut Data) -> Self {
+        let checkpoint = Checkpoint {
+            changes: data.changes.len(),
+            last_reservation_id: data.last_reservation_id,
+            last_waitlist_id: data.last_waitlist_id,
+            last_series_id: data.last_series_id,
+            last_change_id: data.last_change_id,
+        };
+        Self {
+            data,
+            checkpoint,
+            committed: false,
+        }
+    }
+
+    fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for Tx<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.data.rollback(self.checkpoint);
+        }
+        self.data.undo.clear();
+    }
+}
+
+impl Data {
+    // every change of a row goes through these, which log how to undo it
+
+    fn put_reservation(&mut self, id: ReservationId, entry: Entry) {
+        let previous = self.reservations.insert(id, entry);
+        self.undo.push(Undo::Reservation(id, previous));
+    }
+
+    fn remove_reservation(&mut self, id: ReservationId) {
+        let previous = self.reservations.remove(&id);
+        self.undo.push(Undo::Reservation(id, previous));
+    }
+
+    fn put_resource(&mut self, key: (String, String), resource: abi::Resource) {
+        let previous = self.resources.insert(key.clone(), resource);
+        self.undo.push(Undo::Resource(key, previous));
+    }
+
+    fn remove_resource(&mut self, key: (String, String)) -> Option<abi::Resource> {
+        let previous = self.resources.remove(&key);
+        self.undo.push(Undo::Resource(key, previous.clone()));
+        previous
+    }
+
+    fn put_waiting(&mut self, id: WaitlistId, waiting: Waiting) {
+        let previous = self.waitlist.insert(id, waiting);
+        self.undo.push(Undo::Waiting(id, previous));
+    }
+
+    fn remove_waiting(&mut self, id: WaitlistId) {
+        let previous = self.waitlist.remove(&id);
+        self.undo.push(Undo::Waiting(id, previous));
+    }
+
+    // the expired outcomes dropped by `idempotency_key` aren't restored, they're gone either way
+    fn put_outcome(&mut self, key: (String, String, String), stored: StoredOutcome) {
+        let previous = self.idempotency_keys.insert(key.clone(), stored);
+        self.undo.push(Undo::Outcome(key, previous));
+    }
+
+    // undo the changes since the checkpoint, latest first
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Reservation(id, Some(entry)) => {
+                    self.reservations.insert(id, entry);
+                }
+                Undo::Reservation(id, None) => {
+                    self.reservations.remove(&id);
+                }
+                Undo::Resource(key, Some(resource)) => {
+                    self.resources.insert(key, resource);
+                }
+                Undo::Resource(key, None) => {
+                    self.resources.remove(&key);
+                }
+                Undo::Waiting(id, Some(waiting)) => {
+                    self.waitlist.insert(id, waiting);
+                }
+                Undo::Waiting(id, None) => {
+                    self.waitlist.remove(&id);
+                }
+                Undo::Outcome(key, Some(stored)) => {
+                    self.idempotency_keys.insert(key, stored);
+                }
+                Undo::Outcome(key, None) => {
+                    self.idempotency_keys.remove(&key);
+                }
+            }
+        }
+        self.changes.truncate(checkpoint.changes);
+        self.last_reservation_id = checkpoint.last_reservation_id;
+        self.last_waitlist_id = checkpoint.last_waitlist_id;
+        self.last_series_id = checkpoint.last_series_id;
+        self.last_change_id = checkpoint.last_change_id;
+    }
+
+    fn get(&self, tenant: &str, id: ReservationId) -> Result<&Entry, abi::Error> {
+        self.reservations
+            .get(&id)
+            .filter(|entry| entry.tenant == tenant)
+            .ok_or(abi::Error::NotFound)
+    }
+
+    fn resource(&self, tenant: &str, id: &str) -> Option<&abi::Resource> {
+        self.resources.get(&(tenant.to_string(), id.to_string()))
+    }
+
+    // the timespan of a reservation extended by its buffers, or the ones of its resource
+    fn blocked_range(&self, tenant: &str, rsvp: &Reservation) -> Result<Span, abi::Error> {
+        blocked_span(rsvp, self.resource(tenant, &rsvp.resource_id))
+    }
+
+    // the reservations of a resource which aren't cancelled and block some of `during`
+    fn booked<'a>(
+        &'a self,
+        tenant: &'a str,
+        resource_id: &'a str,
+        during: Span,
+        exclude: Option<ReservationId>,
+    ) -> impl Iterator<Item = &'a Entry> {
+        self.reservations.values().filter(move |entry| {
+            entry.tenant == tenant
+                && entry.rsvp.resource_id == resource_id
+                && !is_cancelled(&entry.rsvp)
+                && overlaps(entry.blocked, during)
+                && Some(entry.rsvp.id) != exclude
+        })
+    }
+
+    // the most seats of a resource taken at once during `during`
+    fn peak_seats(
+        &self,
+        tenant: &str,
+        resource_id: &str,
+        during: Span,
+        exclude: Option<ReservationId>,
+    ) -> i32 {
+        let booked: Vec<(Span, i32)> = self
+            .booked(tenant, resource_id, during, exclude)
+            .map(|entry| (intersect(entry.blocked, during), entry.rsvp.seats))
+            .collect();
+
+        peak_seats(&booked)
+    }
+}
+
+impl Rsvp for InMemoryReservationManager {
+    async fn reserve(&self, rsvp: Reservation) -> Result<Reservation, abi::Error> {
+        self.write(|data| self.insert(data, rsvp, None))
+    }
+
+    async fn reserve_or_wait(&self, rsvp: Reservation) -> Result<ReserveOutcome, abi::Error> {
+        self.write(|data| self.insert_or_wait(data, rsvp))
+    }
+
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: Reservation,
+        waitlist: bool,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        let keyed = keyed(Some(&key), || reserve_fingerprint(&rsvp, waitlist))?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+
+            let outcome = if waitlist {
+                self.insert_or_wait(data, rsvp)?
+            } else {
+                ReserveOutcome::Reserved(Box::new(self.insert(data, rsvp, None)?))
+            };
+            self.remember(data, keyed, &outcome);
+
+            Ok(outcome)
+        })
+    }
+
+    async fn reserve_many(&self, rsvps: Vec<Reservation>) -> Result<Vec<Reservation>, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || batch_fingerprint(&rsvps))?;
+
+        // nothing is kept if any reservation fails
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+
+            let mut reserved = Vec::with_capacity(rsvps.len());
+            for (index, rsvp) in rsvps.into_iter().enumerate() {
+                let rsvp =
+                    self.insert(data, rsvp, None)
+                        .map_err(|e| abi::Error::BatchReservation {
+                            index,
+                            source: Box::new(e),
+                        })?;
+                reserved.push(rsvp);
+            }
+            self.remember(data, keyed, &reserved);
+
+            Ok(reserved)
+        })
+    }
+
+    async fn reserve_series(
+        &self,
+        series: abi::ReservationSeries,
+        mode: SeriesConflictMode,
+    ) -> Result<(abi::ReservationSeries, Vec<Reservation>, Vec<Reservation>), abi::Error> {
+        let occurrences = series.occurrences()?;
+        let Some(first) = occurrences.first() else {
+            return Err(abi::Error::InvalidRecurrenceRule(
+                "no occurrence".to_string(),
+            ));
+        };
+        span(first)?;
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            series_fingerprint(&series, mode)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+
+            data.last_series_id += 1;
+            let id = data.last_series_id;
+
+            let mut reserved = Vec::with_capacity(occurrences.len());
+            let mut conflicts = Vec::new();
+            let mut conflict_infos = Vec::new();
+            for mut rsvp in occurrences {
+                rsvp.series_id = Some(id);
+
+                // a failed insert leaves the data untouched, so the others are still reserved
+                match self.insert(data, rsvp.clone(), Some(id)) {
+                    Ok(rsvp) => reserved.push(rsvp),
+                    Err(abi::Error::ConflictReservation(info)) => {
+                        conflict_infos.push(info);
+                        conflicts.push(rsvp);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if mode == SeriesConflictMode::Fail && !conflict_infos.is_empty() {
+                return Err(abi::Error::ConflictSeries(conflict_infos));
+            }
+
+            let outcome = (abi::ReservationSeries { id, ..series }, reserved, conflicts);
+            self.remember(data, keyed, &outcome);
+
+            Ok(outcome)
+        })
+    }
+
+    async fn delete(&self, rsvp: ReservationId) -> Result<(), abi::Error> {
+        self.write(|data| {
+            let entry = data.get(&self.tenant, rsvp)?.clone();
+            data.remove_reservation(rsvp);
+
+            // the deleted reservation is kept as tombstone
+            self.record(
+                data,
+                &self.tenant,
+                rsvp,
+                ReservationUpdateType::Delete,
+                Some(entry.rsvp.clone()),
+                None,
+            );
+            if !is_cancelled(&entry.rsvp) {
+                self.promote_waitlist(data, &self.tenant, &entry.rsvp.resource_id);
+            }
+
+            Ok(())
+        })
+    }
+
+    async fn change_status(
+        &self,
+        rsvp: ReservationId,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            confirm_fingerprint(rsvp, expected_version)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+            self.check_version(data, rsvp, expected_version)?;
+
+            // if a reservation is pending and not expired, it will be confirmed
+            let now = Utc::now();
+            let pending = |rsvp: &Reservation| rsvp.status == ReservationStatus::Pending as i32;
+            let result = self.update(
+                data,
+                &self.tenant,
+                rsvp,
+                |rsvp| pending(rsvp) && rsvp.expires_at().is_none_or(|at| at > now),
+                false,
+                |rsvp| {
+                    rsvp.status = ReservationStatus::Confirmed as i32;
+                    rsvp.expires_at = None;
+                },
+            );
+
+            // tell an expired hold apart from a reservation that can't be confirmed
+            let confirmed = match result {
+                Err(abi::Error::NotFound)
+                    if data
+                        .get(&self.tenant, rsvp)
+                        .is_ok_and(|entry| pending(&entry.rsvp)) =>
+                {
+                    return Err(abi::Error::HoldExpired);
+                }
+                result => result?,
+            };
+            self.remember(data, keyed, &confirmed);
+
+            Ok(confirmed)
+        })
+    }
+
+    async fn cancel(
+        &self,
+        rsvp: ReservationId,
+        reason: Option<String>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            cancel_fingerprint(rsvp, reason.as_ref(), expected_version)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+            self.check_version(data, rsvp, expected_version)?;
+
+            // a cancelled reservation can't be cancelled again
+            let cancelled = self.update(
+                data,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                false,
+                |rsvp| {
+                    rsvp.status = ReservationStatus::Cancelled as i32;
+                    rsvp.cancel_reason = reason;
+                },
+            )?;
+            self.remember(data, keyed, &cancelled);
+
+            Ok(cancelled)
+        })
+    }
+
+    async fn reschedule(
+        &self,
+        rsvp: ReservationId,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        if start >= end {
+            return Err(abi::Error::InvalidTimespan);
+        }
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            reschedule_fingerprint(rsvp, start, end, expected_version)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+            self.check_version(data, rsvp, expected_version)?;
+
+            let current = data.get(&self.tenant, rsvp)?.rsvp.clone();
+            self.check_policy(&current.resource_id, start, end)?;
+            self.check_quota(
+                data,
+                &current.user_id,
+                &current.resource_id,
+                start,
+                end,
+                Some(rsvp),
+            )?;
+
+            let rescheduled = self.update(
+                data,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                true,
+                |rsvp| {
+                    rsvp.start = Some(datetime_to_timestamp(start));
+                    rsvp.end = Some(datetime_to_timestamp(end));
+                },
+            )?;
+            self.remember(data, keyed, &rescheduled);
+
+            Ok(rescheduled)
+        })
+    }
+
+    async fn change_resource(
+        &self,
+        rsvp: ReservationId,
+        resource_id: String,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            change_resource_fingerprint(rsvp, &resource_id, expected_version)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+            self.check_version(data, rsvp, expected_version)?;
+
+            // the booking rules and the quotas of the new resource apply
+            let current = data.get(&self.tenant, rsvp)?.rsvp.clone();
+            let (start, end) = span(&current)?;
+            self.check_policy(&resource_id, start, end)?;
+            self.check_quota(data, &current.user_id, &resource_id, start, end, Some(rsvp))?;
+
+            let moved = self.update(
+                data,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                true,
+                |rsvp| rsvp.resource_id = resource_id,
+            )?;
+            self.remember(data, keyed, &moved);
+
+            Ok(moved)
+        })
+    }
+
+    async fn release_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        self.write(|data| self.release_expired_of(data, Some(&self.tenant)))
+    }
+
+    async fn update_notes(
+        &self,
+        rsvp: ReservationId,
+        note: String,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            update_fingerprint(rsvp, &note, expected_version)
+        })?;
+
+        self.write(|data| {
+            if let Some(outcome) = self.replayed(data, keyed.as_ref())? {
+                return Ok(outcome);
+            }
+            self.check_version(data, rsvp, expected_version)?;
+
+            let updated = self.update(
+                data,
+                &self.tenant,
+                rsvp,
+                |_| true,
+                false,
+                |rsvp| rsvp.note = note,
+            )?;
+            self.remember(data, keyed, &updated);
+
+            Ok(updated)
+        })
+    }
+
+    async fn get(&self, rsvp: ReservationId) -> Result<Reservation, abi::Error> {
+        self.read(|data| Ok(data.get(&self.tenant, rsvp)?.rsvp.clone()))
+    }
+
+    async fn get_history(
+        &self,
+        rsvp: ReservationId,
+    ) -> Result<Vec<abi::ReservationChange>, abi::Error> {
+        let changes: Vec<abi::ReservationChange> = self.read(|data| {
+            data.changes
+                .iter()
+                .filter(|change| change.reservation_id == rsvp && change.tenant == self.tenant)
+                .map(|change| abi::ReservationChange {
+                    id: change.id,
+                    op: change.op as i32,
+                    changed_at: Some(datetime_to_timestamp(change.changed_at)),
+                    actor: change.actor.clone(),
+                    before: change.before.clone(),
+                    after: change.after.clone(),
+                })
+                .collect()
+        });
+
+        if changes.is_empty() {
+            return Err(abi::Error::NotFound);
+        }
+        Ok(changes)
+    }
+
+    async fn query(
+        &self,
+        query: abi::ReservationQuery,
+    ) -> Result<mpsc::Receiver<Result<Reservation, abi::Error>>, abi::Error> {
+        query.timespan()?;
+        let from = query.start.as_ref().map(timestamp_to_datetime);
+        let to = query.end.as_ref().map(timestamp_to_datetime);
+        let page = query.page.max(1) as usize;
+        let page_size = page_size(query.page_size);
+
+        let mut rsvps: Vec<Reservation> = self.read(|data| {
+            data.reservations
+                .values()
+                .filter(|entry| {
+                    let (start, end) = timespan(&entry.rsvp);
+                    entry.tenant == self.tenant
+                        && from.is_none_or(|from| from <= start)
+                        && to.is_none_or(|to| end <= to)
+                        && matches(
+                            &entry.rsvp,
+                            &query.user_id,
+                            &query.resource_id,
+                            query.status,
+                        )
+                })
+                .map(|entry| entry.rsvp.clone())
+                .collect()
+        });
+        rsvps.sort_by_key(|rsvp| timespan(rsvp).0);
+        if query.is_desc {
+            rsvps.reverse();
+        }
+
+        let (tx, rx) = mpsc::channel(32);
+        let rsvps = rsvps
+            .into_iter()
+            .skip((page - 1) * page_size)
+            .take(page_size);
+        let rsvps: Vec<_> = rsvps.collect();
+        tokio::spawn(async move {
+            for rsvp in rsvps {
+                if tx.send(Ok(rsvp)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    async fn filter(
+        &self,
+        filter: abi::ReservationFilter,
+    ) -> Result<(abi::FilterPager, Vec<Reservation>), abi::Error> {
+        let mut filter = filter;
+        if filter.is_prev {
+            filter.is_desc = !filter.is_desc;
+        }
+        filter.page_size = page_size(filter.page_size) as i32;
+        let mut cursor = filter.cursor.max(0);
+        if cursor == 0 && filter.is_desc {
+            cursor = i64::MAX;
+        }
+
+        // filter by user_id, resource_id, status and order by start
+        let mut rsvps: Vec<Reservation> = self.read(|data| {
+            data.reservations
+                .values()
+                .filter(|entry| {
+                    let id = entry.rsvp.id;
+                    entry.tenant == self.tenant
+                        && if filter.is_desc {
+                            id < cursor
+                        } else {
+                            id > cursor
+                        }
+                        && matches(
+                            &entry.rsvp,
+                            &filter.user_id,
+                            &filter.resource_id,
+                            filter.status,
+                        )
+                })
+                .map(|entry| entry.rsvp.clone())
+                .collect()
+        });
+        rsvps.sort_by_key(|rsvp| timespan(rsvp).0);
+        if filter.is_desc {
+            rsvps.reverse();
+        }
+        rsvps.truncate(filter.page_size as usize);
+
+        if filter.is_prev {
+            rsvps.reverse();
+        }
+
+        Ok((pager(&filter, &rsvps), rsvps))
+    }
+
+    async fn find_availability(
+        &self,
+        resource_id: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        duration: TimeDelta,
+    ) -> Result<Vec<abi::TimeSlot>, abi::Error> {
+        if start >= end || duration <= TimeDelta::zero() {
+            return Err(abi::Error::InvalidTimespan);
+        }
+
+        Ok(self.read(|data| {
+            let resource = data.resource(&self.tenant, &resource_id);
+            let capacity = resource.map_or(1, |r| r.capacity);
+            let before = resource.map(|r| r.buffer_before()).unwrap_or_default();
+            let after = resource.map(|r| r.buffer_after()).unwrap_or_default();
+
+            // a free timespan has to leave room for the buffers around a reservation
+            let window = (start - before, end + after);
+            let booked: Vec<(Span, i32)> = data
+                .booked(&self.tenant, &resource_id, window, None)
+                .map(|entry| (intersect(entry.blocked, window), entry.rsvp.seats))
+                .collect();
+
+            slots(
+                free_spans(window, &booked, capacity),
+                before,
+                after,
+                duration,
+            )
+        }))
+    }
+
+    async fn quota_usage(
+        &self,
+        user_id: String,
+        week: DateTime<Utc>,
+    ) -> Result<Vec<abi::QuotaUsage>, abi::Error> {
+        if user_id.is_empty() {
+            return Err(abi::Error::InvalidUserId);
+        }
+
+        let usages = self.read(|data| {
+            self.quota
+                .scopes()
+                .into_iter()
+                .map(|scope| {
+                    let (active, weekly) =
+                        self.quota_count(data, &user_id, scope.resources, week, None);
+                    scope.quota.usage(scope.name, active, weekly)
+                })
+                .collect()
+        });
+
+        Ok(usages)
+    }
+
+    async fn create_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
+
+        self.write(|data| {
+            let key = (self.tenant.clone(), resource.id.clone());
+            if data.resources.contains_key(&key) {
+                return Err(abi::Error::ResourceAlreadyExists);
+            }
+            let resource = normalize(resource);
+            data.put_resource(key, resource.clone());
+
+            Ok(resource)
+        })
+    }
+
+    async fn get_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        self.read(|data| data.resource(&self.tenant, &id).cloned())
+            .ok_or(abi::Error::NotFound)
+    }
+
+    async fn update_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
+
+        self.write(|data| {
+            let key = (self.tenant.clone(), resource.id.clone());
+            if !data.resources.contains_key(&key) {
+                return Err(abi::Error::NotFound);
+            }
+            let resource = normalize(resource);
+            data.put_resource(key, resource.clone());
+
+            Ok(resource)
+        })
+    }
+
+    async fn delete_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        self.write(|data| {
+            let key = (self.tenant.clone(), id);
+            if !data.resources.contains_key(&key) {
+                return Err(abi::Error::NotFound);
+            }
+            // cancelled reservations still reference it
+            let in_use = data
+                .reservations
+                .values()
+                .any(|entry| entry.tenant == key.0 && entry.rsvp.resource_id == key.1);
+            if in_use {
+                return Err(abi::Error::ResourceInUse);
+            }
+
+            Ok(data.remove_resource(key).unwrap())
+        })
+    }
+
+    async fn list_resources(&self) -> Result<Vec<abi::Resource>, abi::Error> {
+        let resources = self.read(|data| {
+            data.resources
+                .iter()
+                .filter(|((tenant, _), _)| *tenant == self.tenant)
+                .map(|(_, resource)| resource.clone())
+                .collect()
+        });
+
+        Ok(resources)
+    }
+
+    async fn listen(
+        &self,
+    ) -> Result<mpsc::Receiver<Result<abi::ListenResponse, abi::Error>>, abi::Error> {
+        // only changes made after the call will be sent, the updates are subscribed to
+        // while holding the data, so no change is missed in between
+        let (mut cursor, mut updates) =
+            self.read(|data| (data.last_change_id, self.shared.updates.subscribe()));
+        let shared = self.shared.clone();
+        let tenant = self.tenant.clone();
+
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    changed = updates.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                // an update may stand for several changes, read all of them
+                let changes: Vec<abi::ListenResponse> = {
+                    let data = shared
+                        .data
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    let from = data.changes.partition_point(|change| change.id <= cursor);
+                    cursor = data.last_change_id;
+                    data.changes[from..]
+                        .iter()
+                        .filter(|change| change.tenant == tenant)
+                        .map(|change| abi::ListenResponse {
+                            op: change.op as i32,
+                            // a deleted reservation is sent with its last state
+                            reservation: change.after.clone().or(change.before.clone()),
+                        })
+                        .collect()
+                };
+
+                for change in changes {
+                    if tx.send(Ok(change)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+// like reservations_resource_trigger, only an enabled resource of the tenant can be reserved
+fn check_resource(data: &Data, tenant: &str, resource_id: &str) -> Result<(), abi::Error> {
+    match data.resource(tenant, resource_id) {
+        Some(resource) if !resource.disabled => Ok(()),
+        _ => Err(abi::Error::ResourceNotAvailable(resource_id.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use abi::error::conflict::ReservationConflictInfo;
+    use abi::utils::timedelta_to_duration;
+    use abi::ReservationFilterBuilder;
+    use abi::ReservationQueryBuilder;
+    use chrono::Duration;
+
+    use super::*;
+    use crate::suite::{suite, with_fixtures};
+
+    // the resources of the fixtures of the database tests
+    async fn manager() -> InMemoryReservationManager {
+        with_fixtures(InMemoryReservationManager::new()).await
+    }
+
+    suite!(#[tokio::test] () => manager().await);
+
+    fn rsvp(resource_id: &str, start: &str, end: &str) -> Reservation {
+        Reservation::new_pendding(
+            "user",
+            resource_id,
+            DateTime::parse_from_rfc3339(start).unwrap(),
+            DateTime::parse_from_rfc3339(end).unwrap(),
+            "note",
+        )
+    }
+
+    fn default_rsvp() -> Reservation {
+        rsvp("resource", "2021-01-01T00:00:00Z", "2021-01-02T00:00:00Z")
+    }
+
+    #[tokio::test]
+    async fn reserve_should_reject_conflicting_timespan() {
+        let manager = manager().await;
+        let existing = manager.reserve(default_rsvp()).await.unwrap();
+        assert_eq!(existing.id, 1);
+        assert_eq!(existing.version, 1);
+
+        let conflicting = rsvp("resource", "2021-01-01T12:00:00Z", "2021-01-02T12:00:00Z");
+        let err = manager.reserve(conflicting.clone()).await.unwrap_err();
+        let abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) = err else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(conflict.existing_id, Some(existing.id));
+        assert_eq!(conflict.existing, vec![existing.clone()]);
+        assert_eq!(conflict.remaining_capacity, 0);
+
+        // adjacent timespans and other resources don't conflict
+        let adjacent = rsvp("resource", "2021-01-02T00:00:00Z", "2021-01-03T00:00:00Z");
+        manager.reserve(adjacent).await.unwrap();
+        let other = rsvp("room", "2021-01-01T12:00:00Z", "2021-01-02T12:00:00Z");
+        manager.reserve(other).await.unwrap();
+
+        // a cancelled reservation frees its timespan
+        manager.cancel(existing.id, None, None).await.unwrap();
+        let start = DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap();
+        let rsvp = rsvp("resource", "2021-01-01T00:00:00Z", "2021-01-01T12:00:00Z");
+        let rsvp = manager.reserve(rsvp).await.unwrap();
+        assert_eq!(timestamp_to_datetime(rsvp.start.as_ref().unwrap()), start);
+
+        let unknown = Reservation {
+            resource_id: "unknown".to_string(),
+            ..default_rsvp()
+        };
+        let err = manager.reserve(unknown).await.unwrap_err();
+        assert!(matches!(err, abi::Error::ResourceNotAvailable(id) if id == "unknown"));
+    }
+
+    #[tokio::test]
+    async fn reserve_should_respect_capacity_and_buffers() {
+        let manager = manager().await;
+        let seats = |seats| Reservation {
+            seats,
+            ..rsvp("lot", "2021-01-01T00:00:00Z", "2021-01-02T00:00:00Z")
+        };
+        manager.reserve(seats(2)).await.unwrap();
+        manager.reserve(seats(1)).await.unwrap();
+        let err = manager.reserve(seats(1)).await.unwrap_err();
+        let abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) = err else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(conflict.existing.len(), 2);
+
+        let resource = abi::Resource {
+            buffer_after: Some(timedelta_to_duration(Duration::hours(1))),
+            ..abi::Resource::new("room", "room")
+        };
+        manager.update_resource(resource).await.unwrap();
+        manager
+            .reserve(rsvp("room", "2021-01-01T00:00:00Z", "2021-01-01T12:00:00Z"))
+            .await
+            .unwrap();
+        let err = manager
+            .reserve(rsvp("room", "2021-01-01T12:30:00Z", "2021-01-01T14:00:00Z"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, abi::Error::ConflictReservation(_)));
+
+        let slots = manager
+            .find_availability(
+                "room".to_string(),
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+                "2021-01-02T00:00:00Z".parse().unwrap(),
+                Duration::try_minutes(1).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(slots.len(), 1);
+        let start = timestamp_to_datetime(slots[0].start.as_ref().unwrap());
+        assert_eq!(
+            start,
+            "2021-01-01T13:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reserve_many_should_keep_nothing_if_one_fails() {
+        let manager = manager().await;
+        let rsvps = vec![
+            rsvp("room", "2021-01-01T00:00:00Z", "2021-01-02T00:00:00Z"),
+            default_rsvp(),
+            default_rsvp(),
+        ];
+        let err = manager.reserve_many(rsvps).await.unwrap_err();
+        assert!(matches!(err, abi::Error::BatchReservation { index: 2, .. }));
+
+        let filter = ReservationFilterBuilder::default().build().unwrap();
+        let (_, rsvps) = manager.filter(filter).await.unwrap();
+        assert!(rsvps.is_empty());
+        assert!(matches!(manager.get(1).await, Err(abi::Error::NotFound)));
+        manager.read(|data| {
+            assert!(data.changes.is_empty());
+            assert_eq!(data.last_reservation_id, 0);
+            assert!(data.undo.is_empty());
+        });
+    }
+
+    #[tokio::test]
+    async fn a_panicking_write_should_be_undone() {
+        let manager = manager().await;
+        let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            manager.write(|data| -> Result<(), abi::Error> {
+                data.remove_reservation(rsvp.id);
+                data.remove_resource((DEFAULT_TENANT.to_string(), "room".to_string()));
+                panic!("the write fails halfway");
+            })
+        }));
+        assert!(result.is_err());
+
+        assert_eq!(manager.get(rsvp.id).await.unwrap(), rsvp);
+        assert!(manager.get_resource("room".to_string()).await.is_ok());
+        manager.read(|data| assert!(data.undo.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn status_should_follow_the_transitions() {
+        let manager = manager().await;
+        let reserved = manager.reserve(default_rsvp()).await.unwrap();
+
+        let err = manager
+            .change_status(reserved.id, Some(2))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            abi::Error::VersionMismatch {
+                expected: 2,
+                actual: 1
+            }
+        ));
+        let confirmed = manager.change_status(reserved.id, Some(1)).await.unwrap();
+        assert_eq!(confirmed.status, ReservationStatus::Confirmed as i32);
+        assert_eq!(confirmed.version, 2);
+        let err = manager.change_status(reserved.id, None).await.unwrap_err();
+        assert!(matches!(err, abi::Error::NotFound));
+
+        let cancelled = manager
+            .cancel(reserved.id, Some("reason".to_string()), None)
+            .await
+            .unwrap();
+        assert_eq!(cancelled.status, ReservationStatus::Cancelled as i32);
+        assert_eq!(cancelled.cancel_reason.as_deref(), Some("reason"));
+        let err = manager.cancel(reserved.id, None, None).await.unwrap_err();
+        assert!(matches!(err, abi::Error::NotFound));
+        let err = manager
+            .change_resource(reserved.id, "room".to_string(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, abi::Error::NotFound));
+
+        // an expired hold can't be confirmed, it's released instead
+        let hold = Reservation {
+            expires_at: Some(datetime_to_timestamp(Utc::now() - Duration::minutes(1))),
+            ..rsvp("room", "2021-01-01T00:00:00Z", "2021-01-02T00:00:00Z")
+        };
+        let hold = manager.reserve(hold).await.unwrap();
+        let err = manager.change_status(hold.id, None).await.unwrap_err();
+        assert!(matches!(err, abi::Error::HoldExpired));
+        let released = manager.release_expired().await.unwrap();
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].cancel_reason.as_deref(), Some("hold expired"));
+
+        let history = manager.get_history(reserved.id).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].op, ReservationUpdateType::Create as i32);
+        assert_eq!(history[2].before.as_ref().unwrap().version, 2);
+        assert_eq!(history[2].after.as_ref().unwrap().version, 3);
+    }
+
+    #[tokio::test]
+    async fn waitlist_should_be_promoted_on_cancel() {
+        let manager = manager().await;
+        let mut changes = manager.listen().await.unwrap();
+
+        let start = Utc::now() + Duration::days(1);
+        let waiting = Reservation {
+            start: Some(datetime_to_timestamp(start)),
+            end: Some(datetime_to_timestamp(start + Duration::hours(1))),
+            ..default_rsvp()
+        };
+        let rsvp = manager.reserve(waiting.clone()).await.unwrap();
+        let outcome = manager.reserve_or_wait(waiting).await.unwrap();
+        assert_eq!(outcome, ReserveOutcome::Waitlisted(1));
+
+        manager.cancel(rsvp.id, None, None).await.unwrap();
+        let ops: Vec<i32> = [
+            changes.recv().await,
+            changes.recv().await,
+            changes.recv().await,
+        ]
+        .into_iter()
+        .map(|change| change.unwrap().unwrap().op)
+        .collect();
+        assert_eq!(
+            ops,
+            [
+                ReservationUpdateType::Create as i32,
+                ReservationUpdateType::Update as i32,
+                ReservationUpdateType::Promote as i32,
+            ]
+        );
+        let promoted = manager.get(rsvp.id + 1).await.unwrap();
+        assert_eq!(promoted.waitlist_id, Some(1));
+    }
+
+    #[tokio::test]
+    async fn query_should_page_reservations() {
+        let manager = manager().await;
+        for day in 1..=5 {
+            let rsvp = rsvp(
+                "resource",
+                &format!("2021-01-0{}T00:00:00Z", day),
+                &format!("2021-01-0{}T12:00:00Z", day),
+            );
+            manager.reserve(rsvp).await.unwrap();
+        }
+
+        let query = |page, is_desc| {
+            ReservationQueryBuilder::default()
+                .user_id("user")
+                .end(datetime_to_timestamp(
+                    "2021-01-05T00:00:00Z".parse().unwrap(),
+                ))
+                .page(page)
+                .page_size(3)
+                .is_desc(is_desc)
+                .build()
+                .unwrap()
+        };
+        let ids = |mut rx: mpsc::Receiver<Result<Reservation, abi::Error>>| async move {
+            let mut ids = Vec::new();
+            while let Some(rsvp) = rx.recv().await {
+                ids.push(rsvp.unwrap().id);
+            }
+            ids
+        };
+
+        // the last one isn't within the timespan
+        let rx = manager.query(query(1, false)).await.unwrap();
+        assert_eq!(ids(rx).await, [1, 2, 3]);
+        let rx = manager.query(query(2, false)).await.unwrap();
+        assert_eq!(ids(rx).await, [4]);
+        let rx = manager.query(query(1, true)).await.unwrap();
+        assert_eq!(ids(rx).await, [4, 3, 2]);
+
+        let query = ReservationQueryBuilder::default().build().unwrap();
+        let err = manager.query(query).await.unwrap_err();
+        assert!(matches!(err, abi::Error::InvalidTimespan));
+    }
+
+    #[tokio::test]
+    async fn filter_should_move_the_cursors() {
+        let manager = manager().await;
+        for day in 1..=5 {
+            let rsvp = rsvp(
+                "resource",
+                &format!("2021-01-0{}T00:00:00Z", day),
+                &format!("2021-01-0{}T12:00:00Z", day),
+            );
+            manager.reserve(rsvp).await.unwrap();
+        }
+        let filter = |cursor, is_prev| {
+            ReservationFilterBuilder::default()
+                .user_id("user")
+                .cursor(cursor)
+                .page_size(2)
+                .is_prev(is_prev)
+                .build()
+                .unwrap()
+        };
+        let ids = |rsvps: Vec<Reservation>| rsvps.iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let (pager, rsvps) = manager.filter(filter(0, false)).await.unwrap();
+        assert_eq!(ids(rsvps), [1, 2]);
+        assert_eq!((pager.prev, pager.next), (None, Some(2)));
+
+        let (pager, rsvps) = manager.filter(filter(2, false)).await.unwrap();
+        assert_eq!(ids(rsvps), [3, 4]);
+        assert_eq!((pager.prev, pager.next), (Some(3), Some(4)));
+
+        let (pager, rsvps) = manager.filter(filter(4, false)).await.unwrap();
+        assert_eq!(ids(rsvps), [5]);
+        assert_eq!((pager.prev, pager.next), (Some(5), None));
+
+        let (pager, rsvps) = manager.filter(filter(3, true)).await.unwrap();
+        assert_eq!(ids(rsvps), [1, 2]);
+        assert_eq!(pager.next, Some(2));
+    }
+
+    #[tokio::test]
+    async fn tenants_should_not_see_each_other() {
+        let manager = manager().await;
+        let other = manager.clone().with_tenant("other");
+        other
+            .create_resource(abi::Resource::new("resource", "resource"))
+            .await
+            .unwrap();
+
+        let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+        let other_rsvp = other.reserve(default_rsvp()).await.unwrap();
+        assert!(matches!(
+            other.get(rsvp.id).await,
+            Err(abi::Error::NotFound)
+        ));
+        assert_eq!(other.get(other_rsvp.id).await.unwrap(), other_rsvp);
+
+        let err = manager
+            .delete_resource("resource".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, abi::Error::ResourceInUse));
+        manager.delete(rsvp.id).await.unwrap();
+        manager
+            .delete_resource("resource".to_string())
+            .await
+            .unwrap();
+        assert_eq!(other.list_resources().await.unwrap().len(), 1);
+    }
+}
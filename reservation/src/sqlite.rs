@@ -0,0 +1,1701 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use abi::config::{IdempotencyConfig, PolicyConfig, QuotaConfig};
+use abi::utils::{datetime_to_timestamp, timedelta_to_duration, timestamp_to_datetime};
+use abi::{Reservation, ReservationStatus, ReservationUpdateType, SeriesConflictMode};
+use chrono::{DateTime, TimeDelta, Utc};
+use prost::Message;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
+use sqlx::{FromRow, Sqlite, Transaction};
+use tokio::sync::{mpsc, watch, Mutex, MutexGuard};
+
+use crate::booking::{
+    blocked_span, conflict, free_spans, intersect, is_cancelled, normalize, page_size, pager,
+    peak_seats, quota_count, slots, span, Span,
+};
+use crate::idempotency::{
+    batch_fingerprint, cancel_fingerprint, change_resource_fingerprint, confirm_fingerprint, keyed,
+    replay, reschedule_fingerprint, reserve_fingerprint, series_fingerprint, update_fingerprint,
+    Keyed, Outcome,
+};
+use crate::{ReservationId, ReserveOutcome, Rsvp, WaitlistId, DEFAULT_TENANT};
+
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations/sqlite");
+
+/// A `Rsvp` storing its data in SQLite, migrated with the migrations in `migrations/sqlite`.
+/// It follows the semantics of [`ReservationManager`](crate::ReservationManager), but SQLite
+/// has no exclusion constraints or triggers for them, so the conflicts are checked in the
+/// transaction which makes a change. The clones of a manager write one at a time, so nothing
+/// can change what was checked before the transaction commits, and their listeners are told
+/// about the changes made with any of them.
+#[derive(Debug, Clone)]
+pub struct SqliteReservationManager {
+    pool: SqlitePool,
+    // held by the write transactions of the clones
+    write_lock: Arc<Mutex<()>>,
+    // sent to the listeners after every committed write
+    updates: Arc<watch::Sender<()>>,
+    policy: Arc<PolicyConfig>,
+    quota: Arc<QuotaConfig>,
+    // how long the outcome of a request with an idempotency key is kept
+    idempotency_ttl: TimeDelta,
+    // who the changes are made by, recorded in the history of the reservations
+    actor: Option<String>,
+    // the tenant whose data the manager sees and changes
+    tenant: String,
+    // the key of the request the changes are made once for
+    idempotency_key: Option<String>,
+}
+
+// a reservation as it's stored
+#[derive(Debug, FromRow)]
+struct ReservationRow {
+    id: ReservationId,
+    user_id: String,
+    status: String,
+    resource_id: String,
+    start_at: i64,
+    end_at: i64,
+    blocked_start: i64,
+    blocked_end: i64,
+    note: String,
+    cancel_reason: Option<String>,
+    series_id: Option<i64>,
+    expires_at: Option<i64>,
+    seats: i32,
+    buffer_before: Option<i64>,
+    buffer_after: Option<i64>,
+    waitlist_id: Option<WaitlistId>,
+    version: i64,
+}
+
+// a stored reservation with the timespan it blocks
+struct Stored {
+    rsvp: Reservation,
+    blocked: Span,
+}
+
+#[derive(Debug, FromRow)]
+struct ResourceRow {
+    id: String,
+    name: String,
+    location: String,
+    capabilities: String,
+    disabled: bool,
+    capacity: i32,
+    buffer_before: i64,
+    buffer_after: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct WaitingRow {
+    id: WaitlistId,
+    user_id: String,
+    resource_id: String,
+    start_at: i64,
+    end_at: i64,
+    note: String,
+    seats: i32,
+    buffer_before: Option<i64>,
+    buffer_after: Option<i64>,
+}
+
+#[derive(Debug, FromRow)]
+struct ChangeRow {
+    id: i64,
+    op: String,
+    changed_at: i64,
+    actor: Option<String>,
+    old_reservation: Option<Vec<u8>>,
+    reservation: Option<Vec<u8>>,
+}
+
+impl SqliteReservationManager {
+    /// Open the database at `url`, which is created if it doesn't exist yet, and migrate it
+    /// An in-memory database only lives as long as its connection, so it gets a single one.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = if url.contains(":memory:") || url.contains("mode=memory") {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect_with(options)
+                .await?
+        } else {
+            SqlitePool::connect_with(options).await?
+        };
+
+        let manager = Self::new(pool);
+        manager.migrate().await?;
+        Ok(manager)
+    }
+
+    pub fn new(pool: SqlitePool) -> Self {
+        let (updates, _) = watch::channel(());
+        Self {
+            pool,
+            write_lock: Default::default(),
+            updates: Arc::new(updates),
+            policy: Default::default(),
+            quota: Default::default(),
+            idempotency_ttl: TimeDelta::try_seconds(IdempotencyConfig::default().ttl as i64)
+                .unwrap(),
+            actor: None,
+            tenant: DEFAULT_TENANT.to_string(),
+            idempotency_key: None,
+        }
+    }
+
+    /// Run the migrations of `migrations/sqlite` which haven't run yet
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Enforce the booking rules when reserving or rescheduling
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Enforce the quotas of the users when reserving or rescheduling
+    pub fn with_quota(mut self, quota: QuotaConfig) -> Self {
+        self.quota = Arc::new(quota);
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Keep the outcome of a request with an idempotency key for the configured time
+    pub fn with_idempotency(mut self, idempotency: IdempotencyConfig) -> Self {
+        self.idempotency_ttl =
+            TimeDelta::try_seconds(idempotency.ttl as i64).unwrap_or(TimeDelta::MAX);
+        self
+    }
+
+    pub async fn release_all_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let released = self.release_expired_of(&mut tx, None).await?;
+        self.commit(tx).await?;
+        Ok(released)
+    }
+
+    // begin a write transaction, the writes of the clones are made one at a time until
+    // the lock is dropped, so a check made in the transaction holds until it commits
+    async fn begin(
+        &self,
+    ) -> Result<(MutexGuard<'_, ()>, Transaction<'static, Sqlite>), abi::Error> {
+        let lock = self.write_lock.lock().await;
+        let mut tx = self.pool.begin().await?;
+        // like BEGIN IMMEDIATE, which sqlx can't send: a write takes the write lock of the
+        // database before anything is read, so the writers of other processes wait for it too
+        sqlx::query("DELETE FROM idempotency_keys WHERE 0")
+            .execute(&mut *tx)
+            .await?;
+        Ok((lock, tx))
+    }
+
+    // commit a write transaction, then tell the listeners about its changes
+    async fn commit(&self, tx: Transaction<'static, Sqlite>) -> Result<(), abi::Error> {
+        tx.commit().await?;
+        self.updates.send_replace(());
+        Ok(())
+    }
+
+    // insert a reservation, as an occurrence of the series if there is one,
+    // nothing is written if it fails
+    async fn insert(
+        &self,
+        conn: &mut SqliteConnection,
+        rsvp: Reservation,
+        series_id: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        rsvp.validate()?;
+
+        let (start, end) = span(&rsvp)?;
+        self.check_policy(&rsvp.resource_id, start, end)?;
+        self.check_quota(conn, &rsvp.user_id, &rsvp.resource_id, start, end, 0)
+            .await?;
+        let status = ReservationStatus::try_from(rsvp.status).unwrap_or(ReservationStatus::Pending);
+
+        let rsvp = Reservation {
+            // not an id of any reservation, until it's inserted
+            id: 0,
+            status: status as i32,
+            seats: rsvp.seats(),
+            series_id,
+            waitlist_id: None,
+            version: 1,
+            ..rsvp
+        };
+        let blocked = self.block(conn, &self.tenant, &rsvp).await?;
+        check_resource(conn, &self.tenant, &rsvp.resource_id).await?;
+
+        self.insert_row(conn, &self.tenant, rsvp, blocked).await
+    }
+
+    // store a new reservation which was checked, recording its creation
+    async fn insert_row(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+        rsvp: Reservation,
+        blocked: Span,
+    ) -> Result<Reservation, abi::Error> {
+        let (start, end) = span(&rsvp)?;
+        let row: ReservationRow = sqlx::query_as(
+            r#"
+            INSERT INTO reservations (tenant_id, user_id, status, resource_id, start_at, end_at,
+                blocked_start, blocked_end, note, cancel_reason, series_id, expires_at, seats,
+                buffer_before, buffer_after, waitlist_id, version)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(tenant)
+        .bind(&rsvp.user_id)
+        .bind(status_name(rsvp.status))
+        .bind(&rsvp.resource_id)
+        .bind(micros(start))
+        .bind(micros(end))
+        .bind(micros(blocked.0))
+        .bind(micros(blocked.1))
+        .bind(&rsvp.note)
+        .bind(&rsvp.cancel_reason)
+        .bind(rsvp.series_id)
+        .bind(rsvp.expires_at().map(micros))
+        .bind(rsvp.seats)
+        .bind(rsvp.buffer_before().map(delta_micros))
+        .bind(rsvp.buffer_after().map(delta_micros))
+        .bind(rsvp.waitlist_id)
+        .bind(rsvp.version)
+        .fetch_one(&mut *conn)
+        .await?;
+        let rsvp = Stored::from(row).rsvp;
+
+        let op = match rsvp.waitlist_id {
+            Some(_) => ReservationUpdateType::Promote,
+            None => ReservationUpdateType::Create,
+        };
+        self.record(conn, tenant, rsvp.id, op, None, Some(&rsvp))
+            .await?;
+
+        Ok(rsvp)
+    }
+
+    // change a reservation of the tenant which passes `filter`, NotFound if there is none
+    // with `reblock`, it's checked again for conflicts like a new reservation
+    async fn update(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+        id: ReservationId,
+        filter: impl FnOnce(&Reservation) -> bool + Send,
+        reblock: bool,
+        change: impl FnOnce(&mut Reservation) + Send,
+    ) -> Result<Reservation, abi::Error> {
+        let stored = fetch(conn, tenant, id)
+            .await?
+            .filter(|stored| filter(&stored.rsvp))
+            .ok_or(abi::Error::NotFound)?;
+        let before = stored.rsvp;
+        let mut blocked = stored.blocked;
+
+        let mut after = before.clone();
+        change(&mut after);
+        if reblock {
+            blocked = self.block(conn, tenant, &after).await?;
+            if after.resource_id != before.resource_id {
+                check_resource(conn, tenant, &after.resource_id).await?;
+            }
+        }
+
+        let (start, end) = span(&after)?;
+        let row: ReservationRow = sqlx::query_as(
+            r#"
+            UPDATE reservations SET status = ?, resource_id = ?, start_at = ?, end_at = ?,
+                blocked_start = ?, blocked_end = ?, note = ?, cancel_reason = ?, expires_at = ?,
+                version = version + 1
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(status_name(after.status))
+        .bind(&after.resource_id)
+        .bind(micros(start))
+        .bind(micros(end))
+        .bind(micros(blocked.0))
+        .bind(micros(blocked.1))
+        .bind(&after.note)
+        .bind(&after.cancel_reason)
+        .bind(after.expires_at().map(micros))
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+        let after = Stored::from(row).rsvp;
+
+        self.record(
+            conn,
+            tenant,
+            id,
+            ReservationUpdateType::Update,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+
+        if is_cancelled(&after) && !is_cancelled(&before) {
+            self.promote_waitlist(conn, tenant, &before.resource_id)
+                .await?;
+        }
+        Ok(after)
+    }
+
+    // the timespan a reservation blocks of its resource, which must have enough seats left
+    // during it, like the triggers of the postgres schema
+    async fn block(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+        rsvp: &Reservation,
+    ) -> Result<Span, abi::Error> {
+        let resource = fetch_resource(conn, tenant, &rsvp.resource_id).await?;
+        let blocked = blocked_span(rsvp, resource.as_ref())?;
+        if is_cancelled(rsvp) {
+            return Ok(blocked);
+        }
+        let Some(resource) = resource else {
+            // reported by check_resource
+            return Ok(blocked);
+        };
+
+        let existing = booked(conn, tenant, &rsvp.resource_id, blocked, rsvp.id).await?;
+        let seats: Vec<(Span, i32)> = existing
+            .iter()
+            .map(|stored| (intersect(stored.blocked, blocked), stored.rsvp.seats))
+            .collect();
+        let remaining = (resource.capacity - peak_seats(&seats)).max(0);
+        if rsvp.seats() <= remaining {
+            return Ok(blocked);
+        }
+
+        let existing = existing.into_iter().map(|stored| stored.rsvp).collect();
+        Err(conflict(rsvp, existing, remaining))
+    }
+
+    // reserve a reservation, or put it on the waitlist if it conflicts
+    async fn insert_or_wait(
+        &self,
+        conn: &mut SqliteConnection,
+        rsvp: Reservation,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        match self.insert(conn, rsvp.clone(), None).await {
+            Ok(rsvp) => Ok(ReserveOutcome::Reserved(Box::new(rsvp))),
+            Err(abi::Error::ConflictReservation(_)) => {
+                let (start, end) = span(&rsvp)?;
+                let id: WaitlistId = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO waitlist (tenant_id, user_id, resource_id, start_at, end_at, note,
+                        seats, buffer_before, buffer_after, created_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    RETURNING id
+                    "#,
+                )
+                .bind(&self.tenant)
+                .bind(&rsvp.user_id)
+                .bind(&rsvp.resource_id)
+                .bind(micros(start))
+                .bind(micros(end))
+                .bind(&rsvp.note)
+                .bind(rsvp.seats())
+                .bind(rsvp.buffer_before().map(delta_micros))
+                .bind(rsvp.buffer_after().map(delta_micros))
+                .bind(micros(Utc::now()))
+                .fetch_one(conn)
+                .await?;
+
+                Ok(ReserveOutcome::Waitlisted(id))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // promote the waiting entries of a resource, oldest first, whose timespan is free now,
+    // an entry keeps waiting while it breaks a booking rule or exceeds a quota of its user
+    async fn promote_waitlist(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+        resource_id: &str,
+    ) -> Result<(), abi::Error> {
+        let waiting: Vec<WaitingRow> = sqlx::query_as(
+            r#"
+            SELECT * FROM waitlist
+            WHERE tenant_id = ? AND resource_id = ? AND end_at > ?
+            ORDER BY id
+            "#,
+        )
+        .bind(tenant)
+        .bind(resource_id)
+        .bind(micros(Utc::now()))
+        .fetch_all(&mut *conn)
+        .await?;
+
+        // the quotas of the tenant of the entries, which the holds released may belong to
+        let scoped = self.clone().with_tenant(tenant);
+        for waiting in waiting {
+            let id = waiting.id;
+            let rsvp = Reservation {
+                status: ReservationStatus::Pending as i32,
+                waitlist_id: Some(id),
+                version: 1,
+                ..waiting.into()
+            };
+            let (start, end) = span(&rsvp)?;
+            if self.check_policy(resource_id, start, end).is_err() {
+                continue;
+            }
+            match scoped
+                .check_quota(conn, &rsvp.user_id, resource_id, start, end, 0)
+                .await
+            {
+                Ok(()) => {}
+                Err(abi::Error::QuotaExceeded { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+            let blocked = match self.block(conn, tenant, &rsvp).await {
+                Ok(blocked) => blocked,
+                Err(abi::Error::ConflictReservation(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            if check_resource(conn, tenant, resource_id).await.is_err() {
+                continue;
+            }
+            self.insert_row(conn, tenant, rsvp, blocked).await?;
+
+            sqlx::query("DELETE FROM waitlist WHERE id = ?")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // release the expired holds of the tenant, or of every tenant if it's None
+    async fn release_expired_of(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: Option<&str>,
+    ) -> Result<Vec<Reservation>, abi::Error> {
+        let expired: Vec<(ReservationId, String)> = sqlx::query_as(
+            r#"
+            SELECT id, tenant_id FROM reservations
+            WHERE status = 'pending' AND expires_at <= ? AND (? IS NULL OR tenant_id = ?)
+            ORDER BY id
+            "#,
+        )
+        .bind(micros(Utc::now()))
+        .bind(tenant)
+        .bind(tenant)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut released = Vec::with_capacity(expired.len());
+        for (id, tenant) in expired {
+            let rsvp = self
+                .update(
+                    conn,
+                    &tenant,
+                    id,
+                    |_| true,
+                    false,
+                    |rsvp| {
+                        rsvp.status = ReservationStatus::Cancelled as i32;
+                        rsvp.cancel_reason = Some("hold expired".to_string());
+                    },
+                )
+                .await?;
+            released.push(rsvp);
+        }
+
+        Ok(released)
+    }
+
+    async fn record(
+        &self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+        reservation_id: ReservationId,
+        op: ReservationUpdateType,
+        before: Option<&Reservation>,
+        after: Option<&Reservation>,
+    ) -> Result<(), abi::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO reservation_changes (tenant_id, reservation_id, op, changed_at, actor,
+                old_reservation, reservation)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(tenant)
+        .bind(reservation_id)
+        .bind(op.to_string())
+        .bind(micros(Utc::now()))
+        .bind(&self.actor)
+        .bind(before.map(Message::encode_to_vec))
+        .bind(after.map(Message::encode_to_vec))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    // the outcome of a change made before with its idempotency key, if it has one,
+    // the expired outcomes are dropped first
+    async fn replayed<T: Outcome>(
+        &self,
+        conn: &mut SqliteConnection,
+        keyed: Option<&Keyed>,
+    ) -> Result<Option<T>, abi::Error> {
+        let Some(keyed) = keyed else {
+            return Ok(None);
+        };
+        if let Some(expired) = Utc::now().checked_sub_signed(self.idempotency_ttl) {
+            sqlx::query("DELETE FROM idempotency_keys WHERE created_at <= ?")
+                .bind(micros(expired))
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let stored: Option<(Vec<u8>, Vec<u8>)> = sqlx::query_as(
+            r#"
+            SELECT request_hash, response FROM idempotency_keys
+            WHERE tenant_id = ? AND actor = ? AND key = ?
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(self.actor.as_deref().unwrap_or_default())
+        .bind(&keyed.key)
+        .fetch_optional(conn)
+        .await?;
+
+        stored
+            .map(|(stored_hash, response)| replay(&stored_hash, &response, &keyed.hash))
+            .transpose()
+    }
+
+    // keep the outcome of a change made with an idempotency key
+    async fn remember(
+        &self,
+        conn: &mut SqliteConnection,
+        keyed: Option<&Keyed>,
+        outcome: &impl Outcome,
+    ) -> Result<(), abi::Error> {
+        let Some(keyed) = keyed else {
+            return Ok(());
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (tenant_id, actor, key, request_hash, response, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(self.actor.as_deref().unwrap_or_default())
+        .bind(&keyed.key)
+        .bind(&keyed.hash)
+        .bind(outcome.encode())
+        .bind(micros(Utc::now()))
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    fn check_policy(
+        &self,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), abi::Error> {
+        self.policy
+            .policy_for(resource_id)
+            .check(start, end, Utc::now())
+    }
+
+    async fn check_version(
+        &self,
+        conn: &mut SqliteConnection,
+        rsvp: ReservationId,
+        expected: Option<i64>,
+    ) -> Result<(), abi::Error> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+
+        let actual = fetch(conn, &self.tenant, rsvp)
+            .await?
+            .ok_or(abi::Error::NotFound)?
+            .rsvp
+            .version;
+        if actual != expected {
+            return Err(abi::Error::VersionMismatch { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    // check the quotas of the user for a reservation of the resource, excluding the
+    // reservation being changed
+    async fn check_quota(
+        &self,
+        conn: &mut SqliteConnection,
+        user_id: &str,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        exclude: ReservationId,
+    ) -> Result<(), abi::Error> {
+        for scope in self.quota.scopes_for(resource_id) {
+            let (active, weekly) = self
+                .quota_count(conn, user_id, scope.resources, start, exclude)
+                .await?;
+            scope
+                .quota
+                .check(scope.name, active, weekly, end > Utc::now(), end - start)?;
+        }
+
+        Ok(())
+    }
+
+    // count the active reservations of the user, and the time booked by the ones starting
+    // in the week of `week`
+    async fn quota_count(
+        &self,
+        conn: &mut SqliteConnection,
+        user_id: &str,
+        resources: Option<&[String]>,
+        week: DateTime<Utc>,
+        exclude: ReservationId,
+    ) -> Result<(i64, TimeDelta), abi::Error> {
+        let rows: Vec<ReservationRow> = sqlx::query_as(
+            r#"
+            SELECT * FROM reservations
+            WHERE tenant_id = ? AND user_id = ? AND status <> 'cancelled' AND id <> ?
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(exclude)
+        .fetch_all(conn)
+        .await?;
+
+        let counted: Vec<Reservation> = rows
+            .into_iter()
+            .filter(|row| resources.is_none_or(|ids| ids.contains(&row.resource_id)))
+            .map(|row| Stored::from(row).rsvp)
+            .collect();
+        Ok(quota_count(counted.iter(), week))
+    }
+}
+
+impl Rsvp for SqliteReservationManager {
+    async fn reserve(&self, rsvp: Reservation) -> Result<Reservation, abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let rsvp = self.insert(&mut tx, rsvp, None).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn reserve_or_wait(&self, rsvp: Reservation) -> Result<ReserveOutcome, abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let outcome = self.insert_or_wait(&mut tx, rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(outcome)
+    }
+
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: Reservation,
+        waitlist: bool,
+    ) -> Result<ReserveOutcome, abi::Error> {
+        let keyed = keyed(Some(&key), || reserve_fingerprint(&rsvp, waitlist))?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+
+        let outcome = if waitlist {
+            self.insert_or_wait(&mut tx, rsvp).await?
+        } else {
+            ReserveOutcome::Reserved(Box::new(self.insert(&mut tx, rsvp, None).await?))
+        };
+        self.remember(&mut tx, keyed.as_ref(), &outcome).await?;
+        self.commit(tx).await?;
+
+        Ok(outcome)
+    }
+
+    async fn reserve_many(&self, rsvps: Vec<Reservation>) -> Result<Vec<Reservation>, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || batch_fingerprint(&rsvps))?;
+
+        // nothing is kept if any reservation fails
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        let mut reserved = Vec::with_capacity(rsvps.len());
+        for (index, rsvp) in rsvps.into_iter().enumerate() {
+            let rsvp = self.insert(&mut tx, rsvp, None).await.map_err(|e| {
+                abi::Error::BatchReservation {
+                    index,
+                    source: Box::new(e),
+                }
+            })?;
+            reserved.push(rsvp);
+        }
+        self.remember(&mut tx, keyed.as_ref(), &reserved).await?;
+        self.commit(tx).await?;
+
+        Ok(reserved)
+    }
+
+    async fn reserve_series(
+        &self,
+        series: abi::ReservationSeries,
+        mode: SeriesConflictMode,
+    ) -> Result<(abi::ReservationSeries, Vec<Reservation>, Vec<Reservation>), abi::Error> {
+        let occurrences = series.occurrences()?;
+        let Some(first) = occurrences.first() else {
+            return Err(abi::Error::InvalidRecurrenceRule(
+                "no occurrence".to_string(),
+            ));
+        };
+        let (start, end) = span(first)?;
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            series_fingerprint(&series, mode)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO reservation_series (tenant_id, user_id, resource_id, start_at, end_at,
+                rrule, note)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(&series.user_id)
+        .bind(&series.resource_id)
+        .bind(micros(start))
+        .bind(micros(end))
+        .bind(&series.rrule)
+        .bind(&series.note)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let mut reserved = Vec::with_capacity(occurrences.len());
+        let mut conflicts = Vec::new();
+        let mut conflict_infos = Vec::new();
+        for mut rsvp in occurrences {
+            rsvp.series_id = Some(id);
+
+            // a conflicting insert writes nothing, so the others are still reserved
+            match self.insert(&mut tx, rsvp.clone(), Some(id)).await {
+                Ok(rsvp) => reserved.push(rsvp),
+                Err(abi::Error::ConflictReservation(info)) => {
+                    conflict_infos.push(info);
+                    conflicts.push(rsvp);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if mode == SeriesConflictMode::Fail && !conflict_infos.is_empty() {
+            return Err(abi::Error::ConflictSeries(conflict_infos));
+        }
+        let outcome = (abi::ReservationSeries { id, ..series }, reserved, conflicts);
+        self.remember(&mut tx, keyed.as_ref(), &outcome).await?;
+        self.commit(tx).await?;
+
+        Ok(outcome)
+    }
+
+    async fn delete(&self, rsvp: ReservationId) -> Result<(), abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let stored = fetch(&mut tx, &self.tenant, rsvp)
+            .await?
+            .ok_or(abi::Error::NotFound)?;
+        sqlx::query("DELETE FROM reservations WHERE id = ?")
+            .bind(rsvp)
+            .execute(&mut *tx)
+            .await?;
+
+        // the deleted reservation is kept as tombstone
+        self.record(
+            &mut tx,
+            &self.tenant,
+            rsvp,
+            ReservationUpdateType::Delete,
+            Some(&stored.rsvp),
+            None,
+        )
+        .await?;
+        if !is_cancelled(&stored.rsvp) {
+            self.promote_waitlist(&mut tx, &self.tenant, &stored.rsvp.resource_id)
+                .await?;
+        }
+        self.commit(tx).await?;
+
+        Ok(())
+    }
+
+    async fn change_status(
+        &self,
+        rsvp: ReservationId,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            confirm_fingerprint(rsvp, expected_version)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        // if a reservation is pending and not expired, it will be confirmed
+        let now = Utc::now();
+        let pending = |rsvp: &Reservation| rsvp.status == ReservationStatus::Pending as i32;
+        let result = self
+            .update(
+                &mut tx,
+                &self.tenant,
+                rsvp,
+                |rsvp| pending(rsvp) && rsvp.expires_at().is_none_or(|at| at > now),
+                false,
+                |rsvp| {
+                    rsvp.status = ReservationStatus::Confirmed as i32;
+                    rsvp.expires_at = None;
+                },
+            )
+            .await;
+
+        // tell an expired hold apart from a reservation that can't be confirmed
+        let rsvp = match result {
+            Err(abi::Error::NotFound) => {
+                let stored = fetch(&mut tx, &self.tenant, rsvp).await?;
+                return Err(match stored {
+                    Some(stored) if pending(&stored.rsvp) => abi::Error::HoldExpired,
+                    _ => abi::Error::NotFound,
+                });
+            }
+            result => result?,
+        };
+        self.remember(&mut tx, keyed.as_ref(), &rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn cancel(
+        &self,
+        rsvp: ReservationId,
+        reason: Option<String>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            cancel_fingerprint(rsvp, reason.as_ref(), expected_version)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        // a cancelled reservation can't be cancelled again
+        let rsvp = self
+            .update(
+                &mut tx,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                false,
+                |rsvp| {
+                    rsvp.status = ReservationStatus::Cancelled as i32;
+                    rsvp.cancel_reason = reason;
+                },
+            )
+            .await?;
+        self.remember(&mut tx, keyed.as_ref(), &rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn reschedule(
+        &self,
+        rsvp: ReservationId,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        if start >= end {
+            return Err(abi::Error::InvalidTimespan);
+        }
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            reschedule_fingerprint(rsvp, start, end, expected_version)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        let current = fetch(&mut tx, &self.tenant, rsvp)
+            .await?
+            .ok_or(abi::Error::NotFound)?
+            .rsvp;
+        self.check_policy(&current.resource_id, start, end)?;
+        self.check_quota(
+            &mut tx,
+            &current.user_id,
+            &current.resource_id,
+            start,
+            end,
+            rsvp,
+        )
+        .await?;
+
+        let rsvp = self
+            .update(
+                &mut tx,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                true,
+                |rsvp| {
+                    rsvp.start = Some(datetime_to_timestamp(start));
+                    rsvp.end = Some(datetime_to_timestamp(end));
+                },
+            )
+            .await?;
+        self.remember(&mut tx, keyed.as_ref(), &rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn change_resource(
+        &self,
+        rsvp: ReservationId,
+        resource_id: String,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            change_resource_fingerprint(rsvp, &resource_id, expected_version)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        // the booking rules and the quotas of the new resource apply
+        let current = fetch(&mut tx, &self.tenant, rsvp)
+            .await?
+            .ok_or(abi::Error::NotFound)?
+            .rsvp;
+        let (start, end) = span(&current)?;
+        self.check_policy(&resource_id, start, end)?;
+        self.check_quota(&mut tx, &current.user_id, &resource_id, start, end, rsvp)
+            .await?;
+
+        let rsvp = self
+            .update(
+                &mut tx,
+                &self.tenant,
+                rsvp,
+                |rsvp| !is_cancelled(rsvp),
+                true,
+                |rsvp| rsvp.resource_id = resource_id,
+            )
+            .await?;
+        self.remember(&mut tx, keyed.as_ref(), &rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn release_expired(&self) -> Result<Vec<Reservation>, abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let released = self.release_expired_of(&mut tx, Some(&self.tenant)).await?;
+        self.commit(tx).await?;
+
+        Ok(released)
+    }
+
+    async fn update_notes(
+        &self,
+        rsvp: ReservationId,
+        note: String,
+        expected_version: Option<i64>,
+    ) -> Result<Reservation, abi::Error> {
+        let keyed = keyed(self.idempotency_key.as_ref(), || {
+            update_fingerprint(rsvp, &note, expected_version)
+        })?;
+
+        let (_lock, mut tx) = self.begin().await?;
+        if let Some(outcome) = self.replayed(&mut tx, keyed.as_ref()).await? {
+            self.commit(tx).await?;
+            return Ok(outcome);
+        }
+        self.check_version(&mut tx, rsvp, expected_version).await?;
+
+        let rsvp = self
+            .update(
+                &mut tx,
+                &self.tenant,
+                rsvp,
+                |_| true,
+                false,
+                |rsvp| rsvp.note = note,
+            )
+            .await?;
+        self.remember(&mut tx, keyed.as_ref(), &rsvp).await?;
+        self.commit(tx).await?;
+
+        Ok(rsvp)
+    }
+
+    async fn get(&self, rsvp: ReservationId) -> Result<Reservation, abi::Error> {
+        let mut conn = self.pool.acquire().await?;
+        let stored = fetch(&mut conn, &self.tenant, rsvp)
+            .await?
+            .ok_or(abi::Error::NotFound)?;
+
+        Ok(stored.rsvp)
+    }
+
+    async fn get_history(
+        &self,
+        rsvp: ReservationId,
+    ) -> Result<Vec<abi::ReservationChange>, abi::Error> {
+        let rows: Vec<ChangeRow> = sqlx::query_as(
+            r#"
+            SELECT * FROM reservation_changes
+            WHERE tenant_id = ? AND reservation_id = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(rsvp)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Err(abi::Error::NotFound);
+        }
+        rows.into_iter()
+            .map(|row| {
+                Ok(abi::ReservationChange {
+                    id: row.id,
+                    op: update_type(&row.op) as i32,
+                    changed_at: Some(datetime_to_timestamp(datetime(row.changed_at))),
+                    actor: row.actor,
+                    before: row.old_reservation.as_deref().map(decode).transpose()?,
+                    after: row.reservation.as_deref().map(decode).transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    async fn query(
+        &self,
+        query: abi::ReservationQuery,
+    ) -> Result<mpsc::Receiver<Result<Reservation, abi::Error>>, abi::Error> {
+        query.timespan()?;
+        let from = query.start.as_ref().map(timestamp_to_datetime);
+        let to = query.end.as_ref().map(timestamp_to_datetime);
+        let page = query.page.max(1) as i64;
+        let page_size = page_size(query.page_size) as i64;
+
+        let sql = format!(
+            r#"
+            SELECT * FROM reservations
+            WHERE tenant_id = ?1 AND (?2 IS NULL OR ?2 <= start_at) AND (?3 IS NULL OR end_at <= ?3)
+                AND (?4 IS NULL OR user_id = ?4) AND (?5 IS NULL OR resource_id = ?5)
+                AND (?6 = 'unknown' OR status = ?6)
+            ORDER BY start_at {}
+            LIMIT ?7 OFFSET ?8
+            "#,
+            if query.is_desc { "DESC" } else { "ASC" }
+        );
+        let rows: Vec<ReservationRow> = sqlx::query_as(&sql)
+            .bind(&self.tenant)
+            .bind(from.map(micros))
+            .bind(to.map(micros))
+            .bind(&query.user_id)
+            .bind(&query.resource_id)
+            .bind(status_name(query.status))
+            .bind(page_size)
+            .bind((page - 1) * page_size)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            for row in rows {
+                if tx.send(Ok(Stored::from(row).rsvp)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    async fn filter(
+        &self,
+        filter: abi::ReservationFilter,
+    ) -> Result<(abi::FilterPager, Vec<Reservation>), abi::Error> {
+        let mut filter = filter;
+        if filter.is_prev {
+            filter.is_desc = !filter.is_desc;
+        }
+        filter.page_size = page_size(filter.page_size) as i32;
+        let mut cursor = filter.cursor.max(0);
+        if cursor == 0 && filter.is_desc {
+            cursor = i64::MAX;
+        }
+
+        // filter by user_id, resource_id, status and order by start
+        let sql = format!(
+            r#"
+            SELECT * FROM reservations
+            WHERE tenant_id = ?1 AND id {} ?2
+                AND (?3 IS NULL OR user_id = ?3) AND (?4 IS NULL OR resource_id = ?4)
+                AND (?5 = 'unknown' OR status = ?5)
+            ORDER BY start_at {}
+            LIMIT ?6
+            "#,
+            if filter.is_desc { "<" } else { ">" },
+            if filter.is_desc { "DESC" } else { "ASC" }
+        );
+        let rows: Vec<ReservationRow> = sqlx::query_as(&sql)
+            .bind(&self.tenant)
+            .bind(cursor)
+            .bind(&filter.user_id)
+            .bind(&filter.resource_id)
+            .bind(status_name(filter.status))
+            .bind(filter.page_size)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut rsvps: Vec<Reservation> =
+            rows.into_iter().map(|row| Stored::from(row).rsvp).collect();
+        if filter.is_prev {
+            rsvps.reverse();
+        }
+
+        Ok((pager(&filter, &rsvps), rsvps))
+    }
+
+    async fn find_availability(
+        &self,
+        resource_id: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        duration: TimeDelta,
+    ) -> Result<Vec<abi::TimeSlot>, abi::Error> {
+        if start >= end || duration <= TimeDelta::zero() {
+            return Err(abi::Error::InvalidTimespan);
+        }
+
+        let mut conn = self.pool.acquire().await?;
+        let resource = fetch_resource(&mut conn, &self.tenant, &resource_id).await?;
+        let capacity = resource.as_ref().map_or(1, |r| r.capacity);
+        let before = resource
+            .as_ref()
+            .map(|r| r.buffer_before())
+            .unwrap_or_default();
+        let after = resource
+            .as_ref()
+            .map(|r| r.buffer_after())
+            .unwrap_or_default();
+
+        // a free timespan has to leave room for the buffers around a reservation
+        let window = (start - before, end + after);
+        let booked: Vec<(Span, i32)> = booked(&mut conn, &self.tenant, &resource_id, window, 0)
+            .await?
+            .into_iter()
+            .map(|stored| (intersect(stored.blocked, window), stored.rsvp.seats))
+            .collect();
+
+        Ok(slots(
+            free_spans(window, &booked, capacity),
+            before,
+            after,
+            duration,
+        ))
+    }
+
+    async fn quota_usage(
+        &self,
+        user_id: String,
+        week: DateTime<Utc>,
+    ) -> Result<Vec<abi::QuotaUsage>, abi::Error> {
+        if user_id.is_empty() {
+            return Err(abi::Error::InvalidUserId);
+        }
+
+        let mut conn = self.pool.acquire().await?;
+        let mut usages = Vec::new();
+        for scope in self.quota.scopes() {
+            let (active, weekly) = self
+                .quota_count(&mut conn, &user_id, scope.resources, week, 0)
+                .await?;
+            usages.push(scope.quota.usage(scope.name, active, weekly));
+        }
+
+        Ok(usages)
+    }
+
+    async fn create_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
+        let resource = normalize(resource);
+
+        let (_lock, mut tx) = self.begin().await?;
+        if fetch_resource(&mut tx, &self.tenant, &resource.id)
+            .await?
+            .is_some()
+        {
+            return Err(abi::Error::ResourceAlreadyExists);
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO resources (tenant_id, id, name, location, capabilities, disabled, capacity,
+                buffer_before, buffer_after)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&self.tenant)
+        .bind(&resource.id)
+        .bind(&resource.name)
+        .bind(&resource.location)
+        .bind(capabilities(&resource))
+        .bind(resource.disabled)
+        .bind(resource.capacity)
+        .bind(delta_micros(resource.buffer_before()))
+        .bind(delta_micros(resource.buffer_after()))
+        .execute(&mut *tx)
+        .await?;
+        self.commit(tx).await?;
+
+        Ok(resource)
+    }
+
+    async fn get_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_resource(&mut conn, &self.tenant, &id)
+            .await?
+            .ok_or(abi::Error::NotFound)
+    }
+
+    async fn update_resource(&self, resource: abi::Resource) -> Result<abi::Resource, abi::Error> {
+        resource.validate()?;
+        let resource = normalize(resource);
+
+        let (_lock, mut tx) = self.begin().await?;
+        let updated = sqlx::query(
+            r#"
+            UPDATE resources SET name = ?, location = ?, capabilities = ?, disabled = ?,
+                capacity = ?, buffer_before = ?, buffer_after = ?
+            WHERE tenant_id = ? AND id = ?
+            "#,
+        )
+        .bind(&resource.name)
+        .bind(&resource.location)
+        .bind(capabilities(&resource))
+        .bind(resource.disabled)
+        .bind(resource.capacity)
+        .bind(delta_micros(resource.buffer_before()))
+        .bind(delta_micros(resource.buffer_after()))
+        .bind(&self.tenant)
+        .bind(&resource.id)
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(abi::Error::NotFound);
+        }
+        self.commit(tx).await?;
+
+        Ok(resource)
+    }
+
+    async fn delete_resource(&self, id: String) -> Result<abi::Resource, abi::Error> {
+        let (_lock, mut tx) = self.begin().await?;
+        let resource = fetch_resource(&mut tx, &self.tenant, &id)
+            .await?
+            .ok_or(abi::Error::NotFound)?;
+
+        // cancelled reservations still reference it
+        let in_use: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM reservations WHERE tenant_id = ? AND resource_id = ?)",
+        )
+        .bind(&self.tenant)
+        .bind(&id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if in_use {
+            return Err(abi::Error::ResourceInUse);
+        }
+
+        sqlx::query("DELETE FROM resources WHERE tenant_id = ? AND id = ?")
+            .bind(&self.tenant)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+        self.commit(tx).await?;
+
+        Ok(resource)
+    }
+
+    async fn list_resources(&self) -> Result<Vec<abi::Resource>, abi::Error> {
+        let rows: Vec<ResourceRow> =
+            sqlx::query_as("SELECT * FROM resources WHERE tenant_id = ? ORDER BY id")
+                .bind(&self.tenant)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(rows.into_iter().map(abi::Resource::from).collect())
+    }
+
+    async fn listen(
+        &self,
+    ) -> Result<mpsc::Receiver<Result<abi::ListenResponse, abi::Error>>, abi::Error> {
+        // only changes made after the call will be sent, the updates are subscribed to
+        // before the last change is read, so no change is missed in between
+        let mut updates = self.updates.subscribe();
+        let mut cursor: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM reservation_changes")
+                .fetch_one(&self.pool)
+                .await?;
+        let pool = self.pool.clone();
+        let tenant = self.tenant.clone();
+
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            'listen: loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    changed = updates.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                // an update may stand for several changes, read all of them
+                let changes: Result<Vec<ChangeRow>, sqlx::Error> = sqlx::query_as(
+                    "SELECT * FROM reservation_changes WHERE id > ? AND tenant_id = ? ORDER BY id",
+                )
+                .bind(cursor)
+                .bind(&tenant)
+                .fetch_all(&pool)
+                .await;
+
+                let changes = match changes {
+                    Ok(changes) => changes,
+                    Err(e) => {
+                        let _ = tx.send(Err(e.into())).await;
+                        break;
+                    }
+                };
+
+                for row in changes {
+                    cursor = row.id;
+                    // a deleted reservation is sent with its last state
+                    let snapshot = row.reservation.or(row.old_reservation).unwrap_or_default();
+                    let change = decode(&snapshot).map(|rsvp| abi::ListenResponse {
+                        op: update_type(&row.op) as i32,
+                        reservation: Some(rsvp),
+                    });
+                    if tx.send(change).await.is_err() {
+                        break 'listen;
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+impl From<ReservationRow> for Stored {
+    fn from(row: ReservationRow) -> Self {
+        let rsvp = Reservation {
+            id: row.id,
+            user_id: row.user_id,
+            status: status(&row.status) as i32,
+            resource_id: row.resource_id,
+            start: Some(datetime_to_timestamp(datetime(row.start_at))),
+            end: Some(datetime_to_timestamp(datetime(row.end_at))),
+            note: row.note,
+            cancel_reason: row.cancel_reason,
+            series_id: row.series_id,
+            expires_at: row.expires_at.map(datetime).map(datetime_to_timestamp),
+            seats: row.seats,
+            buffer_before: row.buffer_before.map(delta).map(timedelta_to_duration),
+            buffer_after: row.buffer_after.map(delta).map(timedelta_to_duration),
+            waitlist_id: row.waitlist_id,
+            version: row.version,
+        };
+
+        Self {
+            rsvp,
+            blocked: (datetime(row.blocked_start), datetime(row.blocked_end)),
+        }
+    }
+}
+
+impl From<ResourceRow> for abi::Resource {
+    fn from(row: ResourceRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            location: row.location,
+            capabilities: serde_json::from_str(&row.capabilities).unwrap_or_default(),
+            disabled: row.disabled,
+            capacity: row.capacity,
+            buffer_before: Some(timedelta_to_duration(delta(row.buffer_before))),
+            buffer_after: Some(timedelta_to_duration(delta(row.buffer_after))),
+        }
+    }
+}
+
+impl From<WaitingRow> for Reservation {
+    fn from(row: WaitingRow) -> Self {
+        Self {
+            user_id: row.user_id,
+            resource_id: row.resource_id,
+            start: Some(datetime_to_timestamp(datetime(row.start_at))),
+            end: Some(datetime_to_timestamp(datetime(row.end_at))),
+            note: row.note,
+            seats: row.seats,
+            buffer_before: row.buffer_before.map(delta).map(timedelta_to_duration),
+            buffer_after: row.buffer_after.map(delta).map(timedelta_to_duration),
+            ..Default::default()
+        }
+    }
+}
+
+async fn fetch(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    id: ReservationId,
+) -> Result<Option<Stored>, abi::Error> {
+    let row: Option<ReservationRow> =
+        sqlx::query_as("SELECT * FROM reservations WHERE tenant_id = ? AND id = ?")
+            .bind(tenant)
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+
+    Ok(row.map(Stored::from))
+}
+
+async fn fetch_resource(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    id: &str,
+) -> Result<Option<abi::Resource>, abi::Error> {
+    let row: Option<ResourceRow> =
+        sqlx::query_as("SELECT * FROM resources WHERE tenant_id = ? AND id = ?")
+            .bind(tenant)
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+
+    Ok(row.map(abi::Resource::from))
+}
+
+// the reservations of a resource which aren't cancelled and block some of `during`, but the
+// excluded one, ordered by the start of their blocked timespan
+async fn booked(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    resource_id: &str,
+    during: Span,
+    exclude: ReservationId,
+) -> Result<Vec<Stored>, abi::Error> {
+    let rows: Vec<ReservationRow> = sqlx::query_as(
+        r#"
+        SELECT * FROM reservations
+        WHERE tenant_id = ? AND resource_id = ? AND status <> 'cancelled'
+            AND blocked_start < ? AND ? < blocked_end AND id <> ?
+        ORDER BY blocked_start, id
+        "#,
+    )
+    .bind(tenant)
+    .bind(resource_id)
+    .bind(micros(during.1))
+    .bind(micros(during.0))
+    .bind(exclude)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.into_iter().map(Stored::from).collect())
+}
+
+// like reservations_resource_trigger, only an enabled resource of the tenant can be reserved
+async fn check_resource(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    resource_id: &str,
+) -> Result<(), abi::Error> {
+    match fetch_resource(conn, tenant, resource_id).await? {
+        Some(resource) if !resource.disabled => Ok(()),
+        _ => Err(abi::Error::ResourceNotAvailable(resource_id.to_string())),
+    }
+}
+
+fn micros(t: DateTime<Utc>) -> i64 {
+    t.timestamp_micros()
+}
+
+fn datetime(micros: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(micros).unwrap_or_default()
+}
+
+fn delta_micros(delta: TimeDelta) -> i64 {
+    delta.num_microseconds().unwrap_or(i64::MAX)
+}
+
+fn delta(micros: i64) -> TimeDelta {
+    TimeDelta::microseconds(micros)
+}
+
+fn capabilities(resource: &abi::Resource) -> String {
+    serde_json::to_string(&resource.capabilities).unwrap_or_else(|_| "[]".to_string())
+}
+
+// statuses and update types are stored by the names the postgres enums have
+fn status_name(status: i32) -> String {
+    ReservationStatus::try_from(status)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn status(name: &str) -> ReservationStatus {
+    [
+        ReservationStatus::Pending,
+        ReservationStatus::Confirmed,
+        ReservationStatus::Blocked,
+        ReservationStatus::Cancelled,
+    ]
+    .into_iter()
+    .find(|status| status.to_string() == name)
+    .unwrap_or_default()
+}
+
+fn update_type(name: &str) -> ReservationUpdateType {
+    [
+        ReservationUpdateType::Create,
+        ReservationUpdateType::Update,
+        ReservationUpdateType::Delete,
+        ReservationUpdateType::Promote,
+    ]
+    .into_iter()
+    .find(|op| op.to_string() == name)
+    .unwrap_or_default()
+}
+
+// a reservation from its snapshot
+fn decode(snapshot: &[u8]) -> Result<Reservation, abi::Error> {
+    <Reservation as Message>::decode(snapshot).map_err(|e| sqlx::Error::Decode(Box::new(e)).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::suite::{suite, with_fixtures};
+
+    async fn manager() -> SqliteReservationManager {
+        let manager = SqliteReservationManager::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        with_fixtures(manager).await
+    }
+
+    suite!(#[tokio::test] () => manager().await);
+
+    #[tokio::test]
+    async fn writers_of_other_processes_should_wait_for_the_capacity_check() {
+        let path = std::env::temp_dir().join(format!("rsvp-{}.db", std::process::id()));
+        let url = format!("sqlite://{}", path.display());
+        let manager = with_fixtures(SqliteReservationManager::connect(&url).await.unwrap()).await;
+        // a manager of another process doesn't share the lock of the first one
+        let other = SqliteReservationManager::connect(&url).await.unwrap();
+
+        let rsvp = crate::suite::default_rsvp();
+        let tasks = (0..8).map(|i| {
+            let manager = if i % 2 == 0 {
+                manager.clone()
+            } else {
+                other.clone()
+            };
+            let rsvp = rsvp.clone();
+            tokio::spawn(async move { manager.reserve(rsvp).await })
+        });
+        let mut reserved = 0;
+        for task in tasks.collect::<Vec<_>>() {
+            match task.await.unwrap() {
+                Ok(_) => reserved += 1,
+                Err(abi::Error::ConflictReservation(_)) => {}
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        }
+        assert_eq!(reserved, 1);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
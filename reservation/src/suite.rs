@@ -0,0 +1,1742 @@
+//! The behavior every `Rsvp` backend shares, each test takes a manager of the backend whose
+//! default tenant has the resources of `fixtures/resources.sql`. `suite!` runs all of them
+//! for a backend.
+
+use abi::{
+    config::{IdempotencyConfig, PolicyConfig, QuotaConfig},
+    error::conflict::{ReservationConflict, ReservationConflictInfo},
+    policy::PolicyRule,
+    utils::timestamp_to_datetime,
+    ReservationStatus, ReservationUpdateType, SeriesConflictMode,
+};
+use chrono::{DateTime, Duration, TimeDelta, Utc};
+
+use crate::{InMemoryReservationManager, ReservationManager, ReserveOutcome, Rsvp, Scoped};
+
+/// A backend the suite runs on, configured like the service configures it
+pub(crate) trait Backend: Scoped + Send + Sync + 'static {
+    fn with_policy(self, policy: PolicyConfig) -> Self;
+
+    fn with_quota(self, quota: QuotaConfig) -> Self;
+
+    fn with_idempotency(self, idempotency: IdempotencyConfig) -> Self;
+}
+
+macro_rules! backend {
+    ($($manager:ty),*) => {
+        $(
+            impl Backend for $manager {
+                fn with_policy(self, policy: PolicyConfig) -> Self {
+                    <$manager>::with_policy(self, policy)
+                }
+
+                fn with_quota(self, quota: QuotaConfig) -> Self {
+                    <$manager>::with_quota(self, quota)
+                }
+
+                fn with_idempotency(self, idempotency: IdempotencyConfig) -> Self {
+                    <$manager>::with_idempotency(self, idempotency)
+                }
+            }
+        )*
+    };
+}
+
+backend!(ReservationManager, InMemoryReservationManager);
+#[cfg(feature = "sqlite")]
+backend!(crate::SqliteReservationManager);
+
+/// Register the resources of `fixtures/resources.sql`, for the backends which can't load it
+pub(crate) async fn with_fixtures<R: Rsvp>(manager: R) -> R {
+    let resources = [
+        ("resource", "resource", 1),
+        ("resource1", "resource 1", 1),
+        ("room", "room", 1),
+        ("room1", "room 1", 1),
+        ("projector", "projector", 1),
+        ("parking", "parking", 1),
+        ("lot", "parking lot", 3),
+    ];
+    for (id, name, capacity) in resources {
+        let resource = abi::Resource {
+            capacity,
+            ..abi::Resource::new(id, name)
+        };
+        manager.create_resource(resource).await.unwrap();
+    }
+
+    manager
+}
+
+/// Run the whole suite on a backend: `#[test attribute] (test arguments) => manager`
+macro_rules! suite {
+    (@tests #[$attr:meta] $args:tt => $manager:expr; $($test:ident),*) => {
+        $(
+            #[$attr]
+            async fn $test $args {
+                $crate::suite::$test($manager).await;
+            }
+        )*
+    };
+    (#[$attr:meta] $args:tt => $manager:expr) => {
+        $crate::suite::suite!(
+            @tests #[$attr] $args => $manager;
+            reserve_should_work_with_valid_timespan,
+            reserve_should_fail_with_invalid_timespan,
+            reserve_should_not_take_series_or_waitlist_from_caller,
+            reserve_should_fail_with_conflicting_timespan,
+            conflict_should_report_existing_reservations,
+            reserve_many_should_work,
+            reserve_many_should_rollback_on_conflict,
+            reserve_series_should_work,
+            reserve_series_should_skip_conflicts,
+            reserve_series_should_fail_on_conflict,
+            reserve_series_should_fail_with_invalid_rrule,
+            reservation_can_be_confirmed,
+            change_reservation_again_should_do_nothing,
+            cancel_should_keep_reservation_and_free_timespan,
+            cancel_again_should_fail,
+            reschedule_should_work,
+            reschedule_should_fail_with_conflicting_timespan,
+            reschedule_should_fail_with_invalid_timespan,
+            change_resource_should_work,
+            change_resource_should_fail_with_conflicting_timespan,
+            change_resource_should_enforce_policy_and_quota,
+            confirm_hold_should_clear_expires_at,
+            confirm_expired_hold_should_fail,
+            release_expired_should_free_timespan,
+            update_notes_should_work,
+            update_with_stale_version_should_fail,
+            get_history_should_record_every_change,
+            tenants_should_be_isolated,
+            get_should_work,
+            delete_should_work,
+            delete_null_should_fail,
+            query_should_work,
+            query_should_work_with_user_id,
+            query_should_work_with_resource_id,
+            query_should_work_with_status,
+            query_should_work_with_timespan,
+            filter_should_work,
+            reserve_should_respect_capacity,
+            reserve_with_invalid_seats_should_fail,
+            reserve_should_respect_buffers,
+            reserve_should_enforce_policy,
+            reserve_should_enforce_quota,
+            concurrent_reserve_should_not_exceed_quota,
+            reserve_idempotent_should_return_first_outcome,
+            reserve_idempotent_should_reserve_again_after_ttl,
+            reserve_idempotent_should_be_scoped_to_actor_and_request,
+            changes_with_idempotency_key_should_be_made_once,
+            reserve_or_wait_should_promote_on_cancel,
+            promotion_should_respect_quota,
+            find_availability_should_return_free_slots,
+            resource_crud_should_work,
+            reserve_unknown_or_disabled_resource_should_fail,
+            delete_resource_in_use_should_fail,
+            listen_should_receive_changes
+        );
+    };
+}
+
+pub(crate) use suite;
+
+pub(crate) fn default_rsvp() -> abi::Reservation {
+    abi::Reservation::new_pendding(
+        "user",
+        "resource",
+        DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2021-01-02T00:00:00Z").unwrap(),
+        "note",
+    )
+}
+
+pub(crate) async fn reserve_should_work_with_valid_timespan(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    assert!(rsvp.id != 0);
+}
+
+pub(crate) async fn reserve_should_fail_with_invalid_timespan(manager: impl Backend) {
+    let rsvp = abi::Reservation::new_pendding(
+        "user",
+        "resource",
+        DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2021-01-01T00:00:00Z").unwrap(),
+        "note",
+    );
+
+    let result = manager.reserve(rsvp).await;
+
+    assert!(matches!(result, Err(abi::Error::InvalidTimespan)));
+}
+
+pub(crate) async fn reserve_should_not_take_series_or_waitlist_from_caller(manager: impl Backend) {
+    let rsvp = abi::Reservation {
+        series_id: Some(42),
+        waitlist_id: Some(42),
+        ..default_rsvp()
+    };
+
+    let reserved = manager.reserve(rsvp.clone()).await.unwrap();
+    assert_eq!(reserved.series_id, None);
+    assert_eq!(reserved.waitlist_id, None);
+
+    let other = abi::Reservation {
+        resource_id: "resource1".to_string(),
+        ..rsvp
+    };
+    let reserved = manager.reserve_many(vec![other]).await.unwrap();
+    let stored = manager.get(reserved[0].id).await.unwrap();
+    assert_eq!(stored.series_id, None);
+    assert_eq!(stored.waitlist_id, None);
+}
+
+pub(crate) async fn reserve_should_fail_with_conflicting_timespan(manager: impl Backend) {
+    let conflict_start = DateTime::parse_from_rfc3339("2021-01-01T12:00:00Z").unwrap();
+    let conflict_end = DateTime::parse_from_rfc3339("2021-01-02T12:00:00Z").unwrap();
+
+    let rsvp = default_rsvp();
+
+    let existing = manager.reserve(rsvp).await.unwrap();
+    assert!(existing.id != 0);
+
+    let rsvp =
+        abi::Reservation::new_pendding("user", "resource", conflict_start, conflict_end, "note");
+
+    let result = manager.reserve(rsvp).await;
+
+    assert!(matches!(result, Err(abi::Error::ConflictReservation(_))));
+    match result.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            let ReservationConflict {
+                new,
+                old,
+                existing_id,
+                ..
+            } = *conflict;
+            assert_eq!(existing_id, Some(existing.id));
+            assert_eq!(new.resource_id, "resource");
+            assert_eq!(old.resource_id, "resource");
+            assert_eq!(new.start, conflict_start);
+            assert_eq!(new.end, conflict_end);
+        }
+
+        e => {
+            eprintln!("{:?}", e);
+            panic!("Unexpected error");
+        }
+    };
+}
+
+pub(crate) async fn conflict_should_report_existing_reservations(manager: impl Backend) {
+    manager
+        .create_resource(abi::Resource {
+            id: "room-1.a".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let rsvp = |start: &str, end: &str| {
+        abi::Reservation::new_pendding(
+            "user",
+            "room-1.a",
+            DateTime::parse_from_rfc3339(start).unwrap(),
+            DateTime::parse_from_rfc3339(end).unwrap(),
+            "note",
+        )
+    };
+
+    let existing = manager
+        .reserve(rsvp("2021-01-01T00:00:00.5Z", "2021-01-02T00:00:00Z"))
+        .await
+        .unwrap();
+    let result = manager
+        .reserve(rsvp("2021-01-01T12:00:00.25Z", "2021-01-03T00:00:00Z"))
+        .await;
+    match result.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            assert_eq!(conflict.new.resource_id, "room-1.a");
+            assert_eq!(
+                conflict.new.start,
+                DateTime::parse_from_rfc3339("2021-01-01T12:00:00.25Z").unwrap()
+            );
+            assert_eq!(conflict.existing.len(), 1);
+            assert_eq!(conflict.existing[0].id, existing.id);
+            assert_eq!(conflict.existing[0].start, existing.start);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    // the id of the request doesn't hide the reservation with that id
+    let mut same_id = rsvp("2021-01-01T12:00:00Z", "2021-01-03T00:00:00Z");
+    same_id.id = existing.id;
+    match manager.reserve(same_id).await.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            assert_eq!(conflict.existing.len(), 1);
+            assert_eq!(conflict.existing[0].id, existing.id);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    // the reservations of the batch which aren't committed yet are found too
+    let result = manager
+        .reserve_many(vec![
+            rsvp("2021-01-05T00:00:00Z", "2021-01-06T00:00:00Z"),
+            rsvp("2021-01-05T12:00:00Z", "2021-01-07T00:00:00Z"),
+        ])
+        .await;
+    match result.unwrap_err() {
+        abi::Error::BatchReservation { index, source } => {
+            assert_eq!(index, 1);
+            let abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) =
+                *source
+            else {
+                panic!("Unexpected error: {:?}", source);
+            };
+            assert_eq!(conflict.existing.len(), 1);
+            assert_eq!(conflict.existing[0].resource_id, "room-1.a");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+pub(crate) async fn reserve_many_should_work(manager: impl Backend) {
+    let mut projector = default_rsvp();
+    projector.resource_id = "projector".to_string();
+
+    let rsvps = manager
+        .reserve_many(vec![default_rsvp(), projector])
+        .await
+        .unwrap();
+    assert_eq!(rsvps.len(), 2);
+    assert!(rsvps.iter().all(|r| r.id != 0));
+    assert_eq!(rsvps[1].resource_id, "projector");
+}
+
+pub(crate) async fn reserve_many_should_rollback_on_conflict(manager: impl Backend) {
+    let mut projector = default_rsvp();
+    projector.resource_id = "projector".to_string();
+
+    let result = manager
+        .reserve_many(vec![projector, default_rsvp(), default_rsvp()])
+        .await;
+
+    match result.unwrap_err() {
+        abi::Error::BatchReservation { index, source } => {
+            assert_eq!(index, 2);
+            assert!(matches!(
+                *source,
+                abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(_))
+            ));
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    let filter = abi::ReservationFilterBuilder::default().build().unwrap();
+    let (_, rsvps) = manager.filter(filter).await.unwrap();
+    assert!(rsvps.is_empty());
+}
+
+fn default_series(rrule: &str) -> abi::ReservationSeries {
+    abi::ReservationSeries {
+        id: 0,
+        user_id: "user".to_string(),
+        resource_id: "resource".to_string(),
+        start: Some(abi::utils::datetime_to_timestamp(
+            DateTime::parse_from_rfc3339("2024-01-01T10:00:00Z")
+                .unwrap()
+                .to_utc(),
+        )),
+        end: Some(abi::utils::datetime_to_timestamp(
+            DateTime::parse_from_rfc3339("2024-01-01T11:00:00Z")
+                .unwrap()
+                .to_utc(),
+        )),
+        rrule: rrule.to_string(),
+        note: "weekly meeting".to_string(),
+    }
+}
+
+pub(crate) async fn reserve_series_should_work(manager: impl Backend) {
+    let (series, rsvps, conflicts) = manager
+        .reserve_series(
+            default_series("FREQ=WEEKLY;COUNT=4"),
+            SeriesConflictMode::Fail,
+        )
+        .await
+        .unwrap();
+    assert!(series.id != 0);
+    assert_eq!(rsvps.len(), 4);
+    assert!(conflicts.is_empty());
+    assert!(rsvps.iter().all(|r| r.series_id == Some(series.id)));
+
+    let rsvp = manager.get(rsvps[3].id).await.unwrap();
+    assert_eq!(rsvp.series_id, Some(series.id));
+    assert_eq!(
+        rsvp.start,
+        Some(abi::utils::datetime_to_timestamp(
+            DateTime::parse_from_rfc3339("2024-01-22T10:00:00Z")
+                .unwrap()
+                .to_utc()
+        ))
+    );
+}
+
+pub(crate) async fn reserve_series_should_skip_conflicts(manager: impl Backend) {
+    let rsvp = abi::Reservation::new_pendding(
+        "user1",
+        "resource",
+        DateTime::parse_from_rfc3339("2024-01-08T10:30:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2024-01-08T12:00:00Z").unwrap(),
+        "note",
+    );
+    manager.reserve(rsvp).await.unwrap();
+
+    let (_, rsvps, conflicts) = manager
+        .reserve_series(
+            default_series("FREQ=WEEKLY;COUNT=4"),
+            SeriesConflictMode::Skip,
+        )
+        .await
+        .unwrap();
+    assert_eq!(rsvps.len(), 3);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(
+        conflicts[0].start,
+        Some(abi::utils::datetime_to_timestamp(
+            DateTime::parse_from_rfc3339("2024-01-08T10:00:00Z")
+                .unwrap()
+                .to_utc()
+        ))
+    );
+}
+
+pub(crate) async fn reserve_series_should_fail_on_conflict(manager: impl Backend) {
+    let rsvp = abi::Reservation::new_pendding(
+        "user1",
+        "resource",
+        DateTime::parse_from_rfc3339("2024-01-08T10:30:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2024-01-08T12:00:00Z").unwrap(),
+        "note",
+    );
+    manager.reserve(rsvp).await.unwrap();
+
+    let result = manager
+        .reserve_series(
+            default_series("FREQ=WEEKLY;COUNT=4"),
+            SeriesConflictMode::Fail,
+        )
+        .await;
+    match result.unwrap_err() {
+        abi::Error::ConflictSeries(conflicts) => assert_eq!(conflicts.len(), 1),
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    // nothing but the existing reservation is kept
+    let filter = abi::ReservationFilterBuilder::default().build().unwrap();
+    let (_, rsvps) = manager.filter(filter).await.unwrap();
+    assert_eq!(rsvps.len(), 1);
+}
+
+pub(crate) async fn reserve_series_should_fail_with_invalid_rrule(manager: impl Backend) {
+    let result = manager
+        .reserve_series(default_series("FREQ=WEEKLY"), SeriesConflictMode::Fail)
+        .await;
+    assert!(matches!(result, Err(abi::Error::InvalidRecurrenceRule(_))));
+}
+
+pub(crate) async fn reservation_can_be_confirmed(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let rsvp = manager.change_status(rsvp.id, None).await.unwrap();
+    assert_eq!(rsvp.status, abi::ReservationStatus::Confirmed as i32);
+}
+
+pub(crate) async fn change_reservation_again_should_do_nothing(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let rsvp = manager.change_status(rsvp.id, None).await.unwrap();
+    assert_eq!(rsvp.status, abi::ReservationStatus::Confirmed as i32);
+
+    let ret = manager.change_status(rsvp.id, None).await;
+    assert!(matches!(ret, Err(abi::Error::NotFound)));
+}
+
+pub(crate) async fn cancel_should_keep_reservation_and_free_timespan(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    let cancelled = manager
+        .cancel(rsvp.id, Some("plan changed".to_string()), None)
+        .await
+        .unwrap();
+    assert_eq!(cancelled.status, ReservationStatus::Cancelled as i32);
+    assert_eq!(cancelled.cancel_reason.as_deref(), Some("plan changed"));
+
+    let cancelled = manager.get(rsvp.id).await.unwrap();
+    assert_eq!(cancelled.status, ReservationStatus::Cancelled as i32);
+
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    assert_ne!(rsvp.id, cancelled.id);
+}
+
+pub(crate) async fn cancel_again_should_fail(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    manager.cancel(rsvp.id, None, None).await.unwrap();
+    let ret = manager.cancel(rsvp.id, None, None).await;
+    assert!(matches!(ret, Err(abi::Error::NotFound)));
+}
+
+pub(crate) async fn reschedule_should_work(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    let start = DateTime::parse_from_rfc3339("2021-01-03T00:00:00Z").unwrap();
+    let end = DateTime::parse_from_rfc3339("2021-01-04T00:00:00Z").unwrap();
+    let rescheduled = manager
+        .reschedule(rsvp.id, start.to_utc(), end.to_utc(), None)
+        .await
+        .unwrap();
+    assert_eq!(rescheduled.id, rsvp.id);
+    assert_eq!(
+        rescheduled.start,
+        Some(abi::utils::datetime_to_timestamp(start.to_utc()))
+    );
+    assert_eq!(
+        rescheduled.end,
+        Some(abi::utils::datetime_to_timestamp(end.to_utc()))
+    );
+
+    // the old timespan is free again
+    manager.reserve(default_rsvp()).await.unwrap();
+}
+
+pub(crate) async fn reschedule_should_fail_with_conflicting_timespan(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    let start = DateTime::parse_from_rfc3339("2021-01-03T00:00:00Z").unwrap();
+    let end = DateTime::parse_from_rfc3339("2021-01-04T00:00:00Z").unwrap();
+    let other = abi::Reservation::new_pendding("user", "resource", start, end, "note");
+    let other = manager.reserve(other).await.unwrap();
+
+    let conflict_start = DateTime::parse_from_rfc3339("2021-01-01T12:00:00Z").unwrap();
+    let result = manager
+        .reschedule(other.id, conflict_start.to_utc(), end.to_utc(), None)
+        .await;
+
+    match result.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            let ReservationConflict {
+                new, old, existing, ..
+            } = *conflict;
+            assert_eq!(new.start, conflict_start);
+            assert_eq!(new.end, end);
+            assert_eq!(old.start, timestamp_to_datetime(&rsvp.start.unwrap()));
+            assert_eq!(existing[0].id, rsvp.id);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+pub(crate) async fn reschedule_should_fail_with_invalid_timespan(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    let start = DateTime::parse_from_rfc3339("2021-01-03T00:00:00Z").unwrap();
+    let result = manager
+        .reschedule(rsvp.id, start.to_utc(), start.to_utc(), None)
+        .await;
+    assert!(matches!(result, Err(abi::Error::InvalidTimespan)));
+}
+
+pub(crate) async fn change_resource_should_work(manager: impl Backend) {
+    let mut changes = manager.listen().await.unwrap();
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+
+    let moved = manager
+        .change_resource(rsvp.id, "resource1".to_string(), None)
+        .await
+        .unwrap();
+    assert_eq!(moved.id, rsvp.id);
+    assert_eq!(moved.resource_id, "resource1");
+    assert_eq!(moved.start, rsvp.start);
+
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Create as i32);
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Update as i32);
+    assert_eq!(change.reservation.unwrap().resource_id, "resource1");
+}
+
+pub(crate) async fn change_resource_should_fail_with_conflicting_timespan(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    let mut other = default_rsvp();
+    other.resource_id = "resource1".to_string();
+    manager.reserve(other).await.unwrap();
+
+    let result = manager
+        .change_resource(rsvp.id, "resource1".to_string(), None)
+        .await;
+
+    match result.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            let ReservationConflict { new, old, .. } = *conflict;
+            assert_eq!(new.resource_id, "resource1");
+            assert_eq!(old.resource_id, "resource1");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+pub(crate) async fn change_resource_should_enforce_policy_and_quota(manager: impl Backend) {
+    let policy = abi::config::PolicyConfig {
+        resources: [(
+            "room".to_string(),
+            abi::config::BookingPolicy {
+                max_duration: Some(3600),
+                ..Default::default()
+            },
+        )]
+        .into(),
+        ..Default::default()
+    };
+    let quota = abi::config::QuotaConfig {
+        classes: [(
+            "rooms".to_string(),
+            abi::config::ClassQuota {
+                resources: vec!["room".to_string(), "room1".to_string()],
+                quota: abi::config::Quota {
+                    max_active: Some(1),
+                    ..Default::default()
+                },
+            },
+        )]
+        .into(),
+        ..Default::default()
+    };
+    let manager = manager.clone().with_policy(policy).with_quota(quota);
+
+    let long = manager
+        .reserve(future_rsvp("projector", 0, 2))
+        .await
+        .unwrap();
+    let result = manager
+        .change_resource(long.id, "room".to_string(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::PolicyViolation {
+            rule: PolicyRule::MaxDuration,
+            ..
+        })
+    ));
+
+    manager.reserve(future_rsvp("room", 1, 1)).await.unwrap();
+    let short = manager
+        .reserve(future_rsvp("projector", 2, 1))
+        .await
+        .unwrap();
+    let result = manager
+        .change_resource(short.id, "room1".to_string(), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::QuotaExceeded { scope, .. }) if scope == "rooms"
+    ));
+}
+
+fn hold_rsvp(ttl: TimeDelta) -> abi::Reservation {
+    let mut rsvp = default_rsvp();
+    rsvp.expires_at = Some(abi::utils::datetime_to_timestamp(Utc::now() + ttl));
+    rsvp
+}
+
+pub(crate) async fn confirm_hold_should_clear_expires_at(manager: impl Backend) {
+    let rsvp = manager
+        .reserve(hold_rsvp(TimeDelta::try_minutes(10).unwrap()))
+        .await
+        .unwrap();
+    assert!(rsvp.expires_at.is_some());
+
+    let rsvp = manager.change_status(rsvp.id, None).await.unwrap();
+    assert_eq!(rsvp.status, ReservationStatus::Confirmed as i32);
+    assert!(rsvp.expires_at.is_none());
+}
+
+pub(crate) async fn confirm_expired_hold_should_fail(manager: impl Backend) {
+    let rsvp = manager
+        .reserve(hold_rsvp(TimeDelta::try_minutes(-1).unwrap()))
+        .await
+        .unwrap();
+
+    let ret = manager.change_status(rsvp.id, None).await;
+    assert!(matches!(ret, Err(abi::Error::HoldExpired)));
+}
+
+pub(crate) async fn release_expired_should_free_timespan(manager: impl Backend) {
+    let expired = manager
+        .reserve(hold_rsvp(TimeDelta::try_minutes(-1).unwrap()))
+        .await
+        .unwrap();
+    let mut valid = hold_rsvp(TimeDelta::try_minutes(10).unwrap());
+    valid.resource_id = "resource1".to_string();
+    manager.reserve(valid).await.unwrap();
+
+    let mut changes = manager.listen().await.unwrap();
+
+    let released = manager.release_expired().await.unwrap();
+    assert_eq!(released.len(), 1);
+    assert_eq!(released[0].id, expired.id);
+    assert_eq!(released[0].status, ReservationStatus::Cancelled as i32);
+    assert_eq!(released[0].cancel_reason.as_deref(), Some("hold expired"));
+
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Update as i32);
+    assert_eq!(change.reservation.unwrap().id, expired.id);
+
+    manager.reserve(default_rsvp()).await.unwrap();
+}
+
+pub(crate) async fn update_notes_should_work(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let rsvp = manager
+        .update_notes(rsvp.id, "new note".to_string(), None)
+        .await
+        .unwrap();
+    assert_eq!(rsvp.note, "new note");
+}
+
+pub(crate) async fn update_with_stale_version_should_fail(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    assert_eq!(rsvp.version, 1);
+
+    let updated = manager
+        .update_notes(rsvp.id, "new note".to_string(), Some(1))
+        .await
+        .unwrap();
+    assert_eq!(updated.version, 2);
+
+    let err = manager
+        .change_status(rsvp.id, Some(rsvp.version))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        abi::Error::VersionMismatch {
+            expected: 1,
+            actual: 2
+        }
+    ));
+
+    // the failed change didn't touch the reservation
+    let rsvp = manager.get(rsvp.id).await.unwrap();
+    assert_eq!(rsvp.version, 2);
+    assert_eq!(rsvp.status, ReservationStatus::Pending as i32);
+}
+
+pub(crate) async fn get_history_should_record_every_change(manager: impl Backend) {
+    let rsvp = manager
+        .clone()
+        .with_actor("alice")
+        .reserve(default_rsvp())
+        .await
+        .unwrap();
+    // actors aren't limited in length, like the subjects of tokens
+    let support = format!("https://accounts.example.com/users/{}", "0".repeat(64));
+    manager
+        .clone()
+        .with_actor(&support)
+        .update_notes(rsvp.id, "moved".to_string(), None)
+        .await
+        .unwrap();
+    manager.delete(rsvp.id).await.unwrap();
+
+    let changes = manager.get_history(rsvp.id).await.unwrap();
+    assert_eq!(changes.len(), 3);
+
+    assert_eq!(changes[0].op, ReservationUpdateType::Create as i32);
+    assert_eq!(changes[0].actor.as_deref(), Some("alice"));
+    assert!(changes[0].changed_at.is_some());
+    assert_eq!(changes[0].before, None);
+    assert_eq!(changes[0].after.as_ref().unwrap().note, rsvp.note);
+
+    assert_eq!(changes[1].op, ReservationUpdateType::Update as i32);
+    assert_eq!(changes[1].actor.as_ref(), Some(&support));
+    assert_eq!(changes[1].before.as_ref().unwrap().note, rsvp.note);
+    assert_eq!(changes[1].after.as_ref().unwrap().note, "moved");
+    assert_eq!(changes[1].after.as_ref().unwrap().version, 2);
+
+    assert_eq!(changes[2].op, ReservationUpdateType::Delete as i32);
+    assert_eq!(changes[2].actor, None);
+    assert_eq!(changes[2].before.as_ref().unwrap().note, "moved");
+    assert_eq!(changes[2].after, None);
+
+    let err = manager.get_history(rsvp.id + 1).await.unwrap_err();
+    assert!(matches!(err, abi::Error::NotFound));
+}
+
+pub(crate) async fn tenants_should_be_isolated(manager: impl Backend) {
+    let other = manager.clone().with_tenant("other");
+
+    // the other tenant has to register its own resource
+    let err = other.reserve(default_rsvp()).await.unwrap_err();
+    assert!(matches!(err, abi::Error::ResourceNotAvailable(_)));
+    other
+        .create_resource(abi::Resource {
+            id: "resource".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // the same timespan of the same resource id doesn't conflict across tenants
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    let other_rsvp = other.reserve(default_rsvp()).await.unwrap();
+
+    assert!(matches!(
+        other.get(rsvp.id).await,
+        Err(abi::Error::NotFound)
+    ));
+    assert!(matches!(
+        other.cancel(rsvp.id, None, None).await,
+        Err(abi::Error::NotFound)
+    ));
+    assert!(matches!(
+        other.get_history(rsvp.id).await,
+        Err(abi::Error::NotFound)
+    ));
+    assert_eq!(other.list_resources().await.unwrap().len(), 1);
+
+    let filter = abi::ReservationFilterBuilder::default().build().unwrap();
+    let (_, rsvps) = other.filter(filter).await.unwrap();
+    assert_eq!(rsvps.len(), 1);
+    assert_eq!(rsvps[0].id, other_rsvp.id);
+
+    assert_eq!(manager.get(rsvp.id).await.unwrap().id, rsvp.id);
+}
+
+pub(crate) async fn get_should_work(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp1 = manager.reserve(rsvp).await.unwrap();
+
+    let rsvp = manager.get(rsvp1.id).await.unwrap();
+    assert_eq!(rsvp.id, rsvp1.id);
+}
+
+pub(crate) async fn delete_should_work(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    manager.delete(rsvp.id).await.unwrap();
+
+    let rsvp = manager.get(rsvp.id).await;
+    assert!(matches!(rsvp, Err(abi::Error::NotFound)));
+}
+
+pub(crate) async fn delete_null_should_fail(manager: impl Backend) {
+    let result = manager.delete(0).await;
+    assert!(matches!(result, Err(abi::Error::NotFound)));
+}
+
+pub(crate) async fn query_should_work(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let query = abi::ReservationQueryBuilder::default()
+        .end(abi::utils::datetime_to_timestamp(Utc::now()))
+        .build()
+        .unwrap();
+
+    let mut query = manager.query(query).await.unwrap();
+
+    assert_eq!(query.recv().await.unwrap().unwrap().id, rsvp.id);
+    assert_eq!(query.len(), 0);
+
+    let query = abi::ReservationQueryBuilder::default()
+        .user_id("user")
+        .resource_id("resource")
+        .end(abi::utils::datetime_to_timestamp(Utc::now()))
+        .build()
+        .unwrap();
+
+    let mut query = manager.query(query).await.unwrap();
+    assert_eq!(query.recv().await.unwrap().unwrap().id, rsvp.id);
+    assert_eq!(query.len(), 0);
+}
+
+pub(crate) async fn query_should_work_with_user_id(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let _rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let query = abi::ReservationQueryBuilder::default()
+        .user_id("user1")
+        .end(abi::utils::datetime_to_timestamp(Utc::now()))
+        .build()
+        .unwrap();
+
+    let query = manager.query(query).await.unwrap();
+
+    assert_eq!(query.len(), 0);
+}
+
+pub(crate) async fn query_should_work_with_resource_id(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let _rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let query = abi::ReservationQueryBuilder::default()
+        .resource_id("resource1")
+        .end(abi::utils::datetime_to_timestamp(Utc::now()))
+        .build()
+        .unwrap();
+
+    let query = manager.query(query).await.unwrap();
+
+    assert_eq!(query.len(), 0);
+}
+
+pub(crate) async fn query_should_work_with_status(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let _rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let query = abi::ReservationQueryBuilder::default()
+        .status(2)
+        .end(abi::utils::datetime_to_timestamp(Utc::now()))
+        .build()
+        .unwrap();
+
+    let query = manager.query(query).await.unwrap();
+
+    assert_eq!(query.len(), 0);
+}
+
+pub(crate) async fn query_should_work_with_timespan(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let _rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let query = abi::ReservationQueryBuilder::default()
+        .start(abi::utils::datetime_to_timestamp(Utc::now()))
+        .end(abi::utils::datetime_to_timestamp(
+            Utc::now() + Duration::try_days(1).unwrap(),
+        ))
+        .build()
+        .unwrap();
+
+    let query = manager.query(query).await.unwrap();
+
+    assert_eq!(query.len(), 0);
+}
+
+pub(crate) async fn filter_should_work(manager: impl Backend) {
+    let rsvp = default_rsvp();
+
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let filter = abi::ReservationFilterBuilder::default().build().unwrap();
+
+    let filter = manager.filter(filter).await.unwrap();
+    assert!(filter.0.next.is_none());
+    assert_eq!(filter.1.len(), 1);
+    assert_eq!(filter.1[0].id, rsvp.id);
+
+    let filter = abi::ReservationFilterBuilder::default()
+        .user_id("user")
+        .resource_id("resource")
+        .build()
+        .unwrap();
+
+    let filter = manager.filter(filter).await.unwrap();
+    assert_eq!(filter.1.len(), 1);
+    assert_eq!(filter.1[0].id, rsvp.id);
+
+    let filter = abi::ReservationFilterBuilder::default()
+        .user_id("user1")
+        .resource_id("resource")
+        .build()
+        .unwrap();
+
+    let filter = manager.filter(filter).await.unwrap();
+    assert_eq!(filter.1.len(), 0);
+}
+
+pub(crate) async fn reserve_should_respect_capacity(manager: impl Backend) {
+    // "lot" has a capacity of 3
+    let lot = |start: &str, end: &str, seats: i32| abi::Reservation {
+        seats,
+        ..abi::Reservation::new_pendding(
+            "user",
+            "lot",
+            DateTime::parse_from_rfc3339(start).unwrap(),
+            DateTime::parse_from_rfc3339(end).unwrap(),
+            "note",
+        )
+    };
+
+    manager
+        .reserve(lot("2021-01-01T00:00:00Z", "2021-01-03T00:00:00Z", 1))
+        .await
+        .unwrap();
+    manager
+        .reserve(lot("2021-01-03T00:00:00Z", "2021-01-05T00:00:00Z", 2))
+        .await
+        .unwrap();
+    // overlaps both, but they never take more than 2 seats at the same time
+    let rsvp = manager
+        .reserve(lot("2021-01-02T00:00:00Z", "2021-01-04T00:00:00Z", 0))
+        .await
+        .unwrap();
+    assert_eq!(rsvp.seats, 1);
+
+    let result = manager
+        .reserve(lot("2021-01-02T00:00:00Z", "2021-01-02T12:00:00Z", 2))
+        .await;
+    match result.unwrap_err() {
+        abi::Error::ConflictReservation(ReservationConflictInfo::Parsed(conflict)) => {
+            assert_eq!(conflict.new.resource_id, "lot");
+            assert_eq!(conflict.remaining_capacity, 1);
+            // the first and the third one overlap it
+            assert_eq!(conflict.existing.len(), 2);
+            assert_eq!(conflict.existing_id, Some(conflict.existing[0].id));
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    let slots = manager
+        .find_availability(
+            "lot".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-06T00:00:00Z".parse().unwrap(),
+            TimeDelta::try_minutes(1).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(
+        slots[0].end,
+        Some(abi::utils::datetime_to_timestamp(
+            "2021-01-03T00:00:00Z".parse().unwrap()
+        ))
+    );
+}
+
+pub(crate) async fn reserve_with_invalid_seats_should_fail(manager: impl Backend) {
+    let rsvp = abi::Reservation {
+        seats: -1,
+        ..default_rsvp()
+    };
+    let result = manager.reserve(rsvp).await;
+    assert!(matches!(result, Err(abi::Error::InvalidSeats)));
+}
+
+pub(crate) async fn reserve_should_respect_buffers(manager: impl Backend) {
+    let room = manager.get_resource("room".to_string()).await.unwrap();
+    manager
+        .update_resource(abi::Resource {
+            buffer_after: Some(abi::utils::timedelta_to_duration(
+                TimeDelta::try_minutes(30).unwrap(),
+            )),
+            ..room
+        })
+        .await
+        .unwrap();
+
+    let room = |start: &str, end: &str| {
+        abi::Reservation::new_pendding(
+            "user",
+            "room",
+            DateTime::parse_from_rfc3339(start).unwrap(),
+            DateTime::parse_from_rfc3339(end).unwrap(),
+            "note",
+        )
+    };
+
+    let rsvp = manager
+        .reserve(room("2021-01-01T10:00:00Z", "2021-01-01T11:00:00Z"))
+        .await
+        .unwrap();
+    let rsvp = manager.get(rsvp.id).await.unwrap();
+    // the buffers don't change the booked time
+    assert_eq!(
+        rsvp.end,
+        Some(abi::utils::datetime_to_timestamp(
+            "2021-01-01T11:00:00Z".parse().unwrap()
+        ))
+    );
+
+    let result = manager
+        .reserve(room("2021-01-01T11:15:00Z", "2021-01-01T12:00:00Z"))
+        .await;
+    assert!(matches!(result, Err(abi::Error::ConflictReservation(_))));
+
+    // its own buffer takes precedence over the one of the resource
+    let other = manager
+        .reserve(abi::Reservation {
+            buffer_after: Some(abi::utils::timedelta_to_duration(TimeDelta::zero())),
+            ..room("2021-01-01T12:00:00Z", "2021-01-01T13:00:00Z")
+        })
+        .await
+        .unwrap();
+    let result = manager
+        .reschedule(
+            other.id,
+            "2021-01-01T11:15:00Z".parse().unwrap(),
+            "2021-01-01T12:00:00Z".parse().unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(abi::Error::ConflictReservation(_))));
+
+    // a buffer out of the range of a time delta is rejected
+    let mut buffer = abi::utils::timedelta_to_duration(TimeDelta::zero());
+    buffer.seconds = i64::MAX;
+    let result = manager
+        .reserve(abi::Reservation {
+            buffer_before: Some(buffer),
+            ..room("2021-01-02T12:00:00Z", "2021-01-02T13:00:00Z")
+        })
+        .await;
+    assert!(matches!(result, Err(abi::Error::InvalidBuffer)));
+
+    let slots = manager
+        .find_availability(
+            "room".to_string(),
+            "2021-01-01T09:00:00Z".parse().unwrap(),
+            "2021-01-01T15:00:00Z".parse().unwrap(),
+            TimeDelta::try_minutes(1).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(
+        slots[0].end,
+        Some(abi::utils::datetime_to_timestamp(
+            "2021-01-01T09:30:00Z".parse().unwrap()
+        ))
+    );
+    // there is no room for the buffer after a reservation between 11:30 and 12:00
+    assert_eq!(
+        slots[1].start,
+        Some(abi::utils::datetime_to_timestamp(
+            "2021-01-01T13:00:00Z".parse().unwrap()
+        ))
+    );
+}
+
+pub(crate) async fn reserve_should_enforce_policy(manager: impl Backend) {
+    let policy = abi::config::PolicyConfig {
+        default: abi::config::BookingPolicy {
+            reject_past: Some(true),
+            ..Default::default()
+        },
+        resources: [(
+            "room".to_string(),
+            abi::config::BookingPolicy {
+                max_duration: Some(3600),
+                ..Default::default()
+            },
+        )]
+        .into(),
+        ..Default::default()
+    };
+    let manager = manager.clone().with_policy(policy);
+
+    let result = manager.reserve(default_rsvp()).await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::PolicyViolation {
+            rule: PolicyRule::RejectPast,
+            ..
+        })
+    ));
+
+    let start = Utc::now() + TimeDelta::try_days(1).unwrap();
+    let rsvp = abi::Reservation::new_pendding(
+        "user",
+        "room",
+        start.fixed_offset(),
+        (start + TimeDelta::try_hours(1).unwrap()).fixed_offset(),
+        "note",
+    );
+    let rsvp = manager.reserve(rsvp).await.unwrap();
+
+    let result = manager
+        .reschedule(
+            rsvp.id,
+            start,
+            start + TimeDelta::try_hours(2).unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::PolicyViolation {
+            rule: PolicyRule::MaxDuration,
+            ..
+        })
+    ));
+}
+
+fn future_rsvp(resource_id: &str, days: i64, hours: i64) -> abi::Reservation {
+    // mondays, so reservations of the same week stay in it
+    let monday = Utc::now()
+        .date_naive()
+        .week(chrono::Weekday::Mon)
+        .first_day()
+        + TimeDelta::try_weeks(1).unwrap();
+    let start = monday.and_hms_opt(8, 0, 0).unwrap().and_utc() + TimeDelta::try_days(days).unwrap();
+    abi::Reservation::new_pendding(
+        "user",
+        resource_id,
+        start.fixed_offset(),
+        (start + TimeDelta::try_hours(hours).unwrap()).fixed_offset(),
+        "note",
+    )
+}
+
+pub(crate) async fn reserve_should_enforce_quota(manager: impl Backend) {
+    let quota = abi::config::QuotaConfig {
+        default: abi::config::Quota {
+            max_active: Some(2),
+            ..Default::default()
+        },
+        classes: [(
+            "rooms".to_string(),
+            abi::config::ClassQuota {
+                resources: vec!["room".to_string(), "room1".to_string()],
+                quota: abi::config::Quota {
+                    max_weekly_hours: Some(3),
+                    ..Default::default()
+                },
+            },
+        )]
+        .into(),
+    };
+    let manager = manager.clone().with_quota(quota);
+
+    let rsvp = manager.reserve(future_rsvp("room", 0, 2)).await.unwrap();
+    let result = manager.reserve(future_rsvp("room1", 1, 2)).await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::QuotaExceeded { scope, .. }) if scope == "rooms"
+    ));
+    // reservations which are over don't count as active
+    manager.reserve(default_rsvp()).await.unwrap();
+    manager.reserve(future_rsvp("room1", 7, 2)).await.unwrap();
+
+    let result = manager.reserve(future_rsvp("projector", 0, 1)).await;
+    assert!(matches!(
+        result,
+        Err(abi::Error::QuotaExceeded { scope, .. }) if scope == "default"
+    ));
+
+    // rescheduling counts the new duration instead of the old one
+    let start = timestamp_to_datetime(rsvp.start.as_ref().unwrap());
+    manager
+        .reschedule(
+            rsvp.id,
+            start,
+            start + TimeDelta::try_hours(3).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+    let result = manager
+        .reschedule(
+            rsvp.id,
+            start,
+            start + TimeDelta::try_hours(4).unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(abi::Error::QuotaExceeded { .. })));
+
+    let usages = manager
+        .quota_usage("user".to_string(), start)
+        .await
+        .unwrap();
+    assert_eq!(usages.len(), 2);
+    assert_eq!(usages[0].scope, "default");
+    assert_eq!(usages[0].active, 2);
+    assert_eq!(usages[0].remaining_active, Some(0));
+    assert_eq!(usages[1].scope, "rooms");
+    assert_eq!(
+        usages[1].remaining_weekly,
+        Some(abi::utils::timedelta_to_duration(TimeDelta::zero()))
+    );
+}
+
+pub(crate) async fn concurrent_reserve_should_not_exceed_quota(manager: impl Backend) {
+    let quota = abi::config::QuotaConfig {
+        default: abi::config::Quota {
+            max_active: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let manager = manager.clone().with_quota(quota);
+
+    let handles: Vec<_> = ["resource", "resource1", "room", "room1", "projector"]
+        .into_iter()
+        .map(|id| {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.reserve(future_rsvp(id, 0, 1)).await })
+        })
+        .collect();
+    let mut reserved = 0;
+    for handle in handles {
+        if handle.await.unwrap().is_ok() {
+            reserved += 1;
+        }
+    }
+    assert_eq!(reserved, 1);
+}
+
+pub(crate) async fn reserve_idempotent_should_return_first_outcome(manager: impl Backend) {
+    let first = manager
+        .reserve_idempotent("key".to_string(), future_rsvp("room", 0, 1), false)
+        .await
+        .unwrap();
+    let ReserveOutcome::Reserved(rsvp) = &first else {
+        panic!("Unexpected outcome: {:?}", first);
+    };
+
+    // the retry would conflict with the first reservation
+    let retry = manager
+        .reserve_idempotent("key".to_string(), future_rsvp("room", 0, 1), false)
+        .await
+        .unwrap();
+    assert_eq!(retry, first);
+
+    // the outcome is kept even if the reservation changes later
+    manager.cancel(rsvp.id, None, None).await.unwrap();
+    let retry = manager
+        .reserve_idempotent("key".to_string(), future_rsvp("room", 0, 1), false)
+        .await
+        .unwrap();
+    assert_eq!(retry, first);
+
+    let filter = abi::ReservationFilterBuilder::default().build().unwrap();
+    let (_, rsvps) = manager.filter(filter).await.unwrap();
+    assert_eq!(rsvps.len(), 1);
+
+    let waiting = manager
+        .reserve_idempotent("other".to_string(), future_rsvp("room", 0, 1), true)
+        .await
+        .unwrap();
+    assert!(matches!(waiting, ReserveOutcome::Reserved(_)));
+    let waiting = manager
+        .reserve_idempotent("wait".to_string(), future_rsvp("room", 0, 1), true)
+        .await
+        .unwrap();
+    let ReserveOutcome::Waitlisted(_) = waiting else {
+        panic!("Unexpected outcome: {:?}", waiting);
+    };
+    let retry = manager
+        .reserve_idempotent("wait".to_string(), future_rsvp("room", 0, 1), true)
+        .await
+        .unwrap();
+    assert_eq!(retry, waiting);
+}
+
+pub(crate) async fn reserve_idempotent_should_reserve_again_after_ttl(manager: impl Backend) {
+    let manager = manager
+        .clone()
+        .with_idempotency(abi::config::IdempotencyConfig { ttl: 0 });
+
+    let first = manager
+        .reserve_idempotent("key".to_string(), future_rsvp("room", 0, 1), false)
+        .await
+        .unwrap();
+    let retry = manager
+        .reserve_idempotent("key".to_string(), future_rsvp("room1", 0, 1), false)
+        .await
+        .unwrap();
+    assert_ne!(retry, first);
+
+    let err = manager
+        .reserve_idempotent(String::new(), future_rsvp("room", 1, 1), false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::InvalidIdempotencyKey));
+}
+
+pub(crate) async fn reserve_idempotent_should_be_scoped_to_actor_and_request(
+    manager: impl Backend,
+) {
+    let rsvp = |user_id: &str, resource_id: &str| abi::Reservation {
+        user_id: user_id.to_string(),
+        ..future_rsvp(resource_id, 0, 1)
+    };
+    let alice = manager.clone().with_actor("alice");
+    let first = alice
+        .reserve_idempotent("key".to_string(), rsvp("alice", "room"), false)
+        .await
+        .unwrap();
+
+    // another actor with the same key doesn't get the outcome of alice
+    let other = manager
+        .clone()
+        .with_actor("bob")
+        .reserve_idempotent("key".to_string(), rsvp("bob", "room1"), false)
+        .await
+        .unwrap();
+    let ReserveOutcome::Reserved(other) = other else {
+        panic!("Unexpected outcome: {:?}", other);
+    };
+    assert_eq!(other.user_id, "bob");
+
+    // a retry with another request is rejected, a later hold is the same request
+    let err = alice
+        .reserve_idempotent("key".to_string(), rsvp("alice", "room1"), false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::IdempotencyKeyReused));
+    let err = alice
+        .reserve_idempotent("key".to_string(), rsvp("alice", "room"), true)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::IdempotencyKeyReused));
+    let hold = abi::Reservation {
+        expires_at: Some(abi::utils::datetime_to_timestamp(
+            Utc::now() + TimeDelta::try_minutes(10).unwrap(),
+        )),
+        ..rsvp("alice", "room")
+    };
+    let retry = alice
+        .reserve_idempotent("key".to_string(), hold, false)
+        .await
+        .unwrap();
+    assert_eq!(retry, first);
+}
+
+pub(crate) async fn changes_with_idempotency_key_should_be_made_once(manager: impl Backend) {
+    let once = |key: &str| manager.clone().with_idempotency_key(key);
+
+    // a retried batch or series would conflict with the first one
+    let batch = vec![future_rsvp("room", 0, 1), future_rsvp("room1", 0, 1)];
+    let reserved = once("batch").reserve_many(batch.clone()).await.unwrap();
+    let retry = once("batch").reserve_many(batch).await.unwrap();
+    assert_eq!(retry, reserved);
+
+    let series = default_series("FREQ=WEEKLY;COUNT=4");
+    let first = once("series")
+        .reserve_series(series.clone(), SeriesConflictMode::Fail)
+        .await
+        .unwrap();
+    let retry = once("series")
+        .reserve_series(series, SeriesConflictMode::Fail)
+        .await
+        .unwrap();
+    assert_eq!(retry, first);
+
+    // a retried reschedule or cancel would fail for the version it expects
+    let rsvp = &reserved[0];
+    let start =
+        timestamp_to_datetime(rsvp.start.as_ref().unwrap()) + TimeDelta::try_hours(2).unwrap();
+    let end = start + TimeDelta::try_hours(1).unwrap();
+    let rescheduled = once("reschedule")
+        .reschedule(rsvp.id, start, end, Some(rsvp.version))
+        .await
+        .unwrap();
+    let retry = once("reschedule")
+        .reschedule(rsvp.id, start, end, Some(rsvp.version))
+        .await
+        .unwrap();
+    assert_eq!(retry, rescheduled);
+
+    // so would a retried update or move, a retried confirm would find nothing pending
+    let version = Some(rescheduled.version);
+    let updated = once("update")
+        .update_notes(rsvp.id, "retried".to_string(), version)
+        .await
+        .unwrap();
+    let retry = once("update")
+        .update_notes(rsvp.id, "retried".to_string(), version)
+        .await
+        .unwrap();
+    assert_eq!(retry, updated);
+
+    let version = Some(updated.version);
+    let moved = once("move")
+        .change_resource(rsvp.id, "room1".to_string(), version)
+        .await
+        .unwrap();
+    let retry = once("move")
+        .change_resource(rsvp.id, "room1".to_string(), version)
+        .await
+        .unwrap();
+    assert_eq!(retry, moved);
+
+    let confirmed = once("confirm").change_status(rsvp.id, None).await.unwrap();
+    let retry = once("confirm").change_status(rsvp.id, None).await.unwrap();
+    assert_eq!(retry, confirmed);
+
+    let version = Some(confirmed.version);
+    let cancelled = once("cancel").cancel(rsvp.id, None, version).await.unwrap();
+    let retry = once("cancel").cancel(rsvp.id, None, version).await.unwrap();
+    assert_eq!(retry, cancelled);
+
+    // a key is for a single change
+    let err = once("cancel")
+        .cancel(reserved[1].id, None, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::IdempotencyKeyReused));
+    let err = once("batch")
+        .reschedule(reserved[1].id, start, end, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::IdempotencyKeyReused));
+    let err = once("")
+        .cancel(reserved[1].id, None, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, abi::Error::InvalidIdempotencyKey));
+}
+
+pub(crate) async fn reserve_or_wait_should_promote_on_cancel(manager: impl Backend) {
+    let outcome = manager
+        .reserve_or_wait(future_rsvp("room", 0, 1))
+        .await
+        .unwrap();
+    let ReserveOutcome::Reserved(first) = outcome else {
+        panic!("Unexpected outcome: {:?}", outcome);
+    };
+
+    let waiting = abi::Reservation {
+        user_id: "waiter".to_string(),
+        ..future_rsvp("room", 0, 1)
+    };
+    let outcome = manager.reserve_or_wait(waiting).await.unwrap();
+    let ReserveOutcome::Waitlisted(waitlist_id) = outcome else {
+        panic!("Unexpected outcome: {:?}", outcome);
+    };
+
+    manager.cancel(first.id, None, None).await.unwrap();
+
+    let filter = abi::ReservationFilterBuilder::default()
+        .user_id("waiter")
+        .build()
+        .unwrap();
+    let (_, rsvps) = manager.filter(filter).await.unwrap();
+    assert_eq!(rsvps.len(), 1);
+    assert_eq!(rsvps[0].waitlist_id, Some(waitlist_id));
+    assert_eq!(rsvps[0].status, abi::ReservationStatus::Pending as i32);
+
+    let changes = manager.get_history(rsvps[0].id).await.unwrap();
+    assert_eq!(changes[0].op, ReservationUpdateType::Promote as i32);
+}
+
+pub(crate) async fn promotion_should_respect_quota(manager: impl Backend) {
+    let quota = abi::config::QuotaConfig {
+        default: abi::config::Quota {
+            max_active: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let manager = manager.clone().with_quota(quota);
+
+    let first = manager.reserve(future_rsvp("room", 0, 1)).await.unwrap();
+    let waiting = abi::Reservation {
+        user_id: "waiter".to_string(),
+        ..future_rsvp("room", 0, 1)
+    };
+    let outcome = manager.reserve_or_wait(waiting).await.unwrap();
+    assert!(matches!(outcome, ReserveOutcome::Waitlisted(_)));
+
+    // the waiter reaches the quota while waiting
+    let other = abi::Reservation {
+        user_id: "waiter".to_string(),
+        ..future_rsvp("room1", 0, 1)
+    };
+    let other = manager.reserve(other).await.unwrap();
+
+    manager.cancel(first.id, None, None).await.unwrap();
+
+    let filter = abi::ReservationFilterBuilder::default()
+        .user_id("waiter")
+        .build()
+        .unwrap();
+    let (_, rsvps) = manager.filter(filter).await.unwrap();
+    assert_eq!(rsvps.len(), 1);
+    assert_eq!(rsvps[0].id, other.id);
+}
+
+pub(crate) async fn find_availability_should_return_free_slots(manager: impl Backend) {
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    let other = abi::Reservation::new_pendding(
+        "user",
+        "resource",
+        DateTime::parse_from_rfc3339("2021-01-02T02:00:00Z").unwrap(),
+        DateTime::parse_from_rfc3339("2021-01-03T00:00:00Z").unwrap(),
+        "note",
+    );
+    manager.reserve(other).await.unwrap();
+
+    let start = DateTime::parse_from_rfc3339("2020-12-31T00:00:00Z").unwrap();
+    let end = DateTime::parse_from_rfc3339("2021-01-04T00:00:00Z").unwrap();
+
+    // the 2 hours gap between the reservations is too short
+    let slots = manager
+        .find_availability(
+            "resource".to_string(),
+            start.to_utc(),
+            end.to_utc(),
+            TimeDelta::try_hours(3).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(slots[0].end, rsvp.start);
+    assert_eq!(
+        slots[1].start,
+        Some(abi::utils::datetime_to_timestamp(
+            DateTime::parse_from_rfc3339("2021-01-03T00:00:00Z")
+                .unwrap()
+                .to_utc()
+        ))
+    );
+
+    let slots = manager
+        .find_availability(
+            "resource".to_string(),
+            start.to_utc(),
+            end.to_utc(),
+            TimeDelta::try_hours(1).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(slots.len(), 3);
+    assert_eq!(slots[1].start, rsvp.end);
+
+    // cancelled reservations don't hold their timespan
+    manager.cancel(rsvp.id, None, None).await.unwrap();
+    let slots = manager
+        .find_availability(
+            "resource".to_string(),
+            start.to_utc(),
+            end.to_utc(),
+            TimeDelta::try_hours(3).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(
+        slots[0].start,
+        Some(abi::utils::datetime_to_timestamp(start.to_utc()))
+    );
+
+    // every timespan is at least as long as no duration
+    for duration in [TimeDelta::zero(), TimeDelta::try_hours(-1).unwrap()] {
+        let result = manager
+            .find_availability(
+                "resource".to_string(),
+                start.to_utc(),
+                end.to_utc(),
+                duration,
+            )
+            .await;
+        assert!(matches!(result, Err(abi::Error::InvalidTimespan)));
+    }
+}
+
+pub(crate) async fn resource_crud_should_work(manager: impl Backend) {
+    let mut resource = abi::Resource::new("lab", "lab");
+    resource.location = "building 1".to_string();
+    resource.capabilities = vec!["microscope".to_string()];
+    let resource = manager.create_resource(resource).await.unwrap();
+    assert_eq!(
+        manager.get_resource("lab".to_string()).await.unwrap(),
+        resource
+    );
+
+    let ret = manager
+        .create_resource(abi::Resource::new("lab", "lab"))
+        .await;
+    assert!(matches!(ret, Err(abi::Error::ResourceAlreadyExists)));
+
+    let updated = manager
+        .update_resource(abi::Resource {
+            name: "lab 1".to_string(),
+            ..resource
+        })
+        .await
+        .unwrap();
+    assert_eq!(updated.name, "lab 1");
+    assert_eq!(updated.capabilities, vec!["microscope".to_string()]);
+
+    let resources = manager.list_resources().await.unwrap();
+    assert!(resources.iter().any(|r| r.id == "lab"));
+
+    manager.delete_resource("lab".to_string()).await.unwrap();
+    let ret = manager.get_resource("lab".to_string()).await;
+    assert!(matches!(ret, Err(abi::Error::NotFound)));
+}
+
+pub(crate) async fn reserve_unknown_or_disabled_resource_should_fail(manager: impl Backend) {
+    let mut rsvp = default_rsvp();
+    rsvp.resource_id = "unknown".to_string();
+    let ret = manager.reserve(rsvp).await;
+    assert!(matches!(ret, Err(abi::Error::ResourceNotAvailable(id)) if id == "unknown"));
+
+    let resource = manager.get_resource("resource".to_string()).await.unwrap();
+    manager
+        .update_resource(abi::Resource {
+            disabled: true,
+            ..resource
+        })
+        .await
+        .unwrap();
+    let ret = manager.reserve(default_rsvp()).await;
+    assert!(matches!(ret, Err(abi::Error::ResourceNotAvailable(_))));
+}
+
+pub(crate) async fn delete_resource_in_use_should_fail(manager: impl Backend) {
+    manager.reserve(default_rsvp()).await.unwrap();
+
+    let ret = manager.delete_resource("resource".to_string()).await;
+    assert!(matches!(ret, Err(abi::Error::ResourceInUse)));
+}
+
+pub(crate) async fn listen_should_receive_changes(manager: impl Backend) {
+    let mut changes = manager.listen().await.unwrap();
+
+    let rsvp = manager.reserve(default_rsvp()).await.unwrap();
+    manager.change_status(rsvp.id, None).await.unwrap();
+    manager.delete(rsvp.id).await.unwrap();
+
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Create as i32);
+    assert_eq!(change.reservation.unwrap().id, rsvp.id);
+
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Update as i32);
+    assert_eq!(
+        change.reservation.unwrap().status,
+        ReservationStatus::Confirmed as i32
+    );
+
+    // deleted reservation should be sent as tombstone
+    let change = changes.recv().await.unwrap().unwrap();
+    assert_eq!(change.op, ReservationUpdateType::Delete as i32);
+    let tombstone = change.reservation.unwrap();
+    assert_eq!(tombstone.id, rsvp.id);
+    assert_eq!(tombstone.user_id, rsvp.user_id);
+    assert_eq!(tombstone.start, rsvp.start);
+    assert_eq!(tombstone.status, ReservationStatus::Confirmed as i32);
+}
@@ -0,0 +1,458 @@
+use std::{fs, sync::Arc};
+
+use abi::{
+    config::{AuthConfig, AuthKey},
+    Reservation, ReservationStatus,
+};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use serde::Deserialize;
+use tonic::{service::Interceptor, Request, Status};
+
+/// What the caller of a request may do, from the `role` claim of its token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// reads and modifies its own reservations
+    #[default]
+    User,
+    /// also confirms and blocks the reservations of the resources it manages
+    Manager,
+    /// does everything, on every resource
+    Admin,
+}
+
+/// The caller of a request, as authenticated by its token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// the `sub` of the token
+    pub subject: String,
+    pub role: Role,
+    /// the resources a manager manages, from the `resources` claim of the token
+    pub resources: Vec<String>,
+    /// the tenant the caller belongs to, from the `tenant` claim of the token,
+    /// the default tenant if the token has none
+    pub tenant: Option<String>,
+}
+
+/// What is done with an existing reservation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Modify,
+    Confirm,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    role: Role,
+    #[serde(default)]
+    resources: Vec<String>,
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+impl Principal {
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            role: Role::User,
+            resources: Vec::new(),
+            tenant: None,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// an admin manages every resource
+    pub fn manages(&self, resource_id: &str) -> bool {
+        match self.role {
+            Role::Admin => true,
+            Role::Manager => self.resources.iter().any(|id| id == resource_id),
+            Role::User => false,
+        }
+    }
+
+    /// Check the caller may do something with a reservation: its owner and the manager of its
+    /// resource read, modify and confirm it, only a manager confirms a blocked one
+    pub fn check(&self, permission: Permission, rsvp: &Reservation) -> Result<(), abi::Error> {
+        let owner = rsvp.user_id == self.subject;
+        let manager = self.manages(&rsvp.resource_id);
+        let allowed = match permission {
+            Permission::Read => owner || manager,
+            Permission::Modify => owner || manager,
+            Permission::Confirm => {
+                manager || (owner && rsvp.status != ReservationStatus::Blocked as i32)
+            }
+        };
+        if allowed {
+            return Ok(());
+        }
+
+        Err(abi::Error::PermissionDenied(match permission {
+            Permission::Confirm if rsvp.status == ReservationStatus::Blocked as i32 => format!(
+                "only a manager of resource {} can confirm reservation {}",
+                rsvp.resource_id, rsvp.id
+            ),
+            _ => format!("reservation {} belongs to another user", rsvp.id),
+        }))
+    }
+
+    /// Check the caller may reserve, only a manager of the resource can block it
+    pub fn check_reserve(&self, rsvp: &Reservation) -> Result<(), abi::Error> {
+        if rsvp.status == ReservationStatus::Blocked as i32 && !self.manages(&rsvp.resource_id) {
+            return Err(abi::Error::PermissionDenied(format!(
+                "only a manager of resource {} can block it",
+                rsvp.resource_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check the caller may access the data of a user, only admins access the data of others
+    pub fn check_user(&self, user_id: &str) -> Result<(), abi::Error> {
+        if user_id == self.subject || self.is_admin() {
+            return Ok(());
+        }
+
+        Err(abi::Error::PermissionDenied(format!(
+            "user {} can't access the data of user {}",
+            self.subject, user_id
+        )))
+    }
+
+    /// Check the caller is an admin, `action` tells what it tries to do
+    pub fn check_admin(&self, action: &str) -> Result<(), abi::Error> {
+        if self.is_admin() {
+            return Ok(());
+        }
+
+        Err(abi::Error::PermissionDenied(format!(
+            "only an admin can {}",
+            action
+        )))
+    }
+}
+
+#[derive(Clone)]
+struct Key {
+    // only set for the keys of a JWKS
+    kid: Option<String>,
+    key: DecodingKey,
+}
+
+/// Interceptor validating the bearer JWT of every request, the [`Principal`] is put into
+/// the extensions of the request. Every request is accepted if there is no key.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    keys: Arc<Vec<Key>>,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl Authenticator {
+    pub fn from_config(config: &AuthConfig) -> Result<Self, abi::Error> {
+        let mut keys = Vec::new();
+        for key in &config.keys {
+            match key {
+                AuthKey::Hmac { secret } => keys.push(Key {
+                    kid: None,
+                    key: DecodingKey::from_secret(secret.as_bytes()),
+                }),
+                AuthKey::Pem { path } => {
+                    let pem = fs::read(path)?;
+                    let key = DecodingKey::from_rsa_pem(&pem)
+                        .or_else(|_| DecodingKey::from_ec_pem(&pem))
+                        .or_else(|_| DecodingKey::from_ed_pem(&pem))
+                        .map_err(|e| invalid_key(path.display(), e))?;
+                    keys.push(Key { kid: None, key });
+                }
+                AuthKey::Jwks { path } => {
+                    let jwks: JwkSet = serde_json::from_slice(&fs::read(path)?)
+                        .map_err(|e| invalid_key(path.display(), e))?;
+                    for jwk in &jwks.keys {
+                        let key = DecodingKey::from_jwk(jwk)
+                            .map_err(|e| invalid_key(path.display(), e))?;
+                        keys.push(Key {
+                            kid: jwk.common.key_id.clone(),
+                            key,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            keys: Arc::new(keys),
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Validate a token with every key it may be signed with, `None` if none of them accepts it
+    pub fn authenticate(&self, token: &str) -> Option<Principal> {
+        let header = decode_header(token).ok()?;
+
+        let mut validation = Validation::new(header.alg);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        // a token with a kid is only validated with the JWKS key of that kid
+        // or with the keys which don't have one
+        let keys = self
+            .keys
+            .iter()
+            .filter(|key| match (&key.kid, &header.kid) {
+                (Some(kid), Some(token_kid)) => kid == token_kid,
+                _ => true,
+            });
+        for key in keys {
+            if let Ok(data) = decode::<Claims>(token, &key.key, &validation) {
+                return Some(Principal {
+                    subject: data.claims.sub,
+                    role: data.claims.role,
+                    resources: data.claims.resources,
+                    tenant: data.claims.tenant,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if !self.is_enabled() {
+            return Ok(request);
+        }
+
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                Status::from(abi::Error::Unauthenticated(
+                    "Missing bearer token".to_string(),
+                ))
+            })?;
+        let principal = self.authenticate(token).ok_or_else(|| {
+            Status::from(abi::Error::Unauthenticated("Invalid token".to_string()))
+        })?;
+        request.extensions_mut().insert(principal);
+
+        Ok(request)
+    }
+}
+
+fn invalid_key(path: impl std::fmt::Display, e: impl std::fmt::Display) -> abi::Error {
+    abi::Error::InvalidConfig(format!("invalid key {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde::Serialize;
+    use tonic::Code;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestClaims<'a> {
+        sub: &'a str,
+        exp: u64,
+        iss: &'a str,
+    }
+
+    fn token(header: &Header, key: &EncodingKey, iss: &str) -> String {
+        let claims = TestClaims {
+            sub: "alice",
+            exp: jsonwebtoken::get_current_timestamp() + 600,
+            iss,
+        };
+        encode(header, &claims, key).unwrap()
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/auth")
+            .join(name)
+    }
+
+    // the principal put into the extensions, or the code of the rejection
+    fn call(auth: &mut Authenticator, token: Option<&str>) -> Result<Option<Principal>, Code> {
+        let mut request = Request::new(());
+        if let Some(token) = token {
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+        }
+        auth.call(request)
+            .map(|request| request.extensions().get::<Principal>().cloned())
+            .map_err(|status| status.code())
+    }
+
+    fn alice() -> Option<Principal> {
+        Some(Principal::new("alice"))
+    }
+
+    #[test]
+    fn disabled_authenticator_should_accept_every_request() {
+        let mut auth = Authenticator::from_config(&AuthConfig::default()).unwrap();
+        assert_eq!(call(&mut auth, None), Ok(None));
+    }
+
+    #[test]
+    fn hmac_token_should_be_validated() {
+        let mut auth = Authenticator::from_config(&AuthConfig {
+            issuer: Some("issuer".to_string()),
+            audience: None,
+            keys: vec![AuthKey::Hmac {
+                secret: "secret".to_string(),
+            }],
+        })
+        .unwrap();
+        let key = EncodingKey::from_secret(b"secret");
+        let other = EncodingKey::from_secret(b"other");
+        let header = Header::default();
+
+        let principal = call(&mut auth, Some(&token(&header, &key, "issuer")));
+        assert_eq!(principal, Ok(alice()));
+
+        let unauthenticated = Err(Code::Unauthenticated);
+        assert_eq!(call(&mut auth, None), unauthenticated);
+        let principal = call(&mut auth, Some(&token(&header, &other, "issuer")));
+        assert_eq!(principal, unauthenticated);
+        let principal = call(&mut auth, Some(&token(&header, &key, "other")));
+        assert_eq!(principal, unauthenticated);
+    }
+
+    #[test]
+    fn tenant_claim_should_be_decoded() {
+        let mut auth = Authenticator::from_config(&AuthConfig {
+            issuer: None,
+            audience: None,
+            keys: vec![AuthKey::Hmac {
+                secret: "secret".to_string(),
+            }],
+        })
+        .unwrap();
+        let claims = serde_json::json!({
+            "sub": "alice",
+            "exp": jsonwebtoken::get_current_timestamp() + 600,
+            "tenant": "acme",
+        });
+        let key = EncodingKey::from_secret(b"secret");
+        let token = encode(&Header::default(), &claims, &key).unwrap();
+
+        let principal = Principal {
+            tenant: Some("acme".to_string()),
+            ..Principal::new("alice")
+        };
+        assert_eq!(call(&mut auth, Some(&token)), Ok(Some(principal)));
+    }
+
+    #[test]
+    fn only_managers_should_confirm_blocked_reservations() {
+        let mut rsvp = Reservation {
+            id: 1,
+            user_id: "alice".to_string(),
+            resource_id: "room".to_string(),
+            status: ReservationStatus::Pending as i32,
+            ..Default::default()
+        };
+        let alice = Principal::new("alice");
+        let manager = Principal {
+            role: Role::Manager,
+            resources: vec!["room".to_string()],
+            ..Principal::new("carol")
+        };
+        assert!(alice.check(Permission::Confirm, &rsvp).is_ok());
+        assert!(Principal::new("bob")
+            .check(Permission::Confirm, &rsvp)
+            .is_err());
+
+        rsvp.status = ReservationStatus::Blocked as i32;
+        assert!(alice.check(Permission::Confirm, &rsvp).is_err());
+        assert!(manager.check(Permission::Confirm, &rsvp).is_ok());
+    }
+
+    #[test]
+    fn managers_should_modify_reservations_of_their_resources() {
+        let rsvp = Reservation {
+            id: 1,
+            user_id: "alice".to_string(),
+            resource_id: "room".to_string(),
+            status: ReservationStatus::Pending as i32,
+            ..Default::default()
+        };
+        let manager = |resource: &str| Principal {
+            role: Role::Manager,
+            resources: vec![resource.to_string()],
+            ..Principal::new("carol")
+        };
+        assert!(Principal::new("alice")
+            .check(Permission::Modify, &rsvp)
+            .is_ok());
+        assert!(manager("room").check(Permission::Modify, &rsvp).is_ok());
+        assert!(manager("hall").check(Permission::Modify, &rsvp).is_err());
+        assert!(Principal::new("bob")
+            .check(Permission::Modify, &rsvp)
+            .is_err());
+    }
+
+    #[test]
+    fn pem_and_jwks_tokens_should_be_validated() {
+        let mut auth = Authenticator::from_config(&AuthConfig {
+            issuer: None,
+            audience: None,
+            keys: vec![
+                AuthKey::Pem {
+                    path: fixture("ec_public.pem"),
+                },
+                AuthKey::Jwks {
+                    path: fixture("jwks.json"),
+                },
+            ],
+        })
+        .unwrap();
+
+        let pem = fs::read(fixture("ec_private.pem")).unwrap();
+        let key = EncodingKey::from_ec_pem(&pem).unwrap();
+        let header = Header::new(Algorithm::ES256);
+        let principal = call(&mut auth, Some(&token(&header, &key, "issuer")));
+        assert_eq!(principal, Ok(alice()));
+
+        let key = EncodingKey::from_secret(b"jwks secret");
+        let header = Header {
+            kid: Some("hmac".to_string()),
+            ..Default::default()
+        };
+        let principal = call(&mut auth, Some(&token(&header, &key, "issuer")));
+        assert_eq!(principal, Ok(alice()));
+
+        let header = Header {
+            kid: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        let principal = call(&mut auth, Some(&token(&header, &key, "issuer")));
+        assert_eq!(principal, Err(Code::Unauthenticated));
+    }
+}
@@ -1,9 +1,12 @@
+mod auth;
 mod service;
 
-use abi::Reservation;
+use abi::{ListenResponse, Reservation};
 
 use abi::reservation_service_server::ReservationServiceServer;
 use anyhow::Result;
+pub use auth::{Authenticator, Permission, Principal, Role};
+use reservation::Scoped;
 pub use service::RsvpService;
 use std::{
     net::SocketAddr,
@@ -18,16 +21,21 @@ use tokio_stream::Stream;
 use tonic::Status;
 
 type ReservationStream = Pin<Box<dyn Stream<Item = Result<Reservation, Status>> + Send>>;
+type ListenStream = Pin<Box<dyn Stream<Item = Result<ListenResponse, Status>> + Send>>;
 
-pub async fn run(
+pub async fn run<R: Scoped + Send + Sync + 'static>(
     listen: SocketAddr,
-    service: RsvpService,
+    service: RsvpService<R>,
 ) -> Result<(Sender<()>, JoinHandle<Result<(), tonic::transport::Error>>)> {
     let (tx, rx) = tokio::sync::oneshot::channel();
+    let authenticator = service.authenticator.clone();
 
     let handler = tokio::spawn(async move {
         tonic::transport::Server::builder()
-            .add_service(ReservationServiceServer::new(service))
+            .add_service(ReservationServiceServer::with_interceptor(
+                service,
+                authenticator,
+            ))
             .serve_with_shutdown(listen, async {
                 rx.await.ok();
             })
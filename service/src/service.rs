@@ -1,53 +1,323 @@
 use abi::{
-    reservation_service_server::ReservationService, CancelRequest, CancelResponse, ConfirmRequest,
-    ConfirmResponse, FilterRequest, FilterResponse, GetRequest, GetResponse, ListenRequest,
-    QueryRequest, ReserveRequest, ReserveResponse, UpdateRequest, UpdateResponse,
+    reservation_service_server::ReservationService,
+    utils::{datetime_to_timestamp, duration_to_timedelta, try_timestamp_to_datetime},
+    AvailabilityRequest, AvailabilityResponse, CancelRequest, CancelResponse,
+    ChangeResourceRequest, ChangeResourceResponse, ConfirmRequest, ConfirmResponse,
+    CreateResourceRequest, CreateResourceResponse, DeleteResourceRequest, DeleteResourceResponse,
+    FilterRequest, FilterResponse, GetHistoryRequest, GetHistoryResponse, GetRequest,
+    GetResourceRequest, GetResourceResponse, GetResponse, ListResourcesRequest,
+    ListResourcesResponse, ListenRequest, QueryRequest, QuotaUsageRequest, QuotaUsageResponse,
+    RescheduleRequest, RescheduleResponse, ReservationStatus, ReserveBatchRequest,
+    ReserveBatchResponse, ReserveRequest, ReserveResponse, ReserveSeriesRequest,
+    ReserveSeriesResponse, SeriesConflictMode, UpdateRequest, UpdateResourceRequest,
+    UpdateResourceResponse, UpdateResponse,
 };
 use anyhow::Result;
-use reservation::{ReservationManager, Rsvp as _};
+use chrono::{TimeDelta, Utc};
+use reservation::{ReservationManager, ReserveOutcome, Rsvp, Scoped, DEFAULT_TENANT};
+use std::time::Duration;
+use tokio::task::AbortHandle;
 
 use tonic::{Request, Response, Status};
 
-use crate::{ReservationStream, TonicReceiverStream};
+use crate::{
+    auth::{Permission, Principal},
+    Authenticator, ListenStream, ReservationStream, TonicReceiverStream,
+};
+
+// metadata key of the tenant the request belongs to
+const TENANT: &str = "tenant-id";
+// metadata key of who makes a change, recorded in the history of the reservation
+const ACTOR: &str = "actor";
+// metadata key of the idempotency key, if the request doesn't set it
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
 
-pub struct RsvpService {
-    manager: ReservationManager,
+/// The gRPC service, backed by the database or by any other `Rsvp`
+pub struct RsvpService<R = ReservationManager> {
+    manager: R,
+    // hold ttl for a pending reservation, if the request doesn't set one
+    default_hold_ttl: Option<TimeDelta>,
+    // background task releasing expired holds, stopped when the service is dropped
+    hold_sweeper: Option<AbortHandle>,
+    // validates the bearer token of every request, used as the interceptor of the server
+    pub(crate) authenticator: Authenticator,
 }
 
 impl RsvpService {
     pub async fn from_config(config: &abi::config::Config) -> Result<Self> {
-        let manager = ReservationManager::from_config(&config.db).await?;
-        Ok(Self { manager })
+        let manager = ReservationManager::from_config(&config.db)
+            .await?
+            .with_policy(config.policy.clone())
+            .with_quota(config.quota.clone())
+            .with_idempotency(config.idempotency.clone());
+        config.validate()?;
+        let authenticator = Authenticator::from_config(&config.auth)?;
+        let hold_sweeper = spawn_hold_sweeper(
+            manager.clone(),
+            Duration::from_secs(config.hold.sweep_interval),
+        );
+
+        Ok(Self {
+            manager,
+            default_hold_ttl: config
+                .hold
+                .default_ttl
+                .and_then(|ttl| TimeDelta::try_seconds(ttl as i64)),
+            hold_sweeper: Some(hold_sweeper),
+            authenticator,
+        })
+    }
+}
+
+impl<R: Scoped + Send + Sync + 'static> RsvpService<R> {
+    pub fn new(manager: R) -> Self {
+        Self {
+            manager,
+            default_hold_ttl: None,
+            hold_sweeper: None,
+            authenticator: Authenticator::default(),
+        }
+    }
+
+    // the manager for the tenant of the request, making the changes on behalf of its actor
+    // a request without a tenant belongs to the default one, an authenticated caller belongs
+    // to the tenant of its token and is the actor
+    fn manager_for<T>(&self, request: &Request<T>) -> Result<R, abi::Error> {
+        let metadata = request.metadata();
+        let mut manager = self.manager.clone();
+        let header = match metadata.get(TENANT) {
+            Some(tenant) => Some(tenant.to_str().map_err(|_| abi::Error::InvalidTenantId)?),
+            None => None,
+        };
+        let tenant = match request.extensions().get::<Principal>() {
+            Some(principal) => {
+                let tenant = principal.tenant.as_deref().unwrap_or(DEFAULT_TENANT);
+                if header.is_some_and(|header| header != tenant) {
+                    return Err(abi::Error::PermissionDenied(format!(
+                        "user {} doesn't belong to the tenant of the request",
+                        principal.subject
+                    )));
+                }
+                Some(tenant)
+            }
+            None => header,
+        };
+        if let Some(tenant) = tenant {
+            if tenant.is_empty() || tenant.len() > 64 {
+                return Err(abi::Error::InvalidTenantId);
+            }
+            manager = manager.with_tenant(tenant);
+        }
+        if let Some(principal) = request.extensions().get::<Principal>() {
+            manager = manager.with_actor(&principal.subject);
+        } else if let Some(actor) = metadata.get(ACTOR).and_then(|actor| actor.to_str().ok()) {
+            manager = manager.with_actor(actor);
+        }
+
+        Ok(manager)
+    }
+}
+
+impl<R> Drop for RsvpService<R> {
+    fn drop(&mut self) {
+        if let Some(hold_sweeper) = self.hold_sweeper.take() {
+            hold_sweeper.abort();
+        }
+    }
+}
+
+// the idempotency key sent as metadata, a key in the request itself wins over it
+fn idempotency_key<T>(request: &Request<T>) -> Option<String> {
+    request
+        .metadata()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(|key| key.to_string())
+}
+
+// the manager making its change once for the idempotency key, if the request has one
+fn once<R: Scoped>(manager: R, key: Option<String>) -> R {
+    match key {
+        Some(key) => manager.with_idempotency_key(key),
+        None => manager,
+    }
+}
+
+// the authenticated caller of the request, `None` if authentication is disabled
+fn principal<T>(request: &Request<T>) -> Option<Principal> {
+    request.extensions().get::<Principal>().cloned()
+}
+
+// check the caller may do something with a reservation, anyone may if authentication is disabled
+async fn authorize(
+    manager: &impl Rsvp,
+    principal: Option<&Principal>,
+    id: i64,
+    permission: Permission,
+) -> Result<(), abi::Error> {
+    match principal {
+        Some(principal) if !principal.is_admin() => {
+            principal.check(permission, &manager.get(id).await?)
+        }
+        _ => Ok(()),
     }
+}
 
-    pub fn new(manager: ReservationManager) -> Self {
-        Self { manager }
+// a new reservation starts pending without an expiry, only a manager of the resource can block it
+fn new_reservation(
+    rsvp: &mut abi::Reservation,
+    principal: Option<&Principal>,
+) -> Result<(), abi::Error> {
+    // an authenticated caller can only reserve for itself
+    if let Some(principal) = principal {
+        rsvp.user_id = principal.subject.clone();
+        principal.check_reserve(rsvp)?;
+    }
+    if rsvp.status != ReservationStatus::Blocked as i32 {
+        rsvp.status = ReservationStatus::Pending as i32;
     }
+    rsvp.expires_at = None;
+
+    Ok(())
+}
+
+// release expired holds of every tenant periodically, the listeners get an update for each of them
+fn spawn_hold_sweeper<R: Scoped + Send + Sync + 'static>(
+    manager: R,
+    interval: Duration,
+) -> AbortHandle {
+    let handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = manager.release_all_expired().await {
+                tracing::warn!(error = %e, "failed to release expired holds");
+            }
+        }
+    });
+
+    handle.abort_handle()
 }
 
 #[tonic::async_trait]
-impl ReservationService for RsvpService {
+impl<R: Scoped + Send + Sync + 'static> ReservationService for RsvpService<R> {
     async fn reserve(
         &self,
         request: Request<ReserveRequest>,
     ) -> Result<Response<ReserveResponse>, Status> {
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
+        let principal = principal(&request);
         let request: ReserveRequest = request.into_inner();
+        let idempotency_key = request.idempotency_key.or(metadata_key);
         match request.reservation {
-            Some(rsvp) => {
-                let rsvp = self.manager.reserve(rsvp).await?;
-                Ok(Response::new(ReserveResponse {
-                    reservation: Some(rsvp),
-                }))
+            Some(mut rsvp) => {
+                new_reservation(&mut rsvp, principal.as_ref())?;
+                // a hold which expires as it's made holds nothing
+                let ttl = match request.hold_ttl.as_ref().map(duration_to_timedelta) {
+                    Some(Some(ttl)) if ttl > TimeDelta::zero() => Some(ttl),
+                    Some(_) => return Err(abi::Error::InvalidTimespan.into()),
+                    None => self.default_hold_ttl,
+                };
+                // only a pending reservation can be a hold
+                let pending = rsvp.status == ReservationStatus::Pending as i32;
+                if let (Some(ttl), true) = (ttl, pending) {
+                    let expires_at = Utc::now()
+                        .checked_add_signed(ttl)
+                        .ok_or(abi::Error::InvalidTimespan)?;
+                    rsvp.expires_at = Some(datetime_to_timestamp(expires_at));
+                }
+
+                let outcome = match (idempotency_key, request.waitlist) {
+                    (Some(key), waitlist) => {
+                        manager.reserve_idempotent(key, rsvp, waitlist).await?
+                    }
+                    (None, true) => manager.reserve_or_wait(rsvp).await?,
+                    (None, false) => {
+                        ReserveOutcome::Reserved(Box::new(manager.reserve(rsvp).await?))
+                    }
+                };
+
+                let response = match outcome {
+                    ReserveOutcome::Reserved(rsvp) => ReserveResponse {
+                        reservation: Some(*rsvp),
+                        waitlist_id: None,
+                    },
+                    ReserveOutcome::Waitlisted(id) => ReserveResponse {
+                        reservation: None,
+                        waitlist_id: Some(id),
+                    },
+                };
+                Ok(Response::new(response))
             }
-            None => Err(Status::invalid_argument("Invalid reservation")),
+            None => Err(Status::from(abi::Error::MissingField("reservation"))),
         }
     }
+
+    async fn reserve_batch(
+        &self,
+        request: Request<ReserveBatchRequest>,
+    ) -> Result<Response<ReserveBatchResponse>, Status> {
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
+        let principal = principal(&request);
+        let mut request: ReserveBatchRequest = request.into_inner();
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        for rsvp in request.reservations.iter_mut() {
+            new_reservation(rsvp, principal.as_ref())?;
+        }
+        let rsvps = manager.reserve_many(request.reservations).await?;
+
+        Ok(Response::new(ReserveBatchResponse {
+            reservations: rsvps,
+        }))
+    }
+
+    async fn reserve_series(
+        &self,
+        request: Request<ReserveSeriesRequest>,
+    ) -> Result<Response<ReserveSeriesResponse>, Status> {
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
+        let principal = principal(&request);
+        let request: ReserveSeriesRequest = request.into_inner();
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let Some(mut series) = request.series else {
+            return Err(Status::from(abi::Error::MissingField("series")));
+        };
+        if let Some(principal) = principal {
+            series.user_id = principal.subject;
+        }
+        let mode = SeriesConflictMode::try_from(request.mode).unwrap_or(SeriesConflictMode::Fail);
+
+        let (series, rsvps, conflicts) = manager.reserve_series(series, mode).await?;
+
+        Ok(Response::new(ReserveSeriesResponse {
+            series: Some(series),
+            reservations: rsvps,
+            conflicts,
+        }))
+    }
+
     async fn confirm(
         &self,
         request: Request<ConfirmRequest>,
     ) -> Result<Response<ConfirmResponse>, Status> {
+        let principal = principal(&request);
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
         let request: ConfirmRequest = request.into_inner();
-        let rsvp = self.manager.change_status(request.id).await?;
+        authorize(
+            &manager,
+            principal.as_ref(),
+            request.id,
+            Permission::Confirm,
+        )
+        .await?;
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let rsvp = manager
+            .change_status(request.id, request.expected_version)
+            .await?;
 
         Ok(Response::new(ConfirmResponse {
             reservation: Some(rsvp),
@@ -58,20 +328,77 @@ impl ReservationService for RsvpService {
         &self,
         request: Request<UpdateRequest>,
     ) -> Result<Response<UpdateResponse>, Status> {
+        let principal = principal(&request);
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
         let request: UpdateRequest = request.into_inner();
-        let rsvp = self.manager.update_notes(request.id, request.note).await?;
+        authorize(&manager, principal.as_ref(), request.id, Permission::Modify).await?;
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let rsvp = manager
+            .update_notes(request.id, request.note, request.expected_version)
+            .await?;
 
         Ok(Response::new(UpdateResponse {
             reservation: Some(rsvp),
         }))
     }
 
+    async fn reschedule(
+        &self,
+        request: Request<RescheduleRequest>,
+    ) -> Result<Response<RescheduleResponse>, Status> {
+        let principal = principal(&request);
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
+        let request: RescheduleRequest = request.into_inner();
+        authorize(&manager, principal.as_ref(), request.id, Permission::Modify).await?;
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let start = request.start.as_ref().and_then(try_timestamp_to_datetime);
+        let end = request.end.as_ref().and_then(try_timestamp_to_datetime);
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(abi::Error::InvalidTimespan.into());
+        };
+        let rsvp = manager
+            .reschedule(request.id, start, end, request.expected_version)
+            .await?;
+
+        Ok(Response::new(RescheduleResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    async fn change_resource(
+        &self,
+        request: Request<ChangeResourceRequest>,
+    ) -> Result<Response<ChangeResourceResponse>, Status> {
+        let principal = principal(&request);
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
+        let request: ChangeResourceRequest = request.into_inner();
+        authorize(&manager, principal.as_ref(), request.id, Permission::Modify).await?;
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let rsvp = manager
+            .change_resource(request.id, request.resource_id, request.expected_version)
+            .await?;
+
+        Ok(Response::new(ChangeResourceResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
     async fn cancel(
         &self,
         request: Request<CancelRequest>,
     ) -> Result<Response<CancelResponse>, Status> {
+        let principal = principal(&request);
+        let metadata_key = idempotency_key(&request);
+        let manager = self.manager_for(&request)?;
         let request: CancelRequest = request.into_inner();
-        let rsvp = self.manager.change_status(request.id).await?;
+        authorize(&manager, principal.as_ref(), request.id, Permission::Modify).await?;
+        let manager = once(manager, request.idempotency_key.or(metadata_key));
+        let rsvp = manager
+            .cancel(request.id, request.reason, request.expected_version)
+            .await?;
 
         Ok(Response::new(CancelResponse {
             reservation: Some(rsvp),
@@ -79,14 +406,36 @@ impl ReservationService for RsvpService {
     }
 
     async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let principal = principal(&request);
+        let manager = self.manager_for(&request)?;
         let request: GetRequest = request.into_inner();
-        let rsvp = self.manager.get(request.id).await?;
+        authorize(&manager, principal.as_ref(), request.id, Permission::Read).await?;
+        let rsvp = manager.get(request.id).await?;
 
         Ok(Response::new(GetResponse {
             reservation: Some(rsvp),
         }))
     }
 
+    async fn get_history(
+        &self,
+        request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        let principal = principal(&request);
+        let manager = self.manager_for(&request)?;
+        let request: GetHistoryRequest = request.into_inner();
+        let changes = manager.get_history(request.id).await?;
+        // the latest state tells who can read the history, even if the reservation is deleted
+        let latest = changes
+            .last()
+            .and_then(|change| change.after.as_ref().or(change.before.as_ref()));
+        if let (Some(principal), Some(rsvp)) = (principal, latest) {
+            principal.check(Permission::Read, rsvp)?;
+        }
+
+        Ok(Response::new(GetHistoryResponse { changes }))
+    }
+
     /// Server streaming response type for the query method.
     type queryStream = ReservationStream;
     /// for user to query reservations
@@ -94,12 +443,26 @@ impl ReservationService for RsvpService {
         &self,
         request: Request<QueryRequest>,
     ) -> Result<Response<Self::queryStream>, Status> {
+        let principal = principal(&request);
+        let manager = self.manager_for(&request)?;
         let request = request.into_inner();
-        let Some(query_para) = request.query else {
-            return Err(Status::invalid_argument("Invalid query"));
+        let Some(mut query_para) = request.query else {
+            return Err(Status::from(abi::Error::MissingField("query")));
         };
+        // a user queries its own reservations, a manager also those of the resources it manages
+        if let Some(principal) = principal {
+            let managed = query_para
+                .resource_id
+                .as_deref()
+                .is_some_and(|id| principal.manages(id));
+            match &query_para.user_id {
+                _ if managed => {}
+                Some(user_id) => principal.check_user(user_id)?,
+                None => query_para.user_id = Some(principal.subject),
+            }
+        }
 
-        let rsvps = self.manager.query(query_para).await?;
+        let rsvps = manager.query(query_para).await?;
         let stream = TonicReceiverStream::new(rsvps);
 
         Ok(Response::new(Box::pin(stream) as Self::queryStream))
@@ -109,42 +472,724 @@ impl ReservationService for RsvpService {
         &self,
         request: Request<FilterRequest>,
     ) -> Result<Response<FilterResponse>, Status> {
+        if let Some(principal) = principal(&request) {
+            principal.check_admin("filter reservations")?;
+        }
+        let manager = self.manager_for(&request)?;
         let request: FilterRequest = request.into_inner();
         let Some(filter) = request.filter else {
-            return Err(Status::invalid_argument("Invalid filter"));
+            return Err(Status::from(abi::Error::MissingField("filter")));
         };
 
-        let (pager, rsvps) = self.manager.filter(filter).await?;
+        let (pager, rsvps) = manager.filter(filter).await?;
 
         Ok(Response::new(FilterResponse {
             reservation: rsvps,
             pager: Some(pager),
         }))
     }
+    /// find free timespans of a resource
+    async fn find_availability(
+        &self,
+        request: Request<AvailabilityRequest>,
+    ) -> Result<Response<AvailabilityResponse>, Status> {
+        let manager = self.manager_for(&request)?;
+        let request: AvailabilityRequest = request.into_inner();
+        let start = request.start.as_ref().and_then(try_timestamp_to_datetime);
+        let end = request.end.as_ref().and_then(try_timestamp_to_datetime);
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(abi::Error::InvalidTimespan.into());
+        };
+        let Some(duration) = request.duration.as_ref() else {
+            return Err(abi::Error::MissingField("duration").into());
+        };
+        let duration = duration_to_timedelta(duration).ok_or(abi::Error::InvalidTimespan)?;
+
+        let slots = manager
+            .find_availability(request.resource_id, start, end, duration)
+            .await?;
+
+        Ok(Response::new(AvailabilityResponse { slots }))
+    }
+    /// how much of their quotas a user has left
+    async fn get_quota_usage(
+        &self,
+        request: Request<QuotaUsageRequest>,
+    ) -> Result<Response<QuotaUsageResponse>, Status> {
+        let principal = principal(&request);
+        let manager = self.manager_for(&request)?;
+        let request = request.into_inner();
+        if let Some(principal) = principal {
+            principal.check_user(&request.user_id)?;
+        }
+        let week = match request.week.as_ref() {
+            Some(week) => try_timestamp_to_datetime(week).ok_or(abi::Error::InvalidTimespan)?,
+            None => Utc::now(),
+        };
+
+        let usages = manager.quota_usage(request.user_id, week).await?;
+
+        Ok(Response::new(QuotaUsageResponse { usages }))
+    }
+
+    /// register a resource, so it can be reserved
+    async fn create_resource(
+        &self,
+        request: Request<CreateResourceRequest>,
+    ) -> Result<Response<CreateResourceResponse>, Status> {
+        if let Some(principal) = principal(&request) {
+            principal.check_admin("create resources")?;
+        }
+        let manager = self.manager_for(&request)?;
+        let request = request.into_inner();
+        let Some(resource) = request.resource else {
+            return Err(Status::from(abi::Error::MissingField("resource")));
+        };
+
+        let resource = manager.create_resource(resource).await?;
+
+        Ok(Response::new(CreateResourceResponse {
+            resource: Some(resource),
+        }))
+    }
+    async fn get_resource(
+        &self,
+        request: Request<GetResourceRequest>,
+    ) -> Result<Response<GetResourceResponse>, Status> {
+        let manager = self.manager_for(&request)?;
+        let request = request.into_inner();
+
+        let resource = manager.get_resource(request.id).await?;
+
+        Ok(Response::new(GetResourceResponse {
+            resource: Some(resource),
+        }))
+    }
+    async fn update_resource(
+        &self,
+        request: Request<UpdateResourceRequest>,
+    ) -> Result<Response<UpdateResourceResponse>, Status> {
+        if let Some(principal) = principal(&request) {
+            principal.check_admin("update resources")?;
+        }
+        let manager = self.manager_for(&request)?;
+        let request = request.into_inner();
+        let Some(resource) = request.resource else {
+            return Err(Status::from(abi::Error::MissingField("resource")));
+        };
+
+        let resource = manager.update_resource(resource).await?;
+
+        Ok(Response::new(UpdateResourceResponse {
+            resource: Some(resource),
+        }))
+    }
+    async fn delete_resource(
+        &self,
+        request: Request<DeleteResourceRequest>,
+    ) -> Result<Response<DeleteResourceResponse>, Status> {
+        if let Some(principal) = principal(&request) {
+            principal.check_admin("delete resources")?;
+        }
+        let manager = self.manager_for(&request)?;
+        let request = request.into_inner();
+
+        let resource = manager.delete_resource(request.id).await?;
+
+        Ok(Response::new(DeleteResourceResponse {
+            resource: Some(resource),
+        }))
+    }
+    async fn list_resources(
+        &self,
+        request: Request<ListResourcesRequest>,
+    ) -> Result<Response<ListResourcesResponse>, Status> {
+        let manager = self.manager_for(&request)?;
+        let resources = manager.list_resources().await?;
+
+        Ok(Response::new(ListResourcesResponse { resources }))
+    }
+
     /// Server streaming response type for the listen method.
-    type listenStream = ReservationStream;
+    type listenStream = ListenStream;
     /// another system could monitor newly added/confirmed/cancelled reservations
     async fn listen(
         &self,
-        _request: Request<ListenRequest>,
+        request: Request<ListenRequest>,
     ) -> Result<Response<Self::listenStream>, Status> {
-        todo!()
+        if let Some(principal) = principal(&request) {
+            principal.check_admin("listen to every change")?;
+        }
+        let manager = self.manager_for(&request)?;
+        let changes = manager.listen().await?;
+        let stream = TonicReceiverStream::new(changes);
+
+        Ok(Response::new(Box::pin(stream) as Self::listenStream))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use abi::ReservationStatus;
-    use sqlx::types::chrono::Utc;
+    use abi::ReservationUpdateType;
     use tokio_stream::StreamExt as _;
 
     use super::*;
+    use crate::auth::Role;
 
-    #[sqlx::test(migrations = "../migrations")]
+    fn reserve_request(rsvp: abi::Reservation) -> ReserveRequest {
+        ReserveRequest {
+            reservation: Some(rsvp),
+            hold_ttl: None,
+            waitlist: false,
+            idempotency_key: None,
+        }
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
     async fn test_reserve(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let response = service.reserve(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_with_idempotency_key(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = || {
+            reserve_request(abi::Reservation::new_pendding(
+                "user".to_string(),
+                "room".to_string(),
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+                "2021-01-02T00:00:00Z".parse().unwrap(),
+                "note",
+            ))
+        };
+
+        let mut first = Request::new(request());
+        first
+            .metadata_mut()
+            .insert(IDEMPOTENCY_KEY, "key".parse().unwrap());
+        let first = service.reserve(first).await.unwrap().into_inner();
+
+        // the key in the request is the same as the one in the metadata
+        let retry = ReserveRequest {
+            idempotency_key: Some("key".to_string()),
+            ..request()
+        };
+        let retry = service.reserve(Request::new(retry)).await.unwrap();
+        assert_eq!(retry.into_inner(), first);
+
+        let err = service.reserve(Request::new(request())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+        let conflicts = abi::error::conflict::ReservationConflict::from_status(&err);
+        let existing = &conflicts[0].existing[0];
+        assert_eq!(existing.id, first.reservation.unwrap().id);
+        assert!(existing.user_id.is_empty());
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_cancel_with_idempotency_key(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let rsvp = abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        );
+        service
+            .reserve_batch(Request::new(ReserveBatchRequest {
+                reservations: vec![rsvp],
+                idempotency_key: None,
+            }))
+            .await
+            .unwrap();
+        let request = || CancelRequest {
+            id: 1,
+            reason: None,
+            expected_version: None,
+            idempotency_key: None,
+        };
+
+        let mut first = Request::new(request());
+        first
+            .metadata_mut()
+            .insert(IDEMPOTENCY_KEY, "key".parse().unwrap());
+        let first = service.cancel(first).await.unwrap().into_inner();
+
+        // a cancelled reservation can't be cancelled again, but the retry gets the first response
+        let retry = CancelRequest {
+            idempotency_key: Some("key".to_string()),
+            ..request()
+        };
+        let retry = service.cancel(Request::new(retry)).await.unwrap();
+        assert_eq!(retry.into_inner(), first);
+
+        let err = service.cancel(Request::new(request())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_tenant_from_metadata(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        fn with_tenant<T>(request: T, tenant: &str) -> Request<T> {
+            let mut request = Request::new(request);
+            request
+                .metadata_mut()
+                .insert(TENANT, tenant.parse().unwrap());
+            request
+        }
+
+        let request = CreateResourceRequest {
+            resource: Some(abi::Resource {
+                id: "room".to_string(),
+                ..Default::default()
+            }),
+        };
+        service
+            .create_resource(with_tenant(request, "other"))
+            .await
+            .unwrap();
+
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        service
+            .reserve(Request::new(request.clone()))
+            .await
+            .unwrap();
+        let response = service
+            .reserve(with_tenant(request.clone(), "other"))
+            .await
+            .unwrap();
+        let id = response.get_ref().reservation.as_ref().unwrap().id;
+
+        let err = service
+            .get(Request::new(GetRequest { id }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        service
+            .get(with_tenant(GetRequest { id }, "other"))
+            .await
+            .unwrap();
+
+        let err = service.reserve(with_tenant(request, "")).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_tenant_from_principal(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        fn as_principal<T>(request: T, principal: &Principal, tenant: Option<&str>) -> Request<T> {
+            let mut request = Request::new(request);
+            request.extensions_mut().insert(principal.clone());
+            if let Some(tenant) = tenant {
+                request
+                    .metadata_mut()
+                    .insert(TENANT, tenant.parse().unwrap());
+            }
+            request
+        }
+        let admin = Principal {
+            role: Role::Admin,
+            tenant: Some("other".to_string()),
+            ..Principal::new("dave")
+        };
+        let default_admin = Principal {
+            role: Role::Admin,
+            ..Principal::new("erin")
+        };
+        let alice = Principal {
+            tenant: Some("other".to_string()),
+            ..Principal::new("alice")
+        };
+
+        let request = || CreateResourceRequest {
+            resource: Some(abi::Resource {
+                id: "room".to_string(),
+                ..Default::default()
+            }),
+        };
+        service
+            .create_resource(as_principal(request(), &admin, None))
+            .await
+            .unwrap();
+
+        let request = || {
+            reserve_request(abi::Reservation::new_pendding(
+                "alice".to_string(),
+                "room".to_string(),
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+                "2021-01-02T00:00:00Z".parse().unwrap(),
+                "note",
+            ))
+        };
+        let response = service
+            .reserve(as_principal(request(), &alice, Some("other")))
+            .await
+            .unwrap();
+        let id = response.get_ref().reservation.as_ref().unwrap().id;
+
+        // the tenant of the token wins, a header naming another tenant is rejected
+        let err = service
+            .get(as_principal(GetRequest { id }, &default_admin, None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        let err = service
+            .get(as_principal(
+                GetRequest { id },
+                &default_admin,
+                Some("other"),
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = service
+            .reserve(as_principal(request(), &alice, Some(DEFAULT_TENANT)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        service
+            .get(as_principal(GetRequest { id }, &admin, None))
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_get_history(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let mut request = Request::new(request);
+        request
+            .metadata_mut()
+            .insert(ACTOR, "alice".parse().unwrap());
+        let response = service.reserve(request).await.unwrap();
+        let id = response.get_ref().reservation.as_ref().unwrap().id;
+
+        let response = service
+            .get_history(Request::new(GetHistoryRequest { id }))
+            .await
+            .unwrap();
+        let changes = &response.get_ref().changes;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].actor.as_deref(), Some("alice"));
+        assert_eq!(changes[0].after.as_ref().unwrap().id, id);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_as_principal(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "mallory".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let mut request = Request::new(request);
+        request.extensions_mut().insert(Principal::new("alice"));
+        let response = service.reserve(request).await.unwrap();
+        let rsvp = response.into_inner().reservation.unwrap();
+        assert_eq!(rsvp.user_id, "alice");
+
+        let response = service
+            .get_history(Request::new(GetHistoryRequest { id: rsvp.id }))
+            .await
+            .unwrap();
+        let changes = &response.get_ref().changes;
+        assert_eq!(changes[0].actor.as_deref(), Some("alice"));
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_should_start_pending(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let mut rsvp = abi::Reservation::new_pendding(
+            "alice",
+            "room",
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        );
+        rsvp.status = ReservationStatus::Confirmed as i32;
+        rsvp.expires_at = Some(datetime_to_timestamp(Utc::now()));
+        let mut request = Request::new(reserve_request(rsvp));
+        request.extensions_mut().insert(Principal::new("alice"));
+        let response = service.reserve(request).await.unwrap();
+        let rsvp = response.into_inner().reservation.unwrap();
+        assert_eq!(rsvp.status, ReservationStatus::Pending as i32);
+        assert_eq!(rsvp.expires_at, None);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_permissions(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        fn as_principal<T>(request: T, principal: &Principal) -> Request<T> {
+            let mut request = Request::new(request);
+            request.extensions_mut().insert(principal.clone());
+            request
+        }
+        let alice = Principal::new("alice");
+        let bob = Principal::new("bob");
+        let manager = Principal {
+            role: Role::Manager,
+            resources: vec!["room".to_string()],
+            ..Principal::new("carol")
+        };
+        let admin = Principal {
+            role: Role::Admin,
+            ..Principal::new("dave")
+        };
+        let reserve = |status: ReservationStatus| {
+            let mut rsvp = abi::Reservation::new_pendding(
+                "",
+                "room",
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+                "2021-01-02T00:00:00Z".parse().unwrap(),
+                "note",
+            );
+            rsvp.status = status as i32;
+            reserve_request(rsvp)
+        };
+        let denied = |status: Status| assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        // only a manager of the resource can block it
+        let err = service
+            .reserve(as_principal(reserve(ReservationStatus::Blocked), &alice))
+            .await
+            .unwrap_err();
+        denied(err);
+        let response = service
+            .reserve(as_principal(reserve(ReservationStatus::Pending), &alice))
+            .await
+            .unwrap();
+        let id = response.into_inner().reservation.unwrap().id;
+
+        // only the owner and the manager of the resource can read it
+        let get = || GetRequest { id };
+        denied(service.get(as_principal(get(), &bob)).await.unwrap_err());
+        service.get(as_principal(get(), &alice)).await.unwrap();
+        service.get(as_principal(get(), &manager)).await.unwrap();
+
+        // only the owner and the manager of the resource can modify it
+        let update = || UpdateRequest {
+            id,
+            note: "new note".to_string(),
+            expected_version: None,
+            idempotency_key: None,
+        };
+        denied(
+            service
+                .update(as_principal(update(), &bob))
+                .await
+                .unwrap_err(),
+        );
+        service
+            .update(as_principal(update(), &manager))
+            .await
+            .unwrap();
+        service
+            .update(as_principal(update(), &alice))
+            .await
+            .unwrap();
+
+        // only the owner and the manager of the resource can confirm it
+        let confirm = || ConfirmRequest {
+            id,
+            expected_version: None,
+            idempotency_key: None,
+        };
+        denied(
+            service
+                .confirm(as_principal(confirm(), &bob))
+                .await
+                .unwrap_err(),
+        );
+        service
+            .confirm(as_principal(confirm(), &alice))
+            .await
+            .unwrap();
+
+        // a user queries its own reservations only
+        let query = |user_id: Option<&str>| QueryRequest {
+            query: Some(abi::ReservationQuery {
+                user_id: user_id.map(|id| id.to_string()),
+                end: Some(datetime_to_timestamp(Utc::now())),
+                ..Default::default()
+            }),
+        };
+        denied(
+            service
+                .query(as_principal(query(Some("alice")), &bob))
+                .await
+                .err()
+                .unwrap(),
+        );
+        let response = service.query(as_principal(query(None), &bob)).await;
+        let rsvps: Vec<_> = response.unwrap().into_inner().collect().await;
+        assert!(rsvps.is_empty());
+        let response = service.query(as_principal(query(None), &alice)).await;
+        let rsvps: Vec<_> = response.unwrap().into_inner().collect().await;
+        assert_eq!(rsvps.len(), 1);
+
+        // only an admin can filter
+        let filter = || FilterRequest {
+            filter: Some(abi::ReservationFilter {
+                page_size: 10,
+                ..Default::default()
+            }),
+        };
+        denied(
+            service
+                .filter(as_principal(filter(), &manager))
+                .await
+                .unwrap_err(),
+        );
+        let response = service
+            .filter(as_principal(filter(), &admin))
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().reservation.len(), 1);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_batch(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let rsvp = abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        );
+        let request = ReserveBatchRequest {
+            reservations: vec![
+                rsvp.clone(),
+                abi::Reservation {
+                    resource_id: "projector".to_string(),
+                    ..rsvp.clone()
+                },
+            ],
+            idempotency_key: None,
+        };
+        let response = service.reserve_batch(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_ref().reservations.len(), 2);
+
+        let request = ReserveBatchRequest {
+            reservations: vec![
+                abi::Reservation {
+                    resource_id: "parking".to_string(),
+                    ..rsvp.clone()
+                },
+                rsvp,
+            ],
+            idempotency_key: None,
+        };
+        let status = service
+            .reserve_batch(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+        assert!(status
+            .message()
+            .starts_with("Reservation 1 in batch failed"));
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_series(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = ReserveSeriesRequest {
+            series: Some(abi::ReservationSeries {
+                user_id: "user".to_string(),
+                resource_id: "room".to_string(),
+                start: Some(abi::utils::datetime_to_timestamp(
+                    "2024-01-01T10:00:00Z".parse().unwrap(),
+                )),
+                end: Some(abi::utils::datetime_to_timestamp(
+                    "2024-01-01T11:00:00Z".parse().unwrap(),
+                )),
+                rrule: "FREQ=DAILY;COUNT=3".to_string(),
+                ..Default::default()
+            }),
+            mode: SeriesConflictMode::Skip as i32,
+            idempotency_key: None,
+        };
+        let response = service.reserve_series(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_ref().series.as_ref().unwrap().id, 1);
+        assert_eq!(response.get_ref().reservations.len(), 3);
+        assert!(response.get_ref().conflicts.is_empty());
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_with_waitlist(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = |waitlist| ReserveRequest {
             reservation: Some(abi::Reservation::new_pendding(
                 "user".to_string(),
                 "room".to_string(),
@@ -152,13 +1197,30 @@ mod test {
                 "2021-01-02T00:00:00Z".parse().unwrap(),
                 "note",
             )),
+            hold_ttl: None,
+            waitlist,
+            idempotency_key: None,
         };
-        let response = service.reserve(Request::new(request)).await.unwrap();
-        assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
+        let response = service.reserve(Request::new(request(true))).await.unwrap();
+        assert!(response.get_ref().reservation.is_some());
+        assert_eq!(response.get_ref().waitlist_id, None);
+
+        let status = service
+            .reserve(Request::new(request(false)))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let response = service.reserve(Request::new(request(true))).await.unwrap();
+        assert!(response.get_ref().reservation.is_none());
+        assert!(response.get_ref().waitlist_id.is_some());
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn test_confirm(pool: sqlx::PgPool) {
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reserve_with_hold_ttl(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
         let request = ReserveRequest {
@@ -169,14 +1231,90 @@ mod test {
                 "2021-01-02T00:00:00Z".parse().unwrap(),
                 "note",
             )),
+            hold_ttl: Some(prost_types::Duration {
+                seconds: 600,
+                nanos: 0,
+            }),
+            waitlist: false,
+            idempotency_key: None,
+        };
+        let response = service.reserve(Request::new(request)).await.unwrap();
+        let rsvp = response.get_ref().reservation.as_ref().unwrap();
+        let expires_at = abi::utils::timestamp_to_datetime(rsvp.expires_at.as_ref().unwrap());
+        assert!(expires_at > Utc::now() + TimeDelta::try_minutes(9).unwrap());
+
+        let request = ConfirmRequest {
+            id: rsvp.id,
+            expected_version: None,
+            idempotency_key: None,
+        };
+        let response = service.confirm(Request::new(request)).await.unwrap();
+        assert!(response
+            .get_ref()
+            .reservation
+            .as_ref()
+            .unwrap()
+            .expires_at
+            .is_none());
+
+        // a ttl out of the range of a time delta can't make a hold
+        let request = ReserveRequest {
+            reservation: Some(abi::Reservation::new_pendding(
+                "user".to_string(),
+                "room".to_string(),
+                "2021-01-03T00:00:00Z".parse().unwrap(),
+                "2021-01-04T00:00:00Z".parse().unwrap(),
+                "note",
+            )),
+            hold_ttl: Some(prost_types::Duration {
+                seconds: i64::MAX,
+                nanos: 0,
+            }),
+            waitlist: false,
+            idempotency_key: None,
         };
+        let status = service
+            .reserve(Request::new(request.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        // neither can one which has expired already
+        for seconds in [0, -600] {
+            let request = ReserveRequest {
+                hold_ttl: Some(prost_types::Duration { seconds, nanos: 0 }),
+                ..request.clone()
+            };
+            let status = service.reserve(Request::new(request)).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_confirm(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
         assert_eq!(
             response.get_ref().reservation.as_ref().unwrap().status,
             ReservationStatus::Pending as i32
         );
-        let request = ConfirmRequest { id: 1 };
+        let request = ConfirmRequest {
+            id: 1,
+            expected_version: None,
+            idempotency_key: None,
+        };
         let response = service.confirm(Request::new(request)).await.unwrap();
         assert_eq!(
             response.get_ref().reservation.as_ref().unwrap().status,
@@ -184,24 +1322,27 @@ mod test {
         );
     }
 
-    #[sqlx::test(migrations = "../migrations")]
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
     async fn test_update(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
-            reservation: Some(abi::Reservation::new_pendding(
-                "user".to_string(),
-                "room".to_string(),
-                "2021-01-01T00:00:00Z".parse().unwrap(),
-                "2021-01-02T00:00:00Z".parse().unwrap(),
-                "note",
-            )),
-        };
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
         let request = UpdateRequest {
             id: 1,
             note: "new note".to_string(),
+            expected_version: None,
+            idempotency_key: None,
         };
         let response = service.update(Request::new(request)).await.unwrap();
         assert_eq!(
@@ -210,39 +1351,127 @@ mod test {
         );
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn test_cancel(pool: sqlx::PgPool) {
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_reschedule(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
-            reservation: Some(abi::Reservation::new_pendding(
-                "user".to_string(),
-                "room".to_string(),
-                "2021-01-01T00:00:00Z".parse().unwrap(),
-                "2021-01-02T00:00:00Z".parse().unwrap(),
-                "note",
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let response = service.reserve(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
+        let start = abi::utils::datetime_to_timestamp("2021-01-03T00:00:00Z".parse().unwrap());
+        let request = RescheduleRequest {
+            id: 1,
+            start: Some(start.clone()),
+            end: Some(abi::utils::datetime_to_timestamp(
+                "2021-01-04T00:00:00Z".parse().unwrap(),
             )),
+            expected_version: None,
+            idempotency_key: None,
+        };
+        let response = service.reschedule(Request::new(request)).await.unwrap();
+        assert_eq!(
+            response.get_ref().reservation.as_ref().unwrap().start,
+            Some(start.clone())
+        );
+
+        // a timestamp out of range is rejected instead of panicking
+        let request = RescheduleRequest {
+            id: 1,
+            start: Some(prost_types::Timestamp {
+                seconds: -1,
+                nanos: -1,
+            }),
+            end: Some(start),
+            expected_version: None,
+            idempotency_key: None,
+        };
+        let status = service.reschedule(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_change_resource(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let response = service.reserve(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
+        let request = ChangeResourceRequest {
+            id: 1,
+            resource_id: "room1".to_string(),
+            expected_version: None,
+            idempotency_key: None,
         };
+        let response = service
+            .change_resource(Request::new(request))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.get_ref().reservation.as_ref().unwrap().resource_id,
+            "room1"
+        );
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_cancel(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
-        let request = CancelRequest { id: 1 };
+        let request = CancelRequest {
+            id: 1,
+            reason: Some("reason".to_string()),
+            expected_version: None,
+            idempotency_key: None,
+        };
         let response = service.cancel(Request::new(request)).await.unwrap();
-        assert_eq!(response.get_ref().reservation.as_ref().unwrap().status, 2);
+        let rsvp = response.get_ref().reservation.as_ref().unwrap();
+        assert_eq!(rsvp.status, ReservationStatus::Cancelled as i32);
+        assert_eq!(rsvp.cancel_reason.as_deref(), Some("reason"));
     }
 
-    #[sqlx::test(migrations = "../migrations")]
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
     async fn test_get(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
-            reservation: Some(abi::Reservation::new_pendding(
-                "user".to_string(),
-                "room".to_string(),
-                "2021-01-01T00:00:00Z".parse().unwrap(),
-                "2021-01-02T00:00:00Z".parse().unwrap(),
-                "new note",
-            )),
-        };
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "new note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
         let request = GetRequest { id: 1 };
@@ -253,19 +1482,69 @@ mod test {
         );
     }
 
-    #[sqlx::test(migrations = "../migrations")]
+    #[tokio::test]
+    async fn test_in_memory() {
+        let service = RsvpService::new(reservation::InMemoryReservationManager::new());
+        let request = CreateResourceRequest {
+            resource: Some(abi::Resource::new("room", "room")),
+        };
+        service
+            .create_resource(Request::new(request))
+            .await
+            .unwrap();
+
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        let response = service.reserve(Request::new(request.clone())).await;
+        let rsvp = response.unwrap().into_inner().reservation.unwrap();
+        assert_eq!(rsvp.id, 1);
+
+        let err = service.reserve(Request::new(request)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+        let conflicts = abi::error::conflict::ReservationConflict::from_status(&err);
+        assert_eq!(conflicts[0].existing[0].id, rsvp.id);
+        assert_eq!(conflicts[0].existing[0].start, rsvp.start);
+
+        let response = service
+            .confirm(Request::new(ConfirmRequest {
+                id: 1,
+                expected_version: None,
+                idempotency_key: None,
+            }))
+            .await
+            .unwrap();
+        let rsvp = response.into_inner().reservation.unwrap();
+        assert_eq!(rsvp.status, ReservationStatus::Confirmed as i32);
+
+        let query = abi::ReservationQueryBuilder::default()
+            .end(abi::utils::datetime_to_timestamp(Utc::now()))
+            .build()
+            .unwrap();
+        let request = QueryRequest { query: Some(query) };
+        let mut response = service.query(Request::new(request)).await.unwrap();
+        assert_eq!(response.get_mut().next().await.unwrap().unwrap(), rsvp);
+        assert!(response.get_mut().next().await.is_none());
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
     async fn test_query(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
-            reservation: Some(abi::Reservation::new_pendding(
-                "user".to_string(),
-                "room".to_string(),
-                "2021-01-01T00:00:00Z".parse().unwrap(),
-                "2021-01-02T00:00:00Z".parse().unwrap(),
-                "note",
-            )),
-        };
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
 
@@ -280,19 +1559,20 @@ mod test {
         assert!(response.get_mut().next().await.is_none());
     }
 
-    #[sqlx::test(migrations = "../migrations")]
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
     async fn test_filter(pool: sqlx::PgPool) {
         let manager = ReservationManager::new(pool);
         let service = RsvpService::new(manager);
-        let request = ReserveRequest {
-            reservation: Some(abi::Reservation::new_pendding(
-                "user".to_string(),
-                "room".to_string(),
-                "2021-01-01T00:00:00Z".parse().unwrap(),
-                "2021-01-02T00:00:00Z".parse().unwrap(),
-                "note",
-            )),
-        };
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
         let response = service.reserve(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.as_ref().unwrap().id, 1);
         let request = FilterRequest {
@@ -303,4 +1583,209 @@ mod test {
         let response = service.filter(Request::new(request)).await.unwrap();
         assert_eq!(response.get_ref().reservation.len(), 1);
     }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_find_availability(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        service.reserve(Request::new(request)).await.unwrap();
+
+        let request = AvailabilityRequest {
+            resource_id: "room".to_string(),
+            start: Some(abi::utils::datetime_to_timestamp(
+                "2021-01-01T00:00:00Z".parse().unwrap(),
+            )),
+            end: Some(abi::utils::datetime_to_timestamp(
+                "2021-01-03T00:00:00Z".parse().unwrap(),
+            )),
+            duration: Some(prost_types::Duration {
+                seconds: 3600,
+                nanos: 0,
+            }),
+        };
+        let response = service
+            .find_availability(Request::new(request.clone()))
+            .await
+            .unwrap();
+        let slots = &response.get_ref().slots;
+        assert_eq!(slots.len(), 1);
+        assert_eq!(
+            slots[0].start,
+            Some(abi::utils::datetime_to_timestamp(
+                "2021-01-02T00:00:00Z".parse().unwrap()
+            ))
+        );
+
+        // a start out of range or no duration are rejected
+        let invalid = [
+            AvailabilityRequest {
+                start: Some(prost_types::Timestamp {
+                    seconds: -1,
+                    nanos: -1,
+                }),
+                ..request.clone()
+            },
+            AvailabilityRequest {
+                duration: None,
+                ..request
+            },
+        ];
+        for request in invalid {
+            let status = service
+                .find_availability(Request::new(request))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_resources(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+
+        let request = CreateResourceRequest {
+            resource: Some(abi::Resource::new("lab", "lab")),
+        };
+        service
+            .create_resource(Request::new(request))
+            .await
+            .unwrap();
+
+        let request = UpdateResourceRequest {
+            resource: Some(abi::Resource {
+                disabled: true,
+                ..abi::Resource::new("lab", "lab 1")
+            }),
+        };
+        service
+            .update_resource(Request::new(request))
+            .await
+            .unwrap();
+
+        let request = GetResourceRequest {
+            id: "lab".to_string(),
+        };
+        let response = service.get_resource(Request::new(request)).await.unwrap();
+        let resource = response.get_ref().resource.as_ref().unwrap();
+        assert_eq!(resource.name, "lab 1");
+        assert!(resource.disabled);
+
+        let response = service
+            .list_resources(Request::new(ListResourcesRequest {}))
+            .await
+            .unwrap();
+        assert!(response.get_ref().resources.iter().any(|r| r.id == "lab"));
+
+        let request = DeleteResourceRequest {
+            id: "lab".to_string(),
+        };
+        service
+            .delete_resource(Request::new(request))
+            .await
+            .unwrap();
+
+        let request = GetResourceRequest {
+            id: "lab".to_string(),
+        };
+        let status = service
+            .get_resource(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_get_quota_usage(pool: sqlx::PgPool) {
+        let quota = abi::config::QuotaConfig {
+            default: abi::config::Quota {
+                max_weekly_hours: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let manager = ReservationManager::new(pool).with_quota(quota);
+        let service = RsvpService::new(manager);
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user",
+            "room",
+            "2021-01-04T08:00:00Z".parse().unwrap(),
+            "2021-01-04T12:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        service.reserve(Request::new(request)).await.unwrap();
+
+        let request = QuotaUsageRequest {
+            user_id: "user".to_string(),
+            week: Some(datetime_to_timestamp(
+                "2021-01-06T00:00:00Z".parse().unwrap(),
+            )),
+        };
+        let response = service
+            .get_quota_usage(Request::new(request))
+            .await
+            .unwrap();
+        let usages = &response.get_ref().usages;
+        assert_eq!(usages.len(), 1);
+        assert_eq!(usages[0].weekly.as_ref().unwrap().seconds, 4 * 3600);
+        assert_eq!(
+            usages[0].remaining_weekly.as_ref().unwrap().seconds,
+            6 * 3600
+        );
+
+        let request = QuotaUsageRequest {
+            user_id: "user".to_string(),
+            week: Some(prost_types::Timestamp {
+                seconds: i64::MAX,
+                nanos: 0,
+            }),
+        };
+        let status = service
+            .get_quota_usage(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../fixtures", scripts("resources"))
+    )]
+    async fn test_listen(pool: sqlx::PgPool) {
+        let manager = ReservationManager::new(pool);
+        let service = RsvpService::new(manager);
+        let mut response = service
+            .listen(Request::new(ListenRequest {}))
+            .await
+            .unwrap();
+
+        let request = reserve_request(abi::Reservation::new_pendding(
+            "user".to_string(),
+            "room".to_string(),
+            "2021-01-01T00:00:00Z".parse().unwrap(),
+            "2021-01-02T00:00:00Z".parse().unwrap(),
+            "note",
+        ));
+        service.reserve(Request::new(request)).await.unwrap();
+
+        let change = response.get_mut().next().await.unwrap().unwrap();
+        assert_eq!(change.op, ReservationUpdateType::Create as i32);
+        assert_eq!(change.reservation.unwrap().id, 1);
+    }
 }
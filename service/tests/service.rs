@@ -59,6 +59,11 @@ fn load_service_config_from_env_file() -> Config {
             host: HOST.to_string(),
             port,
         },
+        hold: Default::default(),
+        policy: Default::default(),
+        quota: Default::default(),
+        idempotency: Default::default(),
+        auth: Default::default(),
     }
 }
 
@@ -124,6 +129,12 @@ async fn test(
         tonic::transport::Channel,
     >,
 ) {
+    // Register the resource
+    let request = tonic::Request::new(abi::CreateResourceRequest {
+        resource: Some(abi::Resource::new("resource", "resource")),
+    });
+    client.create_resource(request).await.unwrap();
+
     // Reserve
     let rsvp = abi::Reservation::new_pendding(
         "user",
@@ -134,6 +145,9 @@ async fn test(
     );
     let request = tonic::Request::new(abi::ReserveRequest {
         reservation: Some(rsvp),
+        hold_ttl: None,
+        waitlist: false,
+        idempotency_key: None,
     });
     let response1 = client.reserve(request).await.unwrap();
     let request = tonic::Request::new(abi::GetRequest {
@@ -150,6 +164,8 @@ async fn test(
     // Confirm reservation
     let request = tonic::Request::new(abi::ConfirmRequest {
         id: response1.get_ref().reservation.as_ref().unwrap().id,
+        expected_version: None,
+        idempotency_key: None,
     });
     let response3 = client.confirm(request).await.unwrap();
     assert_eq!(
@@ -161,6 +177,9 @@ async fn test(
     for _ in 0..100 {
         let request = tonic::Request::new(abi::ReserveRequest {
             reservation: Some(generation_reservation()),
+            hold_ttl: None,
+            waitlist: false,
+            idempotency_key: None,
         });
 
         let _ = client.reserve(request).await.unwrap();